@@ -0,0 +1,195 @@
+//! Replays a tracked wallet's recorded transactions through the decision engine with candidate
+//! settings, filling copied trades like paper trading does, to see how the settings would have
+//! done.
+
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::engine::{self, CopyDecision, EngineContext};
+use crate::error::AppError;
+use crate::models::{BacktestReport, CopyTradeSettings, Transaction};
+use crate::paper::{self, PaperSettings};
+use crate::parser::{self, ParsedTrade, WSOL_MINT};
+
+/// Backtests `settings` against `transactions`, recorded `getTransaction` results of the tracked
+/// wallet `wallet_address`. Copied trades are filled `settings.max_slippage` percent worse than
+/// the wallet's price, the worst fill the executor would accept.
+pub fn run(
+    settings: &CopyTradeSettings,
+    wallet_address: &str,
+    transactions: &[Value],
+    paper: &PaperSettings,
+) -> Result<BacktestReport, AppError> {
+    let mut trades = Vec::new();
+    for tx in transactions {
+        if let Some(trade) = parser::parse_transaction(tx, wallet_address)? {
+            trades.push(trade);
+        }
+    }
+    trades.sort_by_key(|trade| (trade.slot, trade.timestamp));
+
+    let paper = PaperSettings { slippage_pct: settings.max_slippage, ..paper.clone() };
+    Ok(replay(settings, &trades, &paper))
+}
+
+fn replay(settings: &CopyTradeSettings, trades: &[ParsedTrade], paper: &PaperSettings) -> BacktestReport {
+    let mut fills: Vec<Transaction> = Vec::new();
+    let mut skipped: BTreeMap<String, usize> = BTreeMap::new();
+    let mut leader_holdings: HashMap<String, f64> = HashMap::new();
+    // Open positions are valued at the last price the wallet traded the token at.
+    let mut last_prices: HashMap<String, f64> = HashMap::new();
+    let mut peak_equity = paper.starting_balance_sol;
+    let mut max_drawdown_sol: f64 = 0.0;
+
+    for trade in trades {
+        let tracked_holding = leader_holdings.get(&trade.token_address).copied();
+        if trade.token_address != WSOL_MINT {
+            leader_holdings.insert(trade.token_address.clone(), engine::leader_holding_after(tracked_holding, trade));
+        }
+        if let Some(price) = trade.price_sol {
+            last_prices.insert(trade.token_address.clone(), price);
+        }
+
+        // Only one wallet is replayed, so its positions are all the exposure there is.
+        let mut all_positions = engine::open_positions(&fills);
+        let token_exposure_sol = engine::token_exposure_sol(&all_positions, &trade.token_address);
+        let positions = all_positions
+            .remove(&Some(settings.tracked_wallet_id))
+            .unwrap_or_default();
+        let decision = engine::decide(settings, trade, &EngineContext {
+            positions: &positions,
+            sol_balance: paper::balance(paper, &fills),
+            leader_holding: tracked_holding,
+            holds_token_account: positions.iter().any(|position| position.token_address == trade.token_address),
+            // Fills are charged `paper.fee_sol` instead.
+            priority_fee_sol: 0.0,
+            halted: false,
+            deployable_sol: None,
+            token_exposure_sol,
+            history: &fills,
+            // Trades are paced by when the wallet made them.
+            now: trade.timestamp.unwrap_or_else(Utc::now),
+            // Tokens cannot be assessed as they were at the time of the trade.
+            token_risk: None,
+        });
+
+        if let CopyDecision::Skip { reason } = &decision {
+            *skipped.entry(reason.code().to_string()).or_default() += 1;
+        } else if let Some(fill) = paper::simulate_fill(&decision, trade, paper, "backtest", settings.tracked_wallet_id) {
+            fills.push(fill);
+        }
+
+        let equity = paper::balance(paper, &fills) + market_value(&fills, &last_prices);
+        peak_equity = peak_equity.max(equity);
+        max_drawdown_sol = max_drawdown_sol.max(peak_equity - equity);
+    }
+
+    let tokens = engine::pnl(&fills);
+    let realized_pnl_sol = tokens.iter().map(|token| token.realized_pnl_sol).sum::<f64>();
+    let unrealized_pnl_sol = market_value(&fills, &last_prices)
+        - tokens.iter().map(|token| token.open_cost_sol).sum::<f64>();
+
+    BacktestReport {
+        trades_replayed: trades.len(),
+        buys: tokens.iter().map(|token| token.buys).sum(),
+        sells: tokens.iter().map(|token| token.sells).sum(),
+        skipped,
+        realized_pnl_sol,
+        unrealized_pnl_sol,
+        pnl_sol: realized_pnl_sol + unrealized_pnl_sol,
+        max_drawdown_sol,
+        final_balance_sol: paper::balance(paper, &fills),
+        tokens,
+    }
+}
+
+/// SOL value of the open positions at the last known prices.
+fn market_value(fills: &[Transaction], last_prices: &HashMap<String, f64>) -> f64 {
+    engine::open_positions(fills)
+        .into_values()
+        .flatten()
+        .map(|position| position.amount * last_prices.get(&position.token_address).copied().unwrap_or_default())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::TradeKind;
+    use crate::testing;
+
+    const TOKEN: &str = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R";
+
+    fn settings() -> CopyTradeSettings {
+        CopyTradeSettings {
+            trade_amount_sol: 1.0,
+            max_slippage: 0.0,
+            max_open_positions: 1,
+            min_sol_balance: 0.0,
+            ..testing::settings()
+        }
+    }
+
+    fn paper() -> PaperSettings {
+        PaperSettings { slippage_pct: 0.0, fee_sol: 0.0, starting_balance_sol: 10.0 }
+    }
+
+    fn trade(kind: TradeKind, token_address: &str, price_sol: f64, slot: u64) -> ParsedTrade {
+        ParsedTrade {
+            signature: format!("sig{}", slot),
+            slot,
+            ..testing::trade(kind, token_address, 100.0, 100.0 * price_sol)
+        }
+    }
+
+    #[test]
+    fn reports_pnl_drawdown_and_skips() {
+        let trades = [
+            trade(TradeKind::Buy, TOKEN, 0.01, 1),
+            // A second token would be over `max_open_positions`.
+            trade(TradeKind::Buy, "other", 0.01, 2),
+            // The wallet buys more at half the price: our 100 tokens are worth 0.5 SOL less.
+            trade(TradeKind::Buy, TOKEN, 0.005, 3),
+            trade(TradeKind::Sell, TOKEN, 0.02, 4),
+        ];
+        let report = replay(&settings(), &trades, &paper());
+
+        assert_eq!((report.buys, report.sells), (1, 1));
+        assert_eq!(report.skipped["max_open_positions"], 1);
+        assert_eq!(report.skipped["already_holding"], 1);
+        assert!((report.realized_pnl_sol - 1.0).abs() < 1e-9);
+        assert!(report.unrealized_pnl_sol.abs() < 1e-9);
+        assert!((report.max_drawdown_sol - 0.5).abs() < 1e-9);
+        assert!((report.final_balance_sol - 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fills_at_max_slippage() {
+        let path = format!("{}/tests/fixtures/transactions/pump_fun_buy.json", env!("CARGO_MANIFEST_DIR"));
+        let tx: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let settings = CopyTradeSettings { max_slippage: 5.0, ..settings() };
+        let report = run(&settings, "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU", &[tx], &paper()).unwrap();
+
+        assert_eq!((report.trades_replayed, report.buys), (1, 1));
+        // Marked at the wallet's own price, the 5% worse fill is a 5% / 105% loss.
+        assert!((report.unrealized_pnl_sol + 1.0 * 0.05 / 1.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn replays_a_recorded_history() {
+        let path = format!("{}/tests/fixtures/backtest/pump_fun_round_trip.json", env!("CARGO_MANIFEST_DIR"));
+        let transactions: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let report = run(&settings(), "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU", &transactions, &paper()).unwrap();
+
+        // The failed swap is not decoded; the token transfer is decoded but not copied.
+        assert_eq!(report.trades_replayed, 3);
+        assert_eq!((report.buys, report.sells), (1, 1));
+        assert_eq!(report.skipped, BTreeMap::from([("not_a_trade".to_string(), 1)]));
+        // The wallet bought for 0.5 SOL and sold everything for 0.75: our 1 SOL came back as 1.5.
+        assert!((report.realized_pnl_sol - 0.5).abs() < 1e-9);
+        assert!(report.unrealized_pnl_sol.abs() < 1e-9);
+        assert!(report.max_drawdown_sol.abs() < 1e-9);
+        assert!((report.final_balance_sol - 10.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,120 @@
+//! The trading wallet's SOL and SPL token balances, fetched over RPC and cached briefly so every
+//! copied trade does not cost a round of requests.
+
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::models::{WalletBalances, WalletTokenBalance};
+use crate::parser::LAMPORTS_PER_SOL;
+use crate::rpc::SolanaRpc;
+
+/// How long fetched balances are used before they are fetched again.
+const MAX_AGE_SECS: i64 = 10;
+
+const TOKEN_PROGRAMS: [&str; 2] = [
+    "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+    "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+];
+
+#[derive(Clone)]
+pub struct BalanceService {
+    rpc: SolanaRpc,
+    wallet_address: String,
+    cached: Arc<Mutex<Option<WalletBalances>>>,
+}
+
+impl BalanceService {
+    pub fn new(rpc: SolanaRpc, wallet_address: String) -> Self {
+        Self { rpc, wallet_address, cached: Arc::new(Mutex::new(None)) }
+    }
+
+    pub fn wallet_address(&self) -> &str {
+        &self.wallet_address
+    }
+
+    /// The wallet's balances, fetched again once the cached ones are older than `MAX_AGE_SECS`.
+    pub async fn get(&self) -> Result<WalletBalances, AppError> {
+        // Holding the lock while fetching makes concurrent callers share one fetch.
+        let mut cached = self.cached.lock().await;
+        if let Some(balances) = cached.as_ref() {
+            if Utc::now() - balances.fetched_at < Duration::seconds(MAX_AGE_SECS) {
+                return Ok(balances.clone());
+            }
+        }
+
+        let balances = self.fetch().await?;
+        *cached = Some(balances.clone());
+        Ok(balances)
+    }
+
+    /// Drops the cached balances, e.g. after a trade changed them.
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    async fn fetch(&self) -> Result<WalletBalances, AppError> {
+        let lamports = self.rpc.get_balance(&self.wallet_address).await?;
+        let mut tokens = Vec::new();
+        for program in TOKEN_PROGRAMS {
+            for account in self.rpc.get_token_accounts(&self.wallet_address, program).await? {
+                tokens.push(token_balance(&account)?);
+            }
+        }
+        tokens.sort_by(|a, b| a.mint.cmp(&b.mint));
+
+        Ok(WalletBalances {
+            wallet_address: self.wallet_address.clone(),
+            sol: lamports as f64 / LAMPORTS_PER_SOL,
+            tokens,
+            fetched_at: Utc::now(),
+        })
+    }
+}
+
+impl WalletBalances {
+    /// Whether the wallet has a token account for `mint`, so receiving it costs no rent.
+    pub fn has_token_account(&self, mint: &str) -> bool {
+        self.tokens.iter().any(|token| token.mint == mint)
+    }
+}
+
+/// Reads one `jsonParsed` entry of `getTokenAccountsByOwner`.
+fn token_balance(account: &Value) -> Result<WalletTokenBalance, AppError> {
+    let info = &account["account"]["data"]["parsed"]["info"];
+    let amount = &info["tokenAmount"];
+    let parsed = (|| Some(WalletTokenBalance {
+        mint: info["mint"].as_str()?.to_string(),
+        token_account: account["pubkey"].as_str()?.to_string(),
+        amount: amount["uiAmountString"].as_str()?.parse().ok()?,
+        decimals: u8::try_from(amount["decimals"].as_u64()?).ok()?,
+    }))();
+    parsed.ok_or_else(|| AppError::RpcError(format!("Unexpected token account: {}", account)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_parsed_token_account() {
+        let account = json!({
+            "pubkey": "3emsAVdmGKERbHjmGfQ6oZ1e35dkf5iYcS6U4CPKFVaa",
+            "account": { "data": { "program": "spl-token", "parsed": { "type": "account", "info": {
+                "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                "owner": "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU",
+                "tokenAmount": { "amount": "1500000", "decimals": 6, "uiAmount": 1.5, "uiAmountString": "1.5" },
+            } } } },
+        });
+        let balance = token_balance(&account).unwrap();
+        assert_eq!(balance.mint, "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_eq!(balance.token_account, "3emsAVdmGKERbHjmGfQ6oZ1e35dkf5iYcS6U4CPKFVaa");
+        assert_eq!(balance.amount, 1.5);
+        assert_eq!(balance.decimals, 6);
+
+        assert!(token_balance(&json!({ "pubkey": "x", "account": {} })).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Sends swaps as bundles to a block engine (Jito's `sendBundle` JSON-RPC, or anything speaking
+//! it), each followed by a transaction tipping one of the engine's tip accounts. A bundle lands
+//! all of its transactions in order or none of them, and never shows in the public mempool.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::error::AppError;
+use crate::policy::{self, SYSTEM_PROGRAM};
+use crate::signer::{self, Signer, SIGNATURE_LEN};
+
+#[derive(Clone)]
+pub struct BundleSender {
+    http: reqwest::Client,
+    url: String,
+    tip_accounts: Vec<String>,
+}
+
+impl BundleSender {
+    pub fn new(url: &str, tip_accounts: Vec<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.to_string(),
+            tip_accounts,
+        }
+    }
+
+    /// Sends the signed `swap` with a tip of `tip_lamports` from `signer`, and returns the swap's
+    /// signature. The tip goes last, so it is only paid if the swap lands.
+    pub async fn send(&self, swap: &[u8], signer: &Signer, tip_lamports: u64) -> Result<String, AppError> {
+        let wallet = signer.pubkey();
+        let (slot, _) = signer::signature_slot(swap, &wallet)?;
+        let signature = &swap[slot..slot + SIGNATURE_LEN];
+        // Any tip account will do; spreading tips over them avoids contending for one.
+        let tip_account = self.tip_accounts.get(signature[0] as usize % self.tip_accounts.len().max(1))
+            .ok_or_else(|| AppError::ExecutionError("No bundle tip accounts configured".to_string()))?;
+        // Built on the swap's blockhash, the tip expires with it.
+        let blockhash = policy::recent_blockhash(swap)
+            .map_err(|e| AppError::ExecutionError(format!("Invalid swap transaction: {}", e)))?;
+        let tip = signer.sign_transaction(&transfer_transaction(&wallet, tip_account, tip_lamports, &blockhash)?).await?;
+
+        let bundle_id = self.send_bundle(&[swap, &tip]).await?;
+        let signature = bs58::encode(signature).into_string();
+        info!(%bundle_id, %signature, tip_lamports, %tip_account, "Bundle sent");
+        Ok(signature)
+    }
+
+    /// Submits signed transactions as one bundle and returns its id.
+    async fn send_bundle(&self, txs: &[&[u8]]) -> Result<String, AppError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendBundle",
+            "params": [
+                txs.iter().map(|tx| BASE64.encode(tx)).collect::<Vec<_>>(),
+                { "encoding": "base64" },
+            ],
+        });
+        let resp = self.http
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.to_string())
+            .send()
+            .await
+            .map_err(|e| AppError::ExecutionError(format!("sendBundle failed: {}", e)))?;
+
+        let status = resp.status();
+        let body = resp.text().await
+            .map_err(|e| AppError::ExecutionError(format!("sendBundle failed: {}", e)))?;
+        if !status.is_success() {
+            return Err(AppError::ExecutionError(format!("sendBundle failed with status {}: {}", status, body)));
+        }
+        let response: Value = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Invalid sendBundle response: {}", e)))?;
+        if let Some(error) = response.get("error") {
+            return Err(AppError::ExecutionError(format!("sendBundle failed: {}", error)));
+        }
+        response["result"].as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AppError::ExecutionError(format!("Unexpected sendBundle result: {}", response["result"])))
+    }
+}
+
+/// An unsigned legacy transaction transferring `lamports` from `from`, its only signer, to `to`.
+pub fn transfer_transaction(from: &str, to: &str, lamports: u64, blockhash: &[u8; 32]) -> Result<Vec<u8>, AppError> {
+    let key = |address: &str| {
+        bs58::decode(address).into_vec().ok()
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| AppError::ExecutionError(format!("{} is not an address", address)))
+    };
+
+    let mut tx = vec![1];
+    tx.extend([0; SIGNATURE_LEN]);
+    // One signer, and one read-only account besides it: the System program.
+    tx.extend([1, 0, 1, 3]);
+    tx.extend(key(from)?);
+    tx.extend(key(to)?);
+    tx.extend(key(SYSTEM_PROGRAM)?);
+    tx.extend(blockhash);
+    // A single System Transfer from the first account to the second.
+    tx.extend([1, 2, 2, 0, 1, 12]);
+    tx.extend(2u32.to_le_bytes());
+    tx.extend(lamports.to_le_bytes());
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::SignerPolicy;
+    use crate::testing;
+    use axum::{routing::post, Json, Router};
+    use ed25519_dalek::SigningKey;
+    use std::sync::{Arc, Mutex};
+
+    const TIP_ACCOUNT: &str = "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5";
+
+    /// A block engine answering every `sendBundle` with `response`, and the bundles it got.
+    async fn block_engine(response: Value) -> (String, Arc<Mutex<Vec<Vec<Vec<u8>>>>>) {
+        let bundles = Arc::new(Mutex::new(Vec::new()));
+        let received = bundles.clone();
+        let url = testing::serve(Router::new().route("/", post(move |request: String| {
+            let request: Value = serde_json::from_str(&request).unwrap();
+            assert_eq!(request["method"], "sendBundle");
+            let txs = request["params"][0].as_array().unwrap().iter()
+                .map(|tx| BASE64.decode(tx.as_str().unwrap()).unwrap())
+                .collect();
+            received.lock().unwrap().push(txs);
+            let response = response.clone();
+            async move { Json(response) }
+        }))).await;
+        (url, bundles)
+    }
+
+    #[tokio::test]
+    async fn builds_signed_tip() {
+        let signer = Signer::Local(Arc::new(SigningKey::from_bytes(&[9; 32])));
+        let wallet = signer.pubkey();
+
+        let tip = transfer_transaction(&wallet, TIP_ACCOUNT, 10_000, &[7; 32]).unwrap();
+        let tip = signer.sign_transaction(&tip).await.unwrap();
+        assert_eq!(policy::recent_blockhash(&tip), Ok([7; 32]));
+
+        let policy = SignerPolicy { max_sol_out: 0.001, allowed_programs: vec![SYSTEM_PROGRAM.to_string()] };
+        // The tip and the signature fee.
+        assert!((policy.check(&tip, &wallet).unwrap() - 0.000_015).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn sends_swap_then_tip() {
+        let signer = Signer::Local(Arc::new(SigningKey::from_bytes(&[9; 32])));
+        let wallet = signer.pubkey();
+        let (url, bundles) = block_engine(json!({ "jsonrpc": "2.0", "id": 1, "result": "bundle-1" })).await;
+        let sender = BundleSender::new(&url, vec![TIP_ACCOUNT.to_string()]);
+
+        // Any signed transaction stands in for the swap.
+        let swap = transfer_transaction(&wallet, TIP_ACCOUNT, 1, &[7; 32]).unwrap();
+        let swap = signer.sign_transaction(&swap).await.unwrap();
+        let signature = sender.send(&swap, &signer, 25_000).await.unwrap();
+        assert_eq!(signature, bs58::encode(&swap[1..1 + SIGNATURE_LEN]).into_string());
+
+        // The tip transfers 25_000 lamports to the tip account, on the swap's blockhash.
+        let tip = transfer_transaction(&wallet, TIP_ACCOUNT, 25_000, &[7; 32]).unwrap();
+        let tip = signer.sign_transaction(&tip).await.unwrap();
+        let bundles = bundles.lock().unwrap();
+        assert_eq!(*bundles, vec![vec![swap, tip]]);
+        let tip = &bundles[0][1];
+        let accounts = 1 + SIGNATURE_LEN + 4;
+        assert_eq!(tip[accounts + 32..accounts + 64], bs58::decode(TIP_ACCOUNT).into_vec().unwrap());
+        assert_eq!(tip[tip.len() - 8..], 25_000u64.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn rejected_bundle_is_an_error() {
+        let signer = Signer::Local(Arc::new(SigningKey::from_bytes(&[9; 32])));
+        let wallet = signer.pubkey();
+        let (url, _) = block_engine(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32602, "message": "bundle contains an already processed transaction" },
+        })).await;
+        let sender = BundleSender::new(&url, vec![TIP_ACCOUNT.to_string()]);
+
+        let swap = signer.sign_transaction(&transfer_transaction(&wallet, TIP_ACCOUNT, 1, &[7; 32]).unwrap()).await.unwrap();
+        let error = sender.send(&swap, &signer, 25_000).await.unwrap_err();
+        assert!(matches!(&error, AppError::ExecutionError(message) if message.contains("already processed")), "{}", error);
+    }
+}
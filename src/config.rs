@@ -0,0 +1,956 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::error::AppError;
+use crate::keystore::{Keystore, KeystoreSecret};
+use crate::policy::MAX_TRANSACTION_UNITS;
+use crate::signer::Signer;
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Copy trader API server")]
+pub struct Cli {
+    /// Path to a TOML config file. Defaults to `config.toml` when it exists.
+    #[arg(long, short, global = true, env = "COPY_TRADER_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub overrides: CliOverrides,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the API server (default).
+    Serve,
+    /// Inspect the effective configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Manage the encrypted keystore of the trading wallet.
+    Keystore {
+        #[command(subcommand)]
+        command: KeystoreCommand,
+    },
+    /// Run the signer process: hold the wallet keypair and sign the API's transactions, within
+    /// the `[signer]` policy, over `signer.socket`.
+    SignerServer,
+    /// Replay a wallet's recorded transactions with candidate copy trade settings and print
+    /// the simulated results.
+    Backtest(BacktestArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct BacktestArgs {
+    /// Address of the tracked wallet that made the transactions.
+    #[arg(long)]
+    pub wallet: String,
+    /// JSON file of recorded `getTransaction` results: one, or an array of them.
+    #[arg(long)]
+    pub transactions: PathBuf,
+    /// JSON file of the copy trade settings to try, as returned by `/v1/copy_trade_settings`.
+    #[arg(long)]
+    pub settings: PathBuf,
+    /// Defaults to `paper.starting_balance_sol`.
+    #[arg(long)]
+    pub starting_balance_sol: Option<f64>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Validate the configuration and report every problem found.
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeystoreCommand {
+    /// Encrypt a keypair into a new keystore file, with WALLET_KEYSTORE_PASSWORD or
+    /// WALLET_KEYSTORE_KEY.
+    Create {
+        /// Path of the keystore file to create.
+        #[arg(long, short)]
+        output: PathBuf,
+        /// `solana-keygen` keypair file to encrypt. Defaults to WALLET_PRIVATE_KEY.
+        #[arg(long)]
+        keypair: Option<PathBuf>,
+    },
+}
+
+/// Command-line flags; these take precedence over env vars and the config file.
+#[derive(Debug, Default, Args)]
+pub struct CliOverrides {
+    /// Address to bind the HTTP server to.
+    #[arg(long, global = true)]
+    pub bind: Option<String>,
+
+    /// Port to bind the HTTP server to.
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+
+    /// Storage backend.
+    #[arg(long, global = true)]
+    pub backend: Option<Backend>,
+
+    /// Per-request timeout in seconds.
+    #[arg(long, global = true)]
+    pub request_timeout_secs: Option<u64>,
+
+    /// Seconds to wait for in-flight requests and background work on shutdown.
+    #[arg(long, global = true)]
+    pub shutdown_timeout_secs: Option<u64>,
+
+    /// Allowed CORS origin, repeatable. Replaces the configured list.
+    #[arg(long = "cors-origin", global = true)]
+    pub cors_origins: Vec<String>,
+
+    /// Log filter, e.g. `copy_trader_api=debug`.
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+
+    /// Log output format.
+    #[arg(long, global = true)]
+    pub log_format: Option<LogFormat>,
+
+    /// Solana JSON-RPC HTTP endpoint.
+    #[arg(long, global = true)]
+    pub rpc_url: Option<String>,
+
+    /// Solana JSON-RPC websocket endpoint. Derived from the RPC URL when not set.
+    #[arg(long, global = true)]
+    pub ws_url: Option<String>,
+
+    /// Do not watch tracked wallets for activity.
+    #[arg(long, global = true)]
+    pub no_monitor: bool,
+
+    /// Execute copy trades instead of only logging the decisions.
+    #[arg(long, global = true)]
+    pub execute: bool,
+
+    /// Base URL of the Jupiter-compatible quote/swap API.
+    #[arg(long, global = true)]
+    pub swap_api_url: Option<String>,
+
+    /// Encrypted keystore of the trading wallet.
+    #[arg(long, global = true)]
+    pub keystore: Option<PathBuf>,
+
+    /// Unix socket of the signer process.
+    #[arg(long, global = true)]
+    pub signer_socket: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub wallet: WalletConfig,
+    pub cors: CorsConfig,
+    pub logging: LoggingConfig,
+    pub solana: SolanaConfig,
+    pub monitor: MonitorConfig,
+    pub execution: ExecutionConfig,
+    pub priority_fee: PriorityFeeConfig,
+    pub bundle: BundleConfig,
+    pub signer: SignerConfig,
+    pub paper: PaperConfig,
+    pub exits: ExitsConfig,
+    pub risk: RiskConfig,
+    pub safety: SafetyConfig,
+    pub tokens: TokensConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    pub request_timeout_secs: u64,
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 3001,
+            request_timeout_secs: 30,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Supabase,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub backend: Backend,
+    pub supabase_url: Option<String>,
+    pub supabase_api_key: Option<String>,
+    pub supabase_service_role_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    /// Address of the trading wallet. Derived from the keypair when one is given.
+    pub user_id: Option<String>,
+    /// Encrypted keystore holding the trading wallet's keypair.
+    pub keystore: Option<PathBuf>,
+    /// The trading wallet, unlocked from `keystore` or read from `WALLET_PRIVATE_KEY`.
+    #[serde(skip)]
+    pub signer: Option<Signer>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API from a browser. `*` allows any; empty disables CORS.
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "copy_trader_api=info,tower_http=info".to_string(),
+            format: LogFormat::Pretty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolanaConfig {
+    pub rpc_url: String,
+    /// Defaults to `rpc_url` with the scheme switched to `ws`/`wss`.
+    pub ws_url: Option<String>,
+    pub commitment: String,
+}
+
+impl Default for SolanaConfig {
+    fn default() -> Self {
+        Self {
+            rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
+            ws_url: None,
+            commitment: "confirmed".to_string(),
+        }
+    }
+}
+
+impl SolanaConfig {
+    pub fn ws_url(&self) -> String {
+        match &self.ws_url {
+            Some(ws_url) if !ws_url.is_empty() => ws_url.clone(),
+            _ => self.rpc_url
+                .replacen("https://", "wss://", 1)
+                .replacen("http://", "ws://", 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitorConfig {
+    pub enabled: bool,
+    pub reconnect_delay_secs: u64,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reconnect_delay_secs: 5,
+        }
+    }
+}
+
+impl MonitorConfig {
+    pub fn reconnect_delay(&self) -> Duration {
+        Duration::from_secs(self.reconnect_delay_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutionConfig {
+    pub enabled: bool,
+    /// Base URL of a Jupiter-compatible API; `/quote` and `/swap` are appended.
+    pub swap_api_url: String,
+    pub confirm_timeout_secs: u64,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            swap_api_url: "https://quote-api.jup.ag/v6".to_string(),
+            confirm_timeout_secs: 60,
+        }
+    }
+}
+
+impl ExecutionConfig {
+    pub fn confirm_timeout(&self) -> Duration {
+        Duration::from_secs(self.confirm_timeout_secs)
+    }
+}
+
+/// Compute budget of the swaps the executor builds, for wallets whose copy trade settings do not
+/// set their own.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriorityFeeConfig {
+    /// A fixed compute unit price, in micro-lamports.
+    pub micro_lamports: Option<u64>,
+    /// Instead of a fixed price, this percentile (0 to 100) of the prices recently paid to write
+    /// to the swap's pools.
+    pub percentile: Option<f64>,
+    /// Highest compute unit price, in micro-lamports.
+    pub max_micro_lamports: Option<u64>,
+    /// Compute units to request. Unset to request what the swap API simulates the swap to need.
+    pub compute_unit_limit: Option<u32>,
+}
+
+/// Where and how swaps of wallets whose copy trade settings submit them as bundles are sent.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BundleConfig {
+    /// JSON-RPC endpoint of a block engine taking `sendBundle`.
+    pub block_engine_url: String,
+    /// Tip paid with each bundle, unless the settings set their own.
+    pub tip_lamports: u64,
+    /// Accounts the block engine takes tips on; each bundle pays one of them.
+    pub tip_accounts: Vec<String>,
+}
+
+impl Default for BundleConfig {
+    fn default() -> Self {
+        Self {
+            block_engine_url: "https://mainnet.block-engine.jito.wtf/api/v1/bundles".to_string(),
+            tip_lamports: 10_000,
+            tip_accounts: [
+                "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+                "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+                "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+                "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+                "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+                "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+                "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+                "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        }
+    }
+}
+
+/// Signing in a separate process, so the API never holds the keypair.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignerConfig {
+    /// Unix socket of the signer process. When set the API signs through it, and
+    /// `signer-server` listens on it.
+    pub socket: Option<PathBuf>,
+    /// Most SOL the signer lets a transaction move out of the wallet, fees included.
+    pub max_sol_out: f64,
+    /// Programs the signer lets a transaction call directly. Empty allows any.
+    pub allowed_programs: Vec<String>,
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            max_sol_out: 1.0,
+            allowed_programs: [
+                "11111111111111111111111111111111",             // System
+                "ComputeBudget111111111111111111111111111111",  // Compute Budget
+                "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",  // Token
+                "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",  // Token-2022
+                "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", // Associated Token Account
+                "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4",  // Jupiter v6
+            ].into_iter().map(str::to_string).collect(),
+        }
+    }
+}
+
+/// Simulated fills of wallets whose copy trade settings have `paper_trading` on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaperConfig {
+    /// Percent by which each fill is worse than the tracked wallet's price.
+    pub slippage_pct: f64,
+    /// Fees charged per trade, in SOL.
+    pub fee_sol: f64,
+    /// Virtual SOL each paper-traded wallet starts with.
+    pub starting_balance_sol: f64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            slippage_pct: 1.0,
+            fee_sol: 0.0001,
+            starting_balance_sol: 10.0,
+        }
+    }
+}
+
+/// The position watcher, which closes positions on the take-profit, stop-loss and trailing-stop
+/// thresholds of their copy trade settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExitsConfig {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+    /// A Jupiter-compatible price API, queried with `?ids=<mints>&vsToken=<SOL mint>`.
+    pub price_api_url: String,
+}
+
+impl Default for ExitsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval_secs: 15,
+            price_api_url: "https://api.jup.ag/price/v2".to_string(),
+        }
+    }
+}
+
+impl ExitsConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs)
+    }
+}
+
+/// Account-wide limits, across every tracked wallet. Unset limits are not enforced.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    /// Realized loss per day (UTC), in SOL, that halts all copying.
+    pub max_daily_loss_sol: Option<f64>,
+    /// Most SOL in open positions at once; buys beyond it are skipped.
+    pub max_deployed_sol: Option<f64>,
+}
+
+/// Where token safety checks get pool data from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyConfig {
+    /// A DexScreener-compatible pool API, queried with `/<mint>`.
+    pub pool_api_url: String,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            pool_api_url: "https://api.dexscreener.com/latest/dex/tokens".to_string(),
+        }
+    }
+}
+
+/// Where token metadata for API responses comes from besides the chain.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokensConfig {
+    /// A token list file, in the Solana token list format, consulted before the chain.
+    pub list_file: Option<PathBuf>,
+}
+
+impl Config {
+    /// Builds the effective config from defaults, the config file, env vars and `cli`, in
+    /// increasing order of precedence. On failure returns every problem found, not just the first.
+    pub fn load(cli: &Cli) -> Result<Config, Vec<String>> {
+        let mut problems = Vec::new();
+        let config = Config::layered(cli, &mut problems);
+        config.validate(&mut problems);
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Like `load`, for the `signer-server` process.
+    pub fn load_for_signer(cli: &Cli) -> Result<Config, Vec<String>> {
+        let mut problems = Vec::new();
+        let config = Config::layered(cli, &mut problems);
+        config.validate_signer(&mut problems);
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(problems)
+        }
+    }
+
+    fn layered(cli: &Cli, problems: &mut Vec<String>) -> Config {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path).unwrap_or_else(|problem| {
+                problems.push(problem);
+                Config::default()
+            }),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE)).unwrap_or_else(|problem| {
+                    problems.push(problem);
+                    Config::default()
+                })
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env(problems);
+        config.apply_cli(&cli.overrides);
+        config.load_signer(problems);
+        config
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn apply_env(&mut self, problems: &mut Vec<String>) {
+        if let Ok(bind) = env::var("APP_BIND") {
+            self.server.bind = bind;
+        }
+        if let Ok(port) = env::var("APP_PORT") {
+            match port.parse() {
+                Ok(port) => self.server.port = port,
+                Err(e) => problems.push(format!("APP_PORT: {}", e)),
+            }
+        }
+        if let Ok(timeout) = env::var("APP_REQUEST_TIMEOUT_SECS") {
+            match timeout.parse() {
+                Ok(timeout) => self.server.request_timeout_secs = timeout,
+                Err(e) => problems.push(format!("APP_REQUEST_TIMEOUT_SECS: {}", e)),
+            }
+        }
+        if let Ok(timeout) = env::var("APP_SHUTDOWN_TIMEOUT_SECS") {
+            match timeout.parse() {
+                Ok(timeout) => self.server.shutdown_timeout_secs = timeout,
+                Err(e) => problems.push(format!("APP_SHUTDOWN_TIMEOUT_SECS: {}", e)),
+            }
+        }
+        if let Ok(origins) = env::var("APP_CORS_ORIGINS") {
+            self.cors.allowed_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        if let Ok(backend) = env::var("APP_DATABASE_BACKEND") {
+            match Backend::from_str(&backend, true) {
+                Ok(backend) => self.database.backend = backend,
+                Err(e) => problems.push(format!("APP_DATABASE_BACKEND: {}", e)),
+            }
+        }
+        if let Ok(url) = env::var("SUPABASE_URL") {
+            self.database.supabase_url = Some(url);
+        }
+        if let Ok(key) = env::var("SUPABASE_API_KEY") {
+            self.database.supabase_api_key = Some(key);
+        }
+        if let Ok(key) = env::var("SUPABASE_SERVICE_ROLE_KEY") {
+            self.database.supabase_service_role_key = Some(key);
+        }
+        if let Ok(user_id) = env::var("USER_ID") {
+            self.wallet.user_id = Some(user_id);
+        }
+        if let Ok(keystore) = env::var("WALLET_KEYSTORE") {
+            self.wallet.keystore = Some(PathBuf::from(keystore));
+        }
+        if let Ok(level) = env::var("RUST_LOG") {
+            self.logging.level = level;
+        }
+        if let Ok(rpc_url) = env::var("SOLANA_RPC_URL") {
+            self.solana.rpc_url = rpc_url;
+        }
+        if let Ok(ws_url) = env::var("SOLANA_WS_URL") {
+            self.solana.ws_url = Some(ws_url);
+        }
+        if let Ok(format) = env::var("APP_LOG_FORMAT") {
+            match LogFormat::from_str(&format, true) {
+                Ok(format) => self.logging.format = format,
+                Err(e) => problems.push(format!("APP_LOG_FORMAT: {}", e)),
+            }
+        }
+        if let Ok(enabled) = env::var("APP_EXECUTION_ENABLED") {
+            match enabled.parse() {
+                Ok(enabled) => self.execution.enabled = enabled,
+                Err(e) => problems.push(format!("APP_EXECUTION_ENABLED: {}", e)),
+            }
+        }
+        if let Ok(url) = env::var("SWAP_API_URL") {
+            self.execution.swap_api_url = url;
+        }
+        if let Ok(url) = env::var("BLOCK_ENGINE_URL") {
+            self.bundle.block_engine_url = url;
+        }
+        if let Ok(socket) = env::var("WALLET_SIGNER_SOCKET") {
+            self.signer.socket = Some(PathBuf::from(socket));
+        }
+    }
+
+    fn apply_cli(&mut self, overrides: &CliOverrides) {
+        if let Some(bind) = &overrides.bind {
+            self.server.bind = bind.clone();
+        }
+        if let Some(port) = overrides.port {
+            self.server.port = port;
+        }
+        if let Some(backend) = overrides.backend {
+            self.database.backend = backend;
+        }
+        if let Some(timeout) = overrides.request_timeout_secs {
+            self.server.request_timeout_secs = timeout;
+        }
+        if let Some(timeout) = overrides.shutdown_timeout_secs {
+            self.server.shutdown_timeout_secs = timeout;
+        }
+        if !overrides.cors_origins.is_empty() {
+            self.cors.allowed_origins = overrides.cors_origins.clone();
+        }
+        if let Some(level) = &overrides.log_level {
+            self.logging.level = level.clone();
+        }
+        if let Some(format) = overrides.log_format {
+            self.logging.format = format;
+        }
+        if let Some(rpc_url) = &overrides.rpc_url {
+            self.solana.rpc_url = rpc_url.clone();
+        }
+        if let Some(ws_url) = &overrides.ws_url {
+            self.solana.ws_url = Some(ws_url.clone());
+        }
+        if overrides.no_monitor {
+            self.monitor.enabled = false;
+        }
+        if overrides.execute {
+            self.execution.enabled = true;
+        }
+        if let Some(url) = &overrides.swap_api_url {
+            self.execution.swap_api_url = url.clone();
+        }
+        if let Some(keystore) = &overrides.keystore {
+            self.wallet.keystore = Some(keystore.clone());
+        }
+        if let Some(socket) = &overrides.signer_socket {
+            self.signer.socket = Some(socket.clone());
+        }
+    }
+
+    /// Loads the trading wallet's keypair, if one is given, and derives `user_id` from it.
+    fn load_signer(&mut self, problems: &mut Vec<String>) {
+        let private_key = env::var("WALLET_PRIVATE_KEY").ok().map(Zeroizing::new);
+        let message = |e: AppError| match e {
+            AppError::ConfigError(message) => message,
+            e => e.to_string(),
+        };
+        let signer = match (&self.wallet.keystore, private_key) {
+            (Some(_), Some(_)) => {
+                problems.push("Set either wallet.keystore (WALLET_KEYSTORE) or WALLET_PRIVATE_KEY, not both".to_string());
+                return;
+            }
+            (Some(path), None) => Keystore::read(path)
+                .and_then(|keystore| keystore.unlock(&KeystoreSecret::from_env()?))
+                .map_err(|e| format!("wallet.keystore: {}", message(e))),
+            (None, Some(private_key)) => Signer::from_keypair(&private_key)
+                .map_err(|e| format!("WALLET_PRIVATE_KEY: {}", message(e))),
+            (None, None) => return,
+        };
+
+        match signer {
+            Ok(signer) => {
+                match self.wallet.user_id.as_deref() {
+                    None | Some("") => self.wallet.user_id = Some(signer.pubkey()),
+                    Some(user_id) if user_id != signer.pubkey() => problems.push(format!(
+                        "The wallet keypair belongs to {}, not to wallet.user_id (USER_ID)",
+                        signer.pubkey()
+                    )),
+                    Some(_) => {}
+                }
+                self.wallet.signer = Some(signer);
+            }
+            Err(problem) => problems.push(problem),
+        }
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.server.bind.parse::<IpAddr>().is_err() {
+            problems.push(format!("server.bind: '{}' is not an IP address", self.server.bind));
+        }
+        if self.server.request_timeout_secs == 0 {
+            problems.push("server.request_timeout_secs must be greater than 0".to_string());
+        }
+
+        match self.database.backend {
+            Backend::Supabase => {
+                let required = [
+                    ("database.supabase_url (SUPABASE_URL)", &self.database.supabase_url),
+                    ("database.supabase_api_key (SUPABASE_API_KEY)", &self.database.supabase_api_key),
+                    ("database.supabase_service_role_key (SUPABASE_SERVICE_ROLE_KEY)", &self.database.supabase_service_role_key),
+                ];
+                for (name, value) in required {
+                    if value.as_deref().is_none_or(str::is_empty) {
+                        problems.push(format!("{} must be set", name));
+                    }
+                }
+                if let Some(url) = &self.database.supabase_url {
+                    if !url.is_empty() && reqwest::Url::parse(url).is_err() {
+                        problems.push(format!("database.supabase_url: '{}' is not a valid URL", url));
+                    }
+                }
+            }
+        }
+
+        if self.wallet.user_id.as_deref().is_none_or(str::is_empty) {
+            problems.push("wallet.user_id (USER_ID) must be set, or derived from the wallet keypair".to_string());
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                problems.push(format!("cors.allowed_origins: '{}' must be '*' or an http(s) origin", origin));
+            }
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            problems.push(format!("logging.level: {}", e));
+        }
+
+        match reqwest::Url::parse(&self.solana.rpc_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => problems.push(format!("solana.rpc_url: '{}' is not an http(s) URL", self.solana.rpc_url)),
+        }
+        let ws_url = self.solana.ws_url();
+        match reqwest::Url::parse(&ws_url) {
+            Ok(url) if matches!(url.scheme(), "ws" | "wss") => {}
+            _ => problems.push(format!("solana.ws_url: '{}' is not a ws(s) URL", ws_url)),
+        }
+        if !matches!(self.solana.commitment.as_str(), "processed" | "confirmed" | "finalized") {
+            problems.push(format!(
+                "solana.commitment: '{}' must be processed, confirmed or finalized",
+                self.solana.commitment
+            ));
+        }
+        if self.monitor.reconnect_delay_secs == 0 {
+            problems.push("monitor.reconnect_delay_secs must be greater than 0".to_string());
+        }
+
+        if self.execution.enabled {
+            if !self.monitor.enabled {
+                problems.push("execution.enabled requires the wallet monitor".to_string());
+            }
+            if self.wallet.signer.is_none() && self.signer.socket.is_none() {
+                problems.push(
+                    "execution.enabled requires the wallet keypair (WALLET_KEYSTORE or WALLET_PRIVATE_KEY) or signer.socket".to_string(),
+                );
+            }
+            match reqwest::Url::parse(&self.execution.swap_api_url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => problems.push(format!(
+                    "execution.swap_api_url: '{}' is not an http(s) URL",
+                    self.execution.swap_api_url
+                )),
+            }
+            if self.execution.confirm_timeout_secs == 0 {
+                problems.push("execution.confirm_timeout_secs must be greater than 0".to_string());
+            }
+            match reqwest::Url::parse(&self.bundle.block_engine_url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => problems.push(format!("bundle.block_engine_url: '{}' is not an http(s) URL", self.bundle.block_engine_url)),
+            }
+            if self.bundle.tip_accounts.is_empty() {
+                problems.push("bundle.tip_accounts must not be empty".to_string());
+            }
+            for account in &self.bundle.tip_accounts {
+                if bs58::decode(account).into_vec().map_or(true, |bytes| bytes.len() != 32) {
+                    problems.push(format!("bundle.tip_accounts: '{}' is not an address", account));
+                }
+            }
+        }
+
+        if self.signer.socket.is_some() && self.wallet.signer.is_some() {
+            problems.push("The API must not be given the wallet keypair when signer.socket is set".to_string());
+        }
+        #[cfg(not(unix))]
+        if self.signer.socket.is_some() {
+            problems.push("signer.socket is only supported on Unix".to_string());
+        }
+
+        if self.exits.enabled {
+            if self.exits.poll_interval_secs == 0 {
+                problems.push("exits.poll_interval_secs must be greater than 0".to_string());
+            }
+            match reqwest::Url::parse(&self.exits.price_api_url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => problems.push(format!("exits.price_api_url: '{}' is not an http(s) URL", self.exits.price_api_url)),
+            }
+        }
+
+        if self.priority_fee.micro_lamports.is_some() && self.priority_fee.percentile.is_some() {
+            problems.push("priority_fee: set micro_lamports or percentile, not both".to_string());
+        }
+        if self.priority_fee.percentile.is_some_and(|percentile| !(0.0..=100.0).contains(&percentile)) {
+            problems.push("priority_fee.percentile must be between 0 and 100".to_string());
+        }
+        if self.priority_fee.percentile.is_some() && self.priority_fee.max_micro_lamports.is_none() {
+            problems.push("priority_fee.percentile requires priority_fee.max_micro_lamports".to_string());
+        }
+        if self.priority_fee.compute_unit_limit.is_some_and(|limit| limit == 0 || u64::from(limit) > MAX_TRANSACTION_UNITS) {
+            problems.push(format!("priority_fee.compute_unit_limit must be between 1 and {}", MAX_TRANSACTION_UNITS));
+        }
+
+        match reqwest::Url::parse(&self.safety.pool_api_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => problems.push(format!("safety.pool_api_url: '{}' is not an http(s) URL", self.safety.pool_api_url)),
+        }
+
+        if let Some(list_file) = &self.tokens.list_file {
+            if !list_file.is_file() {
+                problems.push(format!("tokens.list_file: {} does not exist", list_file.display()));
+            }
+        }
+
+        let limits = [
+            ("risk.max_daily_loss_sol", self.risk.max_daily_loss_sol),
+            ("risk.max_deployed_sol", self.risk.max_deployed_sol),
+        ];
+        for (name, value) in limits {
+            if value.is_some_and(|value| !(value.is_finite() && value > 0.0)) {
+                problems.push(format!("{} must be a positive number", name));
+            }
+        }
+
+        let paper = [
+            ("paper.slippage_pct", self.paper.slippage_pct),
+            ("paper.fee_sol", self.paper.fee_sol),
+            ("paper.starting_balance_sol", self.paper.starting_balance_sol),
+        ];
+        for (name, value) in paper {
+            if !(value.is_finite() && value >= 0.0) {
+                problems.push(format!("{} must be a non-negative number", name));
+            }
+        }
+    }
+
+    /// Checks the config of the `signer-server` process, which needs nothing but the keypair,
+    /// the socket and the policy.
+    fn validate_signer(&self, problems: &mut Vec<String>) {
+        if self.wallet.signer.is_none() {
+            problems.push("signer-server requires the wallet keypair (WALLET_KEYSTORE or WALLET_PRIVATE_KEY)".to_string());
+        }
+        if self.signer.socket.is_none() {
+            problems.push("signer-server requires signer.socket (WALLET_SIGNER_SOCKET)".to_string());
+        }
+        if !(self.signer.max_sol_out.is_finite() && self.signer.max_sol_out > 0.0) {
+            problems.push("signer.max_sol_out must be a positive number".to_string());
+        }
+        for program in &self.signer.allowed_programs {
+            if bs58::decode(program).into_vec().map_or(true, |bytes| bytes.len() != 32) {
+                problems.push(format!("signer.allowed_programs: '{}' is not an address", program));
+            }
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            problems.push(format!("logging.level: {}", e));
+        }
+    }
+
+    pub fn bind_addr(&self) -> Result<SocketAddr, AppError> {
+        let ip: IpAddr = self.server.bind.parse()
+            .map_err(|_| AppError::ConfigError(format!("Invalid bind address: {}", self.server.bind)))?;
+        Ok(SocketAddr::new(ip, self.server.port))
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.server.request_timeout_secs)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.server.shutdown_timeout_secs)
+    }
+}
+
+/// Runs `config check`: prints a summary of a valid config, or every problem found.
+pub fn check(config: Result<Config, Vec<String>>) -> Result<(), AppError> {
+    match config {
+        Ok(config) => {
+            println!("Configuration OK");
+            println!("  bind:            {}:{}", config.server.bind, config.server.port);
+            println!("  backend:         {:?}", config.database.backend);
+            println!("  request timeout: {}s", config.server.request_timeout_secs);
+            println!("  drain deadline:  {}s", config.server.shutdown_timeout_secs);
+            println!("  cors origins:    {:?}", config.cors.allowed_origins);
+            println!("  logging:         {} ({:?})", config.logging.level, config.logging.format);
+            println!("  trading wallet:  {} ({})", config.wallet.user_id.as_deref().unwrap_or_default(), match (&config.wallet.signer, &config.wallet.keystore, &config.signer.socket) {
+                (Some(_), Some(keystore), _) => format!("unlocked from {}", keystore.display()),
+                (Some(_), None, _) => "keypair from WALLET_PRIVATE_KEY".to_string(),
+                (None, _, Some(socket)) => format!("signed by the signer at {}", socket.display()),
+                (None, _, None) => "address only".to_string(),
+            });
+            println!("  solana rpc:      {} ({})", config.solana.rpc_url, config.solana.commitment);
+            println!("  wallet monitor:  {}", if config.monitor.enabled { config.solana.ws_url() } else { "disabled".to_string() });
+            println!("  paper trading:   {}% slippage, {} SOL fee, {} SOL start", config.paper.slippage_pct, config.paper.fee_sol, config.paper.starting_balance_sol);
+            println!("  position exits:  {}", if config.exits.enabled { format!("every {}s, prices from {}", config.exits.poll_interval_secs, config.exits.price_api_url) } else { "disabled".to_string() });
+            let limit = |value: Option<f64>| value.map_or("none".to_string(), |value| format!("{} SOL", value));
+            println!("  risk limits:     daily loss {}, deployed {}", limit(config.risk.max_daily_loss_sol), limit(config.risk.max_deployed_sol));
+            println!("  token safety:    pools from {}", config.safety.pool_api_url);
+            println!("  token metadata:  {}", config.tokens.list_file.as_ref().map_or("on-chain".to_string(), |list_file| format!("{}, then on-chain", list_file.display())));
+            let fee = &config.priority_fee;
+            println!("  priority fee:    {}, max {}, {} compute units",
+                match (fee.micro_lamports, fee.percentile) {
+                    (Some(micro_lamports), _) => format!("{} micro-lamports", micro_lamports),
+                    (None, Some(percentile)) => format!("recent fees at p{}", percentile),
+                    (None, None) => "none".to_string(),
+                },
+                fee.max_micro_lamports.map_or("none".to_string(), |max| format!("{} micro-lamports", max)),
+                fee.compute_unit_limit.map_or("simulated".to_string(), |limit| limit.to_string()),
+            );
+            println!("  bundles:         {} ({} lamport tip)", config.bundle.block_engine_url, config.bundle.tip_lamports);
+            println!("  execution:       {}", if config.execution.enabled { config.execution.swap_api_url.clone() } else { "disabled (decisions are only logged)".to_string() });
+            Ok(())
+        }
+        Err(problems) => {
+            println!("Configuration has {} problem(s):", problems.len());
+            for problem in &problems {
+                println!("  - {}", problem);
+            }
+            Err(AppError::ConfigError(format!("{} configuration problem(s)", problems.len())))
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Feeds every transaction of a tracked wallet seen by the monitor through the decision engine.
+//! Each tracked wallet's transactions are handled in order by a worker of its own, so a swap
+//! waiting for confirmation holds up neither the other wallets nor the monitor's events.
+
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::balances::BalanceService;
+use crate::db::SupabaseClient;
+use crate::engine::{self, CopyDecision, EngineContext};
+use crate::error::AppError;
+use crate::executor::{Executor, SwapOrder};
+use crate::ledger::Ledger;
+use crate::models::Transaction;
+use crate::monitor::{ActivityEvent, ActivityKind, MonitorHandle};
+use crate::paper::{self, PaperSettings};
+use crate::parser::{self, TradeKind, WSOL_MINT};
+use crate::risk::RiskGuard;
+use crate::rpc::SolanaRpc;
+use crate::safety::SafetyService;
+use crate::shutdown::BackgroundTasks;
+
+/// A transaction can be announced over the websocket before `getTransaction` returns it.
+const FETCH_ATTEMPTS: u32 = 5;
+const FETCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+pub fn spawn(copier: Copier, monitor: &MonitorHandle, tasks: &BackgroundTasks) {
+    tasks.spawn(Arc::new(copier).run(monitor.subscribe(), tasks.clone()));
+}
+
+/// A transaction of a tracked wallet and its signature.
+type Job = (ActivityEvent, String);
+
+pub struct Copier {
+    pub db: SupabaseClient,
+    /// Copy trades and paper fills, which positions and limits are measured on.
+    pub ledger: Ledger,
+    pub rpc: SolanaRpc,
+    /// The trading wallet and its balances, which limit buys.
+    pub balances: BalanceService,
+    /// The account-wide kill switch and limits.
+    pub risk: RiskGuard,
+    /// Assesses tokens for the settings' token safety checks.
+    pub safety: SafetyService,
+    /// `None` when execution is disabled and decisions are only logged.
+    pub executor: Option<Executor>,
+    pub paper: PaperSettings,
+}
+
+impl Copier {
+    async fn run(self: Arc<Self>, mut events: Receiver<ActivityEvent>, tasks: BackgroundTasks) {
+        info!("Copier started");
+
+        let shutdown = tasks.shutdown_token();
+        let mut workers: HashMap<Uuid, mpsc::UnboundedSender<Job>> = HashMap::new();
+        loop {
+            let event = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                event = events.recv() => event,
+            };
+            match event {
+                Ok(event) => {
+                    let ActivityKind::Transaction { signature, failed: false } = &event.kind else {
+                        continue;
+                    };
+                    let signature = signature.clone();
+                    let worker = workers.entry(event.tracked_wallet_id).or_insert_with(|| {
+                        let (jobs, queue) = mpsc::unbounded_channel();
+                        tasks.spawn(self.clone().work(queue, shutdown.clone()));
+                        jobs
+                    });
+                    // A worker only stops at shutdown.
+                    let _ = worker.send((event, signature));
+                }
+                Err(RecvError::Lagged(missed)) => warn!(missed, "Copier fell behind; transactions were skipped"),
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        info!("Copier stopped");
+    }
+
+    /// Handles one tracked wallet's transactions in the order they were seen. A swap in flight
+    /// at shutdown is finished; transactions still queued are dropped.
+    async fn work(self: Arc<Self>, mut queue: mpsc::UnboundedReceiver<Job>, shutdown: CancellationToken) {
+        loop {
+            let job = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                job = queue.recv() => job,
+            };
+            let Some((event, signature)) = job else {
+                break;
+            };
+            if let Err(e) = self.handle_transaction(&event, &signature).await {
+                warn!(error = %e, %signature, tracked_wallet_id = %event.tracked_wallet_id, "Failed to process transaction");
+            }
+        }
+    }
+
+    async fn handle_transaction(&self, event: &ActivityEvent, signature: &str) -> Result<(), AppError> {
+        let Some(tx) = self.fetch_transaction(signature).await? else {
+            warn!(%signature, "Transaction not found");
+            return Ok(());
+        };
+        let Some(trade) = parser::parse_transaction(&tx, &event.wallet_address)? else {
+            debug!(%signature, "Not a trade of the tracked wallet");
+            return Ok(());
+        };
+
+        // Track the wallet's holdings from every trade, copied or not, so later sells can be
+        // mirrored proportionally.
+        let mut tracked_holding = None;
+        if trade.token_address != WSOL_MINT {
+            tracked_holding = self.db.get_leader_holding(event.tracked_wallet_id, &trade.token_address).await?
+                .map(|holding| holding.amount);
+            let holding_after = engine::leader_holding_after(tracked_holding, &trade);
+            self.db.set_leader_holding(event.tracked_wallet_id, &trade.token_address, holding_after).await?;
+        }
+
+        let settings = match self.db.get_copy_trade_settings_for_wallet(event.tracked_wallet_id).await {
+            Ok(settings) => settings,
+            Err(AppError::NotFound(_)) => {
+                debug!(tracked_wallet_id = %event.tracked_wallet_id, "Wallet has no copy trade settings");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Held until the trade is done, so it is decided on a ledger and balances no other trade
+        // is about to change.
+        let lock = self.ledger.lock(event.tracked_wallet_id, trade.kind == TradeKind::Buy && !settings.paper_trading).await;
+
+        // Account limits are measured on real copy trades, but a halt stops paper trading too.
+        let live_history = self.ledger.history().await?;
+        let risk = self.risk.check(&live_history).await?;
+
+        // Paper-traded wallets trade against simulated fills and their own virtual balance.
+        let (history, balances) = if settings.paper_trading {
+            (self.ledger.paper_history().await?, None)
+        } else {
+            (live_history, Some(self.balances.get().await?))
+        };
+        let mut all_positions = engine::open_positions(&history);
+        let token_exposure_sol = engine::token_exposure_sol(&all_positions, &trade.token_address);
+        let positions = all_positions
+            .remove(&Some(event.tracked_wallet_id))
+            .unwrap_or_default();
+        let (sol_balance, holds_token_account) = match &balances {
+            Some(balances) => (balances.sol, balances.has_token_account(&trade.token_address)),
+            // An open paper position stands in for the token account a real one would have.
+            None => {
+                let fills: Vec<Transaction> = history.iter()
+                    .filter(|transaction| transaction.tracked_wallet_id == Some(event.tracked_wallet_id))
+                    .cloned()
+                    .collect();
+                (
+                    paper::balance(&self.paper, &fills),
+                    positions.iter().any(|position| position.token_address == trade.token_address),
+                )
+            }
+        };
+
+        let mut token_risk = None;
+        if trade.kind == TradeKind::Buy && engine::checks_token_risk(&settings) {
+            token_risk = Some(self.safety.assess(&trade.token_address).await?);
+        }
+
+        let decision = engine::decide(&settings, &trade, &EngineContext {
+            positions: &positions,
+            sol_balance,
+            leader_holding: tracked_holding,
+            holds_token_account,
+            // Paper trades pay no priority fees or tips, nor do trades when execution is disabled.
+            priority_fee_sol: self.executor.as_ref()
+                .filter(|_| !settings.paper_trading)
+                .map_or(0.0, |executor| executor.max_fees_sol(&settings)),
+            halted: risk.halted,
+            // Paper trades deploy no real SOL.
+            deployable_sol: risk.deployable_sol.filter(|_| !settings.paper_trading),
+            token_exposure_sol,
+            history: &history,
+            now: Utc::now(),
+            token_risk: token_risk.as_ref(),
+        });
+        match &decision {
+            CopyDecision::Skip { reason } => info!(
+                %signature,
+                tracked_wallet_id = %event.tracked_wallet_id,
+                kind = trade.kind.as_str(),
+                token = %trade.token_address,
+                %reason,
+                "Not copying trade"
+            ),
+            _ => info!(
+                %signature,
+                tracked_wallet_id = %event.tracked_wallet_id,
+                ?decision,
+                "Copying trade"
+            ),
+        }
+
+        if settings.paper_trading {
+            if let Some(fill) = paper::simulate_fill(&decision, &trade, &self.paper, self.balances.wallet_address(), event.tracked_wallet_id) {
+                self.ledger.record_paper(&fill).await;
+                info!(%signature, amount = fill.amount, price_sol = fill.price_sol, "Paper trade recorded");
+            }
+            return Ok(());
+        }
+
+        let (Some(executor), Some(order)) = (&self.executor, SwapOrder::from_decision(&decision, trade.token_decimals)) else {
+            return Ok(());
+        };
+        executor.execute(&order, &settings, lock).await?;
+
+        Ok(())
+    }
+
+    async fn fetch_transaction(&self, signature: &str) -> Result<Option<serde_json::Value>, AppError> {
+        for attempt in 1..=FETCH_ATTEMPTS {
+            if let Some(tx) = self.rpc.get_transaction(signature).await? {
+                return Ok(Some(tx));
+            }
+            if attempt < FETCH_ATTEMPTS {
+                tokio::time::sleep(FETCH_RETRY_DELAY).await;
+            }
+        }
+        Ok(None)
+    }
+}
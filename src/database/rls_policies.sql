@@ -23,4 +23,35 @@ ALTER TABLE copy_trade_settings ENABLE ROW LEVEL SECURITY;
 CREATE POLICY "Users can only view their own transactions" ON transactions
   USING (user_id = auth.uid()::text);
 
-ALTER TABLE transactions ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
+ALTER TABLE transactions ENABLE ROW LEVEL SECURITY;
+
+-- For the leader_holdings table
+CREATE POLICY "Users can only access their own leader holdings" ON leader_holdings
+  USING (user_id = auth.uid()::text OR auth.role() = 'service_role')
+  WITH CHECK (user_id = auth.uid()::text OR auth.role() = 'service_role');
+
+ALTER TABLE leader_holdings ENABLE ROW LEVEL SECURITY;
+
+-- For the paper_transactions table
+CREATE POLICY "Users can only view their own paper transactions" ON paper_transactions
+  USING (user_id = auth.uid()::text);
+
+ALTER TABLE paper_transactions ENABLE ROW LEVEL SECURITY;
+
+-- For the risk_halts table
+CREATE POLICY "Users can only access their own risk halts" ON risk_halts
+  USING (user_id = auth.uid()::text OR auth.role() = 'service_role')
+  WITH CHECK (user_id = auth.uid()::text OR auth.role() = 'service_role');
+
+ALTER TABLE risk_halts ENABLE ROW LEVEL SECURITY;
+
+-- For the token_metadata table, which is shared by all users
+CREATE POLICY "Anyone can read token metadata" ON token_metadata
+  FOR SELECT
+  USING (true);
+
+CREATE POLICY "Only the service role can change token metadata" ON token_metadata
+  USING (auth.role() = 'service_role')
+  WITH CHECK (auth.role() = 'service_role');
+
+ALTER TABLE token_metadata ENABLE ROW LEVEL SECURITY;
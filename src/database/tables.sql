@@ -2,6 +2,10 @@ DROP TABLE users cascade;
 DROP TABLE tracked_wallets cascade;
 DROP TABLE copy_trade_settings cascade;
 DROP TABLE transactions cascade;
+DROP TABLE leader_holdings cascade;
+DROP TABLE paper_transactions cascade;
+DROP TABLE risk_halts cascade;
+DROP TABLE token_metadata cascade;
 
 
 CREATE TABLE users (
@@ -35,6 +39,30 @@ CREATE TABLE copy_trade_settings (
   allowed_tokens TEXT[],
   use_allowed_tokens_list BOOLEAN DEFAULT false,
   min_sol_balance DECIMAL(18, 9) DEFAULT 0.01,
+  paper_trading BOOLEAN DEFAULT false,
+  take_profit_pct DECIMAL(8, 2),
+  stop_loss_pct DECIMAL(5, 2),
+  trailing_stop_pct DECIMAL(5, 2),
+  max_token_exposure_sol DECIMAL(18, 9),
+  max_wallet_exposure_sol DECIMAL(18, 9),
+  min_seconds_between_trades INT,
+  max_trades_per_hour INT,
+  rebuy_cooldown_minutes INT,
+  schedule JSONB,
+  schedule_timezone TEXT,
+  sizing JSONB,
+  min_trade_sol DECIMAL(18, 9),
+  max_trade_sol DECIMAL(18, 9),
+  reject_mint_authority BOOLEAN DEFAULT false,
+  reject_freeze_authority BOOLEAN DEFAULT false,
+  max_top_holders_pct DECIMAL(5, 2),
+  min_liquidity_sol DECIMAL(18, 9),
+  min_pool_age_minutes INT,
+  priority_fee JSONB,
+  max_priority_fee_micro_lamports BIGINT,
+  compute_unit_limit INT,
+  submission TEXT,
+  bundle_tip_lamports BIGINT,
   created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
   updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
   UNIQUE(user_id, tracked_wallet_id)
@@ -58,7 +86,46 @@ CREATE TABLE transactions (
   signature TEXT NOT NULL,
   transaction_type TEXT NOT NULL,
   token_address TEXT NOT NULL,
-  amount DECIMAL(18, 9) NOT NULL,
-  price_sol DECIMAL(18, 9) NOT NULL,
+  amount DECIMAL(38, 9) NOT NULL,
+  price_sol DOUBLE PRECISION NOT NULL,
   timestamp TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
-);
\ No newline at end of file
+);
+
+CREATE TABLE leader_holdings (
+  id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+  user_id TEXT REFERENCES users(wallet_address),
+  tracked_wallet_id UUID REFERENCES tracked_wallets(id),
+  token_address TEXT NOT NULL,
+  amount DECIMAL(38, 9) NOT NULL,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE(user_id, tracked_wallet_id, token_address)
+);
+
+CREATE TABLE paper_transactions (
+  id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+  user_id TEXT REFERENCES users(wallet_address),
+  tracked_wallet_id UUID REFERENCES tracked_wallets(id),
+  signature TEXT NOT NULL,
+  transaction_type TEXT NOT NULL,
+  token_address TEXT NOT NULL,
+  amount DECIMAL(38, 9) NOT NULL,
+  price_sol DOUBLE PRECISION NOT NULL,
+  timestamp TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE risk_halts (
+  id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+  user_id TEXT REFERENCES users(wallet_address),
+  reason TEXT NOT NULL,
+  halted_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  resumed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE TABLE token_metadata (
+  mint TEXT PRIMARY KEY,
+  symbol TEXT,
+  name TEXT,
+  decimals SMALLINT NOT NULL,
+  logo_uri TEXT,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
@@ -1,9 +1,14 @@
-use postgrest::Postgrest;
+use postgrest::{Builder, Postgrest};
 use serde_json::json;
+use tracing::debug;
 use uuid::Uuid;
 
-use crate::models::{TrackedWallet, CopyTradeSettings, Transaction};
+use crate::models::{TrackedWallet, TrackedWalletPatch, CopyTradeSettings, CopyTradeSettingsPatch, Transaction, LeaderHolding, RiskHalt, TokenMetadata};
+use crate::engine;
 use crate::error::AppError;
+use crate::executor::{self, ComputeBudget};
+use crate::schedule;
+use crate::telemetry;
 
 impl From<reqwest::Error> for AppError {
     fn from(error: reqwest::Error) -> Self {
@@ -19,7 +24,7 @@ pub struct SupabaseClient {
 
 impl SupabaseClient {
     pub fn new(url: &str, _api_key: &str, service_role_key: &str, user_id: &str) -> Self {
-        println!("New Postgrest client created!");
+        debug!("New Postgrest client created");
         let client = Postgrest::new(url)
             .insert_header("apikey", service_role_key)
             .insert_header("Authorization", format!("Bearer {}", service_role_key));
@@ -31,8 +36,22 @@ impl SupabaseClient {
         
     }
 
+    /// Starts a query on `table`, forwarding the current `x-request-id` so a failed API call
+    /// can be matched with the PostgREST request it made.
+    fn from(&self, table: &str) -> Builder {
+        let request_id = telemetry::current_request_id();
+        debug!(table, request_id = request_id.as_deref().unwrap_or_default(), "PostgREST request");
+
+        match request_id {
+            Some(request_id) => self.client.clone()
+                .insert_header("x-request-id", request_id)
+                .from(table),
+            None => self.client.from(table),
+        }
+    }
+
     pub async fn get_tracked_wallets(&self) -> Result<Vec<TrackedWallet>, AppError> {
-        let resp = self.client
+        let resp = self
             .from("tracked_wallets")
             .select("*")
             .eq("user_id", &self.user_id)
@@ -46,11 +65,7 @@ impl SupabaseClient {
         let wallets: Vec<TrackedWallet> = serde_json::from_str(&body)
             .map_err(|e| AppError::JsonParseError(format!("Failed to parse wallets: {}", e)))?;
 
-        if wallets.is_empty() {
-            println!("No wallets found");
-        } else {
-            println!("Found wallets: {:?}", wallets);
-        }
+        debug!(count = wallets.len(), "Fetched tracked wallets");
 
         Ok(wallets)
     }
@@ -64,7 +79,7 @@ impl SupabaseClient {
             "is_active": wallet.is_active
         });
 
-        let resp = self.client
+        let resp = self
             .from("tracked_wallets")
             .insert(insert_data.to_string())
             .execute()
@@ -77,7 +92,7 @@ impl SupabaseClient {
         let inserted: Vec<TrackedWallet> = serde_json::from_str(&body)
             .map_err(|e| AppError::JsonParseError(e.to_string()))?;
 
-        let first_wallet = inserted.get(0)
+        let first_wallet = inserted.first()
             .ok_or_else(|| AppError::DatabaseError("No wallet was inserted".to_string()))?;
 
         first_wallet.id
@@ -85,7 +100,7 @@ impl SupabaseClient {
     }
 
     pub async fn archive_tracked_wallet(&self, wallet_address: &str) -> Result<String, AppError> {
-        let resp = self.client
+        let resp = self
             .from("tracked_wallets")
             .update(json!({"is_active": false}).to_string())
             .eq("user_id", &self.user_id)
@@ -99,13 +114,13 @@ impl SupabaseClient {
 
         let updated: Vec<TrackedWallet> = serde_json::from_str(&body)?;
 
-        updated.get(0)
+        updated.first()
             .ok_or_else(|| AppError::DatabaseError("No wallet was updated".to_string()))
             .map(|wallet| format!("Archived wallet: {}", wallet.wallet_address))
     }
 
     pub async fn unarchive_tracked_wallet(&self, wallet_address: &str) -> Result<String, AppError> {
-        let resp = self.client
+        let resp = self
             .from("tracked_wallets")
             .update(json!({"is_active": true}).to_string())
             .eq("user_id", &self.user_id)
@@ -119,13 +134,13 @@ impl SupabaseClient {
 
         let updated: Vec<TrackedWallet> = serde_json::from_str(&body)?;
 
-        updated.get(0)
+        updated.first()
             .ok_or_else(|| AppError::DatabaseError("No wallet was updated".to_string()))
             .map(|wallet| format!("Unarchived wallet: {}", wallet.wallet_address))
     }
 
     pub async fn delete_tracked_wallet(&self, wallet_address: &str) -> Result<String, AppError> {
-        let resp = self.client
+        let resp = self
             .from("tracked_wallets")
             .delete()
             .eq("user_id", &self.user_id)
@@ -157,7 +172,7 @@ impl SupabaseClient {
         let wallet_id = wallet.id
             .ok_or_else(|| AppError::BadRequest("Wallet ID is required for update".to_string()))?;
         
-        let resp = self.client
+        let resp = self
             .from("tracked_wallets")
             .update(json!({
                 "id": wallet_id,
@@ -176,13 +191,81 @@ impl SupabaseClient {
         
         let updated: Vec<TrackedWallet> = serde_json::from_str(&body)?;
         
-        updated.get(0)
+        updated.first()
             .and_then(|w| w.id)
             .ok_or_else(|| AppError::DatabaseError("Failed to update wallet".to_string()))
     }
 
+    pub async fn get_tracked_wallet(&self, id: Uuid) -> Result<TrackedWallet, AppError> {
+        let resp = self
+            .from("tracked_wallets")
+            .select("*")
+            .eq("user_id", &self.user_id)
+            .eq("id", id.to_string())
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+        let wallets: Vec<TrackedWallet> = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Failed to parse wallets: {}", e)))?;
+
+        wallets.into_iter().next()
+            .ok_or_else(|| AppError::NotFound(format!("Tracked wallet {} not found", id)))
+    }
+
+    pub async fn patch_tracked_wallet(&self, id: Uuid, patch: TrackedWalletPatch) -> Result<TrackedWallet, AppError> {
+        let changes = non_empty_patch(&patch)?;
+
+        let resp = self
+            .from("tracked_wallets")
+            .update(changes)
+            .eq("user_id", &self.user_id)
+            .eq("id", id.to_string())
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+        let updated: Vec<TrackedWallet> = serde_json::from_str(&body)?;
+
+        updated.into_iter().next()
+            .ok_or_else(|| AppError::NotFound(format!("Tracked wallet {} not found", id)))
+    }
+
+    pub async fn delete_tracked_wallet_by_id(&self, id: Uuid) -> Result<String, AppError> {
+        let resp = self
+            .from("tracked_wallets")
+            .delete()
+            .eq("user_id", &self.user_id)
+            .eq("id", id.to_string())
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::DatabaseError(format!("Failed to delete tracked wallet. Status: {}", resp.status())));
+        }
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+        let deleted_items: Vec<serde_json::Value> = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(e.to_string()))?;
+
+        if deleted_items.is_empty() {
+            Err(AppError::NotFound(format!("Tracked wallet {} not found", id)))
+        } else {
+            Ok("Tracked wallet deleted successfully".to_string())
+        }
+    }
+
     pub async fn get_copy_trade_settings(&self) -> Result<Vec<CopyTradeSettings>, AppError> {
-        let resp = self.client
+        let resp = self
             .from("copy_trade_settings")
             .select("*")
             .eq("user_id", &self.user_id)
@@ -196,8 +279,13 @@ impl SupabaseClient {
         Ok(settings)
     }
 
-    pub async fn create_copy_trade_settings(&self, settings: CopyTradeSettings) -> Result<Uuid, AppError> {
-        let resp = self.client
+    /// `defaults` is the compute budget of the config file, which the settings fall back to.
+    pub async fn create_copy_trade_settings(&self, settings: CopyTradeSettings, defaults: &ComputeBudget) -> Result<Uuid, AppError> {
+        schedule::validate(settings.schedule.as_deref(), settings.schedule_timezone.as_deref())?;
+        engine::validate_exits(settings.take_profit_pct, settings.stop_loss_pct, settings.trailing_stop_pct)?;
+        executor::validate_priority_fee(settings.priority_fee.as_ref(), settings.max_priority_fee_micro_lamports, defaults)?;
+
+        let resp = self
             .from("copy_trade_settings")
             .insert(json!({
                 "user_id": self.user_id,
@@ -210,7 +298,31 @@ impl SupabaseClient {
                 "use_allowed_tokens_list": settings.use_allowed_tokens_list,
                 "allow_additional_buys": settings.allow_additional_buys,
                 "match_sell_percentage": settings.match_sell_percentage,
-                "min_sol_balance": settings.min_sol_balance
+                "min_sol_balance": settings.min_sol_balance,
+                "paper_trading": settings.paper_trading,
+                "take_profit_pct": settings.take_profit_pct,
+                "stop_loss_pct": settings.stop_loss_pct,
+                "trailing_stop_pct": settings.trailing_stop_pct,
+                "max_token_exposure_sol": settings.max_token_exposure_sol,
+                "max_wallet_exposure_sol": settings.max_wallet_exposure_sol,
+                "min_seconds_between_trades": settings.min_seconds_between_trades,
+                "max_trades_per_hour": settings.max_trades_per_hour,
+                "rebuy_cooldown_minutes": settings.rebuy_cooldown_minutes,
+                "schedule": settings.schedule,
+                "schedule_timezone": settings.schedule_timezone,
+                "sizing": settings.sizing,
+                "min_trade_sol": settings.min_trade_sol,
+                "max_trade_sol": settings.max_trade_sol,
+                "reject_mint_authority": settings.reject_mint_authority,
+                "reject_freeze_authority": settings.reject_freeze_authority,
+                "max_top_holders_pct": settings.max_top_holders_pct,
+                "min_liquidity_sol": settings.min_liquidity_sol,
+                "min_pool_age_minutes": settings.min_pool_age_minutes,
+                "priority_fee": settings.priority_fee,
+                "max_priority_fee_micro_lamports": settings.max_priority_fee_micro_lamports,
+                "compute_unit_limit": settings.compute_unit_limit,
+                "submission": settings.submission,
+                "bundle_tip_lamports": settings.bundle_tip_lamports
             }).to_string())
             .execute()
             .await
@@ -221,27 +333,52 @@ impl SupabaseClient {
         
         let inserted: Vec<CopyTradeSettings> = serde_json::from_str(&body)?;
 
-        inserted.get(0)
+        inserted.first()
             .and_then(|s| s.id)
             .ok_or_else(|| AppError::DatabaseError("Failed to create copy trade settings".to_string()))
     }
 
-    pub async fn update_copy_trade_settings(&self, settings: CopyTradeSettings) -> Result<Uuid, AppError> {
-        let resp = self.client
+    pub async fn get_copy_trade_settings_for_wallet(&self, tracked_wallet_id: Uuid) -> Result<CopyTradeSettings, AppError> {
+        let resp = self
             .from("copy_trade_settings")
-            .update(json!({
-                "is_enabled": settings.is_enabled,
-                "trade_amount_sol": settings.trade_amount_sol,
-                "max_slippage": settings.max_slippage,
-                "max_open_positions": settings.max_open_positions,
-                "allowed_tokens": settings.allowed_tokens,
-                "use_allowed_tokens_list": settings.use_allowed_tokens_list,
-                "allow_additional_buys": settings.allow_additional_buys,
-                "match_sell_percentage": settings.match_sell_percentage,
-                "min_sol_balance": settings.min_sol_balance
-            }).to_string())
+            .select("*")
             .eq("user_id", &self.user_id)
-            .eq("tracked_wallet_id", settings.tracked_wallet_id.to_string())
+            .eq("tracked_wallet_id", tracked_wallet_id.to_string())
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+        let settings: Vec<CopyTradeSettings> = serde_json::from_str(&body)?;
+
+        settings.into_iter().next()
+            .ok_or_else(|| AppError::NotFound(format!("No copy trade settings for tracked wallet {}", tracked_wallet_id)))
+    }
+
+    /// `defaults` is the compute budget of the config file, which the settings fall back to.
+    pub async fn patch_copy_trade_settings(&self, tracked_wallet_id: Uuid, patch: CopyTradeSettingsPatch, defaults: &ComputeBudget) -> Result<CopyTradeSettings, AppError> {
+        schedule::validate(
+            patch.schedule.as_ref().and_then(Option::as_deref),
+            patch.schedule_timezone.as_ref().and_then(Option::as_deref),
+        )?;
+        engine::validate_exits(patch.take_profit_pct.flatten(), patch.stop_loss_pct.flatten(), patch.trailing_stop_pct.flatten())?;
+        if patch.priority_fee.is_some() || patch.max_priority_fee_micro_lamports.is_some() {
+            // The fee and its cap are checked together, so the one not patched is read.
+            let current = self.get_copy_trade_settings_for_wallet(tracked_wallet_id).await?;
+            executor::validate_priority_fee(
+                patch.priority_fee.unwrap_or(current.priority_fee).as_ref(),
+                patch.max_priority_fee_micro_lamports.unwrap_or(current.max_priority_fee_micro_lamports),
+                defaults,
+            )?;
+        }
+        let changes = non_empty_patch(&patch)?;
+
+        let resp = self
+            .from("copy_trade_settings")
+            .update(changes)
+            .eq("user_id", &self.user_id)
+            .eq("tracked_wallet_id", tracked_wallet_id.to_string())
             .execute()
             .await
             .map_err(|e| AppError::PostgrestError(e.to_string()))?;
@@ -251,13 +388,12 @@ impl SupabaseClient {
 
         let updated: Vec<CopyTradeSettings> = serde_json::from_str(&body)?;
 
-        updated.get(0)
-            .and_then(|s| s.id)
-            .ok_or_else(|| AppError::DatabaseError("Failed to update copy trade settings".to_string()))
+        updated.into_iter().next()
+            .ok_or_else(|| AppError::NotFound(format!("No copy trade settings for tracked wallet {}", tracked_wallet_id)))
     }
 
     pub async fn delete_copy_trade_settings(&self, tracked_wallet_id: Uuid) -> Result<String, AppError> {
-        let resp = self.client
+        let resp = self
             .from("copy_trade_settings")
             .delete()
             .eq("user_id", &self.user_id)
@@ -275,8 +411,17 @@ impl SupabaseClient {
 
 
     pub async fn get_transaction_history(&self) -> Result<Vec<Transaction>, AppError> {
-        let resp = self.client
-            .from("transactions")
+        self.get_transactions("transactions").await
+    }
+
+    /// Simulated fills of wallets in paper trading mode.
+    pub async fn get_paper_transaction_history(&self) -> Result<Vec<Transaction>, AppError> {
+        self.get_transactions("paper_transactions").await
+    }
+
+    async fn get_transactions(&self, table: &str) -> Result<Vec<Transaction>, AppError> {
+        let resp = self
+            .from(table)
             .select("*")
             .eq("user_id", &self.user_id)
             .execute()
@@ -292,21 +437,202 @@ impl SupabaseClient {
         Ok(transactions)
     }
 
-    // pub async fn log_transaction(&self, transaction: Transaction) -> Result<Uuid, AppError> {
-    //     let resp = self.client
-    //         .from("transactions")
-    //         .insert(json!({
-    //             "user_id": self.user_id,
-    //             "tracked_wallet_id": transaction.tracked_wallet_id,
-                
-    //         }).to_string()) // Convert JSON to String
-    //         .execute()
-    //         .await
-    //         .map_err(|e| AppError::PostgrestError(e.to_string()))?;
-
-    //     let body = resp.text().await
-    //         .map_err(|e| AppError::RequestError(e.to_string()))?;
-    //     let inserted: Vec<Transaction> = serde_json::from_str(&body)?;
-    //     Ok(inserted[0].id.unwrap()) //replace this in production
-    // }
-}
\ No newline at end of file
+    pub async fn get_leader_holdings(&self, tracked_wallet_id: Uuid) -> Result<Vec<LeaderHolding>, AppError> {
+        let resp = self
+            .from("leader_holdings")
+            .select("tracked_wallet_id,token_address,amount,updated_at")
+            .eq("user_id", &self.user_id)
+            .eq("tracked_wallet_id", tracked_wallet_id.to_string())
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+        let holdings: Vec<LeaderHolding> = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Failed to parse leader holdings: {}", e)))?;
+
+        Ok(holdings)
+    }
+
+    pub async fn get_leader_holding(&self, tracked_wallet_id: Uuid, token_address: &str) -> Result<Option<LeaderHolding>, AppError> {
+        let resp = self
+            .from("leader_holdings")
+            .select("tracked_wallet_id,token_address,amount,updated_at")
+            .eq("user_id", &self.user_id)
+            .eq("tracked_wallet_id", tracked_wallet_id.to_string())
+            .eq("token_address", token_address)
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+        let holdings: Vec<LeaderHolding> = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Failed to parse leader holdings: {}", e)))?;
+
+        Ok(holdings.into_iter().next())
+    }
+
+    pub async fn set_leader_holding(&self, tracked_wallet_id: Uuid, token_address: &str, amount: f64) -> Result<(), AppError> {
+        let resp = self
+            .from("leader_holdings")
+            .upsert(json!({
+                "user_id": self.user_id,
+                "tracked_wallet_id": tracked_wallet_id,
+                "token_address": token_address,
+                "amount": amount,
+                "updated_at": chrono::Utc::now(),
+            }).to_string())
+            .on_conflict("user_id,tracked_wallet_id,token_address")
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::DatabaseError(format!("Failed to save leader holding. Status: {}", resp.status())));
+        }
+
+        Ok(())
+    }
+
+    /// Cached metadata of those of `mints` that have been looked up before. Token metadata is the
+    /// same for every user.
+    pub async fn get_token_metadata(&self, mints: &[String]) -> Result<Vec<TokenMetadata>, AppError> {
+        let resp = self
+            .from("token_metadata")
+            .select("*")
+            .in_("mint", mints)
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+        let tokens: Vec<TokenMetadata> = serde_json::from_str(&body)?;
+        Ok(tokens)
+    }
+
+    pub async fn save_token_metadata(&self, token: &TokenMetadata) -> Result<(), AppError> {
+        let resp = self
+            .from("token_metadata")
+            .upsert(serde_json::to_string(token)?)
+            .on_conflict("mint")
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::DatabaseError(format!("Failed to save token metadata. Status: {}", resp.status())));
+        }
+
+        Ok(())
+    }
+
+    /// The latest halt, resumed or not.
+    pub async fn get_latest_halt(&self) -> Result<Option<RiskHalt>, AppError> {
+        let resp = self
+            .from("risk_halts")
+            .select("*")
+            .eq("user_id", &self.user_id)
+            .order("halted_at.desc")
+            .limit(1)
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+        let halts: Vec<RiskHalt> = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Failed to parse risk halts: {}", e)))?;
+
+        Ok(halts.into_iter().next())
+    }
+
+    pub async fn record_halt(&self, reason: &str) -> Result<RiskHalt, AppError> {
+        let resp = self
+            .from("risk_halts")
+            .insert(json!({
+                "user_id": self.user_id,
+                "reason": reason,
+                "halted_at": chrono::Utc::now(),
+            }).to_string())
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+        let inserted: Vec<RiskHalt> = serde_json::from_str(&body)?;
+
+        inserted.into_iter().next()
+            .ok_or_else(|| AppError::DatabaseError("Failed to record halt".to_string()))
+    }
+
+    /// Marks every active halt as resumed.
+    pub async fn resume_halts(&self) -> Result<(), AppError> {
+        let resp = self
+            .from("risk_halts")
+            .update(json!({ "resumed_at": chrono::Utc::now() }).to_string())
+            .eq("user_id", &self.user_id)
+            .is("resumed_at", "null")
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::DatabaseError(format!("Failed to resume halts. Status: {}", resp.status())));
+        }
+
+        Ok(())
+    }
+
+    pub async fn log_transaction(&self, transaction: &Transaction) -> Result<Uuid, AppError> {
+        self.insert_transaction("transactions", transaction).await
+    }
+
+    pub async fn log_paper_transaction(&self, transaction: &Transaction) -> Result<Uuid, AppError> {
+        self.insert_transaction("paper_transactions", transaction).await
+    }
+
+    /// Upserted on `id`, so a write retried after a response was lost does not fail.
+    async fn insert_transaction(&self, table: &str, transaction: &Transaction) -> Result<Uuid, AppError> {
+        let resp = self
+            .from(table)
+            .upsert(json!({
+                "id": transaction.id,
+                "user_id": self.user_id,
+                "tracked_wallet_id": transaction.tracked_wallet_id,
+                "signature": transaction.signature,
+                "transaction_type": transaction.transaction_type,
+                "token_address": transaction.token_address,
+                "amount": transaction.amount,
+                "price_sol": transaction.price_sol,
+                "timestamp": transaction.timestamp,
+            }).to_string())
+            .on_conflict("id")
+            .execute()
+            .await
+            .map_err(|e| AppError::PostgrestError(e.to_string()))?;
+
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(e.to_string()))?;
+        let inserted: Vec<Transaction> = serde_json::from_str(&body)?;
+
+        inserted.first()
+            .map(|transaction| transaction.id)
+            .ok_or_else(|| AppError::DatabaseError("No transaction was inserted".to_string()))
+    }
+}
+
+/// Serializes a patch, rejecting one that would not change anything.
+fn non_empty_patch<T: serde::Serialize>(patch: &T) -> Result<String, AppError> {
+    let changes = serde_json::to_value(patch)?;
+    if changes.as_object().is_some_and(|fields| fields.is_empty()) {
+        return Err(AppError::BadRequest("Patch must change at least one field".to_string()));
+    }
+    Ok(changes.to_string())
+}
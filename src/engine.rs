@@ -0,0 +1,898 @@
+//! Decides whether a trade made by a tracked wallet is copied, from that wallet's
+//! `CopyTradeSettings` and the positions already opened by copying it. Everything here is pure;
+//! the copier gathers the inputs and acts on the decision.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::{CopyTradeSettings, Sizing, TokenPnl, TokenRisk, Transaction};
+use crate::parser::{ParsedTrade, TradeKind, TOKEN_ACCOUNT_RENT_SOL};
+use crate::schedule;
+
+/// Amounts smaller than this are rounding noise.
+const DUST: f64 = 1e-9;
+/// Network fee of a transaction with one signature.
+const SIGNATURE_FEE_SOL: f64 = 0.000_005;
+
+/// Tokens held from trades copied from one tracked wallet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Position {
+    pub token_address: String,
+    pub amount: f64,
+    /// SOL paid for the tokens still held.
+    pub cost_sol: f64,
+}
+
+/// What the engine knows besides the settings and the trade.
+#[derive(Debug, Clone)]
+pub struct EngineContext<'a> {
+    /// Open positions copied from the wallet that made the trade.
+    pub positions: &'a [Position],
+    /// SOL balance of the trading wallet.
+    pub sol_balance: f64,
+    /// The wallet's holding of the traded token before the trade, as tracked from its earlier
+    /// trades. `None` when no trade of the token by the wallet has been seen yet.
+    pub leader_holding: Option<f64>,
+    /// Whether the trading wallet already has a token account for the traded token, so a buy
+    /// pays no rent for one.
+    pub holds_token_account: bool,
+    /// The most a copied buy can pay in priority fees and bundle tips, in SOL.
+    pub priority_fee_sol: f64,
+    /// Copying is halted account-wide, by the kill switch or a breached risk limit.
+    pub halted: bool,
+    /// SOL that can still be put into positions under the account's `max_deployed_sol`. `None`
+    /// when there is no limit.
+    pub deployable_sol: Option<f64>,
+    /// SOL in open positions in the traded token, copied from any tracked wallet.
+    pub token_exposure_sol: f64,
+    /// Copy trades recorded so far; those copied from the trade's wallet pace its buys.
+    pub history: &'a [Transaction],
+    /// When the decision is made.
+    pub now: DateTime<Utc>,
+    /// The traded token's assessment, when the settings check token safety. Buys are not
+    /// checked without one.
+    pub token_risk: Option<&'a TokenRisk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum CopyDecision {
+    Buy { token_address: String, sol_amount: f64 },
+    Sell { token_address: String, token_amount: f64 },
+    Skip { reason: SkipReason },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// All copying is halted.
+    Halted,
+    /// `is_enabled` is off for the wallet.
+    Disabled,
+    /// A buy at a time outside the wallet's `schedule` windows.
+    OutsideSchedule,
+    /// A transfer rather than a swap.
+    NotATrade,
+    /// Token-for-token swaps are not copied; only trades against SOL are.
+    NoSolLeg,
+    /// `use_allowed_tokens_list` is on and the token is not in `allowed_tokens`.
+    TokenNotAllowed,
+    /// Already holding the token and `allow_additional_buys` is off.
+    AlreadyHolding,
+    /// `max_open_positions` positions are already open.
+    MaxOpenPositions { open: usize },
+    /// The buy's `sizing` came to nothing, e.g. a percentage of an empty balance.
+    TooSmall,
+    /// Buying would leave less than `min_sol_balance` SOL.
+    InsufficientSol { balance: f64, required: f64 },
+    /// Buying would put more than the account's `max_deployed_sol` into positions.
+    MaxDeployed { deployable: f64 },
+    /// Buying would put more than `max_token_exposure_sol` into the token.
+    TokenExposureCap { exposure: f64, cap: f64 },
+    /// Buying would put more than `max_wallet_exposure_sol` into positions copied from the wallet.
+    WalletExposureCap { exposure: f64, cap: f64 },
+    /// `reject_mint_authority` is on and the token can still be minted.
+    MintAuthority,
+    /// `reject_freeze_authority` is on and the token's holders can be frozen.
+    FreezeAuthority,
+    /// The token's largest holders hold more than `max_top_holders_pct` of the supply.
+    HolderConcentration { top_holders_pct: f64, max: f64 },
+    /// The token's SOL pools hold less than `min_liquidity_sol`.
+    LowLiquidity { liquidity_sol: f64, min: f64 },
+    /// The token's oldest SOL pool is younger than `min_pool_age_minutes`, or there is none.
+    NewPool { age_minutes: Option<i64> },
+    /// A trade was copied from the wallet less than `min_seconds_between_trades` ago.
+    Cooldown { seconds_since_last: i64 },
+    /// `max_trades_per_hour` trades were copied from the wallet in the past hour.
+    HourlyTradeLimit { trades: usize },
+    /// The token was sold less than `rebuy_cooldown_minutes` ago.
+    RebuyCooldown { minutes_since_sell: i64 },
+    /// The wallet sold a token we do not hold.
+    NoPosition,
+}
+
+impl SkipReason {
+    /// The reason's tag, as it is serialized.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SkipReason::Halted => "halted",
+            SkipReason::Disabled => "disabled",
+            SkipReason::OutsideSchedule => "outside_schedule",
+            SkipReason::NotATrade => "not_a_trade",
+            SkipReason::NoSolLeg => "no_sol_leg",
+            SkipReason::TokenNotAllowed => "token_not_allowed",
+            SkipReason::AlreadyHolding => "already_holding",
+            SkipReason::MaxOpenPositions { .. } => "max_open_positions",
+            SkipReason::TooSmall => "too_small",
+            SkipReason::InsufficientSol { .. } => "insufficient_sol",
+            SkipReason::MaxDeployed { .. } => "max_deployed",
+            SkipReason::TokenExposureCap { .. } => "token_exposure_cap",
+            SkipReason::WalletExposureCap { .. } => "wallet_exposure_cap",
+            SkipReason::MintAuthority => "mint_authority",
+            SkipReason::FreezeAuthority => "freeze_authority",
+            SkipReason::HolderConcentration { .. } => "holder_concentration",
+            SkipReason::LowLiquidity { .. } => "low_liquidity",
+            SkipReason::NewPool { .. } => "new_pool",
+            SkipReason::Cooldown { .. } => "cooldown",
+            SkipReason::HourlyTradeLimit { .. } => "hourly_trade_limit",
+            SkipReason::RebuyCooldown { .. } => "rebuy_cooldown",
+            SkipReason::NoPosition => "no_position",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Halted => write!(f, "copy trading is halted"),
+            SkipReason::Disabled => write!(f, "copy trading is disabled for this wallet"),
+            SkipReason::OutsideSchedule => write!(f, "outside the wallet's copy trading schedule"),
+            SkipReason::NotATrade => write!(f, "not a trade"),
+            SkipReason::NoSolLeg => write!(f, "not a trade against SOL"),
+            SkipReason::TokenNotAllowed => write!(f, "token is not in the allowed tokens list"),
+            SkipReason::AlreadyHolding => write!(f, "already holding the token and additional buys are not allowed"),
+            SkipReason::MaxOpenPositions { open } => write!(f, "{} positions already open", open),
+            SkipReason::TooSmall => write!(f, "sized buy is too small"),
+            SkipReason::InsufficientSol { balance, required } => {
+                write!(f, "SOL balance {} is below the {} required", balance, required)
+            }
+            SkipReason::MaxDeployed { deployable } => {
+                write!(f, "only {} more SOL may be deployed", deployable)
+            }
+            SkipReason::TokenExposureCap { exposure, cap } => {
+                write!(f, "{} SOL already in the token, buying would exceed the {} SOL cap", exposure, cap)
+            }
+            SkipReason::WalletExposureCap { exposure, cap } => {
+                write!(f, "{} SOL already copied from the wallet, buying would exceed the {} SOL cap", exposure, cap)
+            }
+            SkipReason::MintAuthority => write!(f, "token can still be minted"),
+            SkipReason::FreezeAuthority => write!(f, "token accounts can be frozen"),
+            SkipReason::HolderConcentration { top_holders_pct, max } => {
+                write!(f, "largest holders hold {:.1}% of the supply, above the {}% allowed", top_holders_pct, max)
+            }
+            SkipReason::LowLiquidity { liquidity_sol, min } => {
+                write!(f, "{} SOL of liquidity is below the {} SOL required", liquidity_sol, min)
+            }
+            SkipReason::NewPool { age_minutes: Some(age_minutes) } => write!(f, "pool was created {} minutes ago", age_minutes),
+            SkipReason::NewPool { age_minutes: None } => write!(f, "token has no SOL pool"),
+            SkipReason::Cooldown { seconds_since_last } => {
+                write!(f, "last trade copied from the wallet {}s ago", seconds_since_last)
+            }
+            SkipReason::HourlyTradeLimit { trades } => write!(f, "{} trades copied from the wallet in the past hour", trades),
+            SkipReason::RebuyCooldown { minutes_since_sell } => {
+                write!(f, "token was sold {} minutes ago", minutes_since_sell)
+            }
+            SkipReason::NoPosition => write!(f, "no position in the token"),
+        }
+    }
+}
+
+fn skip(reason: SkipReason) -> CopyDecision {
+    CopyDecision::Skip { reason }
+}
+
+pub fn decide(settings: &CopyTradeSettings, trade: &ParsedTrade, ctx: &EngineContext) -> CopyDecision {
+    if ctx.halted {
+        return skip(SkipReason::Halted);
+    }
+    if !settings.is_enabled {
+        return skip(SkipReason::Disabled);
+    }
+    if trade.kind == TradeKind::Transfer {
+        return skip(SkipReason::NotATrade);
+    }
+    if trade.kind == TradeKind::Swap || trade.price_sol.is_none() {
+        return skip(SkipReason::NoSolLeg);
+    }
+    // Sells are copied at any time, so positions opened inside a window can still be closed.
+    if trade.kind == TradeKind::Buy && !schedule::in_schedule(settings, ctx.now) {
+        return skip(SkipReason::OutsideSchedule);
+    }
+
+    let position = ctx.positions.iter().find(|position| position.token_address == trade.token_address);
+
+    match trade.kind {
+        TradeKind::Buy => decide_buy(settings, trade, ctx, position),
+        TradeKind::Sell => decide_sell(settings, trade, ctx, position),
+        TradeKind::Transfer | TradeKind::Swap => unreachable!("transfers and swaps are skipped above"),
+    }
+}
+
+fn decide_buy(settings: &CopyTradeSettings, trade: &ParsedTrade, ctx: &EngineContext, position: Option<&Position>) -> CopyDecision {
+    if settings.use_allowed_tokens_list {
+        let allowed = settings.allowed_tokens.as_deref().unwrap_or_default();
+        if !allowed.contains(&trade.token_address) {
+            return skip(SkipReason::TokenNotAllowed);
+        }
+    }
+
+    if let Some(reason) = ctx.token_risk.and_then(|risk| token_risk_reason(settings, risk, ctx.now)) {
+        return skip(reason);
+    }
+    if let Some(reason) = pace(settings, trade, ctx) {
+        return skip(reason);
+    }
+
+    match position {
+        Some(_) if !settings.allow_additional_buys => return skip(SkipReason::AlreadyHolding),
+        Some(_) => {}
+        None => {
+            let open = ctx.positions.len();
+            if open >= settings.max_open_positions.max(0) as usize {
+                return skip(SkipReason::MaxOpenPositions { open });
+            }
+        }
+    }
+
+    let sol_amount = buy_size(settings, trade, ctx);
+    if sol_amount <= DUST {
+        return skip(SkipReason::TooSmall);
+    }
+    let required = sol_amount + buy_overhead_sol(ctx.holds_token_account, ctx.priority_fee_sol) + settings.min_sol_balance;
+    if ctx.sol_balance < required {
+        return skip(SkipReason::InsufficientSol { balance: ctx.sol_balance, required });
+    }
+    if let Some(cap) = settings.max_token_exposure_sol {
+        if ctx.token_exposure_sol + sol_amount > cap + DUST {
+            return skip(SkipReason::TokenExposureCap { exposure: ctx.token_exposure_sol, cap });
+        }
+    }
+    if let Some(cap) = settings.max_wallet_exposure_sol {
+        let exposure = ctx.positions.iter().fold(0.0, |total, position| total + position.cost_sol);
+        if exposure + sol_amount > cap + DUST {
+            return skip(SkipReason::WalletExposureCap { exposure, cap });
+        }
+    }
+    if let Some(deployable) = ctx.deployable_sol {
+        if sol_amount > deployable + DUST {
+            return skip(SkipReason::MaxDeployed { deployable });
+        }
+    }
+
+    CopyDecision::Buy {
+        token_address: trade.token_address.clone(),
+        sol_amount,
+    }
+}
+
+/// SOL to spend on copying a buy, by the settings' `sizing` and clamped to `min_trade_sol` and
+/// `max_trade_sol`.
+fn buy_size(settings: &CopyTradeSettings, trade: &ParsedTrade, ctx: &EngineContext) -> f64 {
+    let size = match settings.sizing.unwrap_or(Sizing::Fixed) {
+        Sizing::Fixed => settings.trade_amount_sol,
+        Sizing::LeaderPercent { percent } => trade.sol_amount * percent / 100.0,
+        Sizing::BalancePercent { percent } => {
+            let spendable = ctx.sol_balance - settings.min_sol_balance - buy_overhead_sol(ctx.holds_token_account, ctx.priority_fee_sol);
+            spendable.max(0.0) * percent / 100.0
+        }
+        Sizing::SolBalanceRatio { multiplier } if trade.sol_balance_before > DUST => {
+            trade.sol_amount / trade.sol_balance_before * ctx.sol_balance * multiplier
+        }
+        Sizing::SolBalanceRatio { .. } => 0.0,
+    };
+    let size = settings.max_trade_sol.map_or(size, |max| size.min(max));
+    settings.min_trade_sol.map_or(size, |min| size.max(min))
+}
+
+/// Whether the settings skip buys on any token safety check, so the token must be assessed.
+pub fn checks_token_risk(settings: &CopyTradeSettings) -> bool {
+    settings.reject_mint_authority
+        || settings.reject_freeze_authority
+        || settings.max_top_holders_pct.is_some()
+        || settings.min_liquidity_sol.is_some()
+        || settings.min_pool_age_minutes.is_some()
+}
+
+/// The first token safety check of the settings that the token fails, if any.
+fn token_risk_reason(settings: &CopyTradeSettings, risk: &TokenRisk, now: DateTime<Utc>) -> Option<SkipReason> {
+    if settings.reject_mint_authority && risk.mint_authority.is_some() {
+        return Some(SkipReason::MintAuthority);
+    }
+    if settings.reject_freeze_authority && risk.freeze_authority.is_some() {
+        return Some(SkipReason::FreezeAuthority);
+    }
+    if let Some(max) = settings.max_top_holders_pct {
+        if risk.top_holders_pct > max {
+            return Some(SkipReason::HolderConcentration { top_holders_pct: risk.top_holders_pct, max });
+        }
+    }
+    if let Some(min) = settings.min_liquidity_sol {
+        let liquidity_sol = risk.liquidity_sol.unwrap_or_default();
+        if liquidity_sol < min {
+            return Some(SkipReason::LowLiquidity { liquidity_sol, min });
+        }
+    }
+    if let Some(min_minutes) = settings.min_pool_age_minutes {
+        let age_minutes = risk.pool_created_at.map(|created_at| (now - created_at).num_minutes());
+        if age_minutes.is_none_or(|age| age < i64::from(min_minutes)) {
+            return Some(SkipReason::NewPool { age_minutes });
+        }
+    }
+    None
+}
+
+/// Why a buy would come too soon after the trades already copied from the wallet, if it would.
+/// Sells are never held back: skipping one would keep a position the wallet is leaving.
+fn pace(settings: &CopyTradeSettings, trade: &ParsedTrade, ctx: &EngineContext) -> Option<SkipReason> {
+    let copied: Vec<&Transaction> = ctx.history.iter()
+        .filter(|transaction| transaction.tracked_wallet_id == Some(settings.tracked_wallet_id))
+        .collect();
+
+    if let Some(min_secs) = settings.min_seconds_between_trades {
+        if let Some(last) = copied.iter().map(|transaction| transaction.timestamp).max() {
+            let seconds_since_last = (ctx.now - last).num_seconds();
+            if seconds_since_last < i64::from(min_secs) {
+                return Some(SkipReason::Cooldown { seconds_since_last });
+            }
+        }
+    }
+    if let Some(max_trades) = settings.max_trades_per_hour {
+        let trades = copied.iter()
+            .filter(|transaction| transaction.timestamp > ctx.now - Duration::hours(1))
+            .count();
+        if trades >= max_trades.max(0) as usize {
+            return Some(SkipReason::HourlyTradeLimit { trades });
+        }
+    }
+    if let Some(minutes) = settings.rebuy_cooldown_minutes {
+        let last_sell = copied.iter()
+            .filter(|transaction| transaction.token_address == trade.token_address && is_sell(&transaction.transaction_type))
+            .map(|transaction| transaction.timestamp)
+            .max();
+        if let Some(last_sell) = last_sell {
+            let minutes_since_sell = (ctx.now - last_sell).num_minutes();
+            if ctx.now - last_sell < Duration::minutes(i64::from(minutes)) {
+                return Some(SkipReason::RebuyCooldown { minutes_since_sell });
+            }
+        }
+    }
+    None
+}
+
+fn decide_sell(settings: &CopyTradeSettings, trade: &ParsedTrade, ctx: &EngineContext, position: Option<&Position>) -> CopyDecision {
+    let Some(position) = position else {
+        return skip(SkipReason::NoPosition);
+    };
+
+    // Without `match_sell_percentage` any sell by the wallet closes the position.
+    let fraction = if settings.match_sell_percentage {
+        let bag = leader_holding_before(ctx.leader_holding, trade);
+        if bag > DUST { (trade.token_amount / bag).min(1.0) } else { 1.0 }
+    } else {
+        1.0
+    };
+
+    CopyDecision::Sell {
+        token_address: trade.token_address.clone(),
+        token_amount: position.amount * fraction,
+    }
+}
+
+/// Why the position watcher closed a position. Exits are recorded like sells, with this as their
+/// `transaction_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    TakeProfit,
+    StopLoss,
+    TrailingStop,
+}
+
+impl ExitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExitReason::TakeProfit => "take_profit",
+            ExitReason::StopLoss => "stop_loss",
+            ExitReason::TrailingStop => "trailing_stop",
+        }
+    }
+}
+
+/// Whether a recorded `transaction_type` sold tokens: a copied sell or an exit.
+pub fn is_sell(transaction_type: &str) -> bool {
+    matches!(transaction_type, "sell" | "take_profit" | "stop_loss" | "trailing_stop")
+}
+
+/// Rejects exit thresholds that would exit at once or never: each must be positive, and a stop
+/// can fall at most 100%.
+pub fn validate_exits(take_profit_pct: Option<f64>, stop_loss_pct: Option<f64>, trailing_stop_pct: Option<f64>) -> Result<(), AppError> {
+    let outside = |pct: Option<f64>, max: f64| pct.is_some_and(|pct| !(pct > 0.0 && pct <= max));
+    if outside(take_profit_pct, f64::INFINITY) {
+        return Err(AppError::BadRequest("take_profit_pct must be greater than 0".to_string()));
+    }
+    for (name, pct) in [("stop_loss_pct", stop_loss_pct), ("trailing_stop_pct", trailing_stop_pct)] {
+        if outside(pct, 100.0) {
+            return Err(AppError::BadRequest(format!("{} must be greater than 0 and at most 100", name)));
+        }
+    }
+    Ok(())
+}
+
+/// The exit due for `position` at `price_sol` per token, if any. `peak_price` is the highest
+/// price seen since the position was opened.
+pub fn exit_due(settings: &CopyTradeSettings, position: &Position, price_sol: f64, peak_price: f64) -> Option<ExitReason> {
+    if position.amount <= DUST || position.cost_sol <= 0.0 {
+        return None;
+    }
+    let entry_price = position.cost_sol / position.amount;
+    let change_pct = (price_sol / entry_price - 1.0) * 100.0;
+
+    if settings.stop_loss_pct.is_some_and(|pct| change_pct <= -pct) {
+        return Some(ExitReason::StopLoss);
+    }
+    let peak_price = peak_price.max(entry_price);
+    if settings.trailing_stop_pct.is_some_and(|pct| price_sol <= peak_price * (1.0 - pct / 100.0)) {
+        return Some(ExitReason::TrailingStop);
+    }
+    if settings.take_profit_pct.is_some_and(|pct| change_pct >= pct) {
+        return Some(ExitReason::TakeProfit);
+    }
+    None
+}
+
+/// SOL a buy needs besides the amount swapped: the network fee and `priority_fee_sol`, the rent
+/// of the temporary wrapped SOL account (returned when it is closed, but needed up front) and,
+/// unless the wallet already has one, the rent of a token account for the bought token.
+pub fn buy_overhead_sol(holds_token_account: bool, priority_fee_sol: f64) -> f64 {
+    let token_account_rent = if holds_token_account { 0.0 } else { TOKEN_ACCOUNT_RENT_SOL };
+    SIGNATURE_FEE_SOL + priority_fee_sol + TOKEN_ACCOUNT_RENT_SOL + token_account_rent
+}
+
+/// The wallet's holding of the traded token before the trade. A transaction only lists the token
+/// accounts it touched, so when the wallet holds the token in several accounts its balance in
+/// the transaction understates the bag; the tracked holding covers what earlier trades added.
+pub fn leader_holding_before(tracked: Option<f64>, trade: &ParsedTrade) -> f64 {
+    tracked.unwrap_or_default().max(trade.token_balance_before)
+}
+
+/// The wallet's holding of the traded token once the trade is applied.
+pub fn leader_holding_after(tracked: Option<f64>, trade: &ParsedTrade) -> f64 {
+    let delta = trade.token_balance_after - trade.token_balance_before;
+    (leader_holding_before(tracked, trade) + delta).max(0.0)
+}
+
+/// A buy/sell ledger of one token copied from one tracked wallet.
+#[derive(Debug, Clone, Default)]
+struct Ledger {
+    buys: usize,
+    sells: usize,
+    amount: f64,
+    cost_sol: f64,
+    realized_pnl_sol: f64,
+    /// Profit of each sell, at the time it was made.
+    realized: Vec<(DateTime<Utc>, f64)>,
+}
+
+/// Replays recorded buys and sells in time order, per tracked wallet and token.
+fn replay(transactions: &[Transaction]) -> BTreeMap<(Option<Uuid>, String), Ledger> {
+    let mut sorted: Vec<&Transaction> = transactions.iter().collect();
+    sorted.sort_by_key(|transaction| transaction.timestamp);
+
+    let mut ledgers: BTreeMap<(Option<Uuid>, String), Ledger> = BTreeMap::new();
+    for transaction in sorted {
+        let ledger = ledgers
+            .entry((transaction.tracked_wallet_id, transaction.token_address.clone()))
+            .or_default();
+
+        match transaction.transaction_type.as_str() {
+            "buy" => {
+                ledger.buys += 1;
+                ledger.amount += transaction.amount;
+                ledger.cost_sol += transaction.amount * transaction.price_sol;
+            }
+            kind if is_sell(kind) && ledger.amount > DUST => {
+                let sold = transaction.amount.min(ledger.amount);
+                let cost = ledger.cost_sol * sold / ledger.amount;
+                let profit = sold * transaction.price_sol - cost;
+                ledger.sells += 1;
+                ledger.realized_pnl_sol += profit;
+                ledger.realized.push((transaction.timestamp, profit));
+                ledger.cost_sol -= cost;
+                ledger.amount -= sold;
+            }
+            _ => {}
+        }
+    }
+    ledgers
+}
+
+/// Open positions per tracked wallet, replayed from the buys, sells and exits recorded.
+pub fn open_positions(transactions: &[Transaction]) -> BTreeMap<Option<Uuid>, Vec<Position>> {
+    let mut positions: BTreeMap<Option<Uuid>, Vec<Position>> = BTreeMap::new();
+    for ((tracked_wallet_id, token_address), ledger) in replay(transactions) {
+        if ledger.amount > DUST {
+            positions.entry(tracked_wallet_id).or_default().push(Position {
+                token_address,
+                amount: ledger.amount,
+                cost_sol: ledger.cost_sol,
+            });
+        }
+    }
+    positions
+}
+
+/// Realized profit of the sells made at or after `since`, across wallets and tokens.
+pub fn realized_pnl_since(transactions: &[Transaction], since: DateTime<Utc>) -> f64 {
+    replay(transactions)
+        .values()
+        .flat_map(|ledger| &ledger.realized)
+        .filter(|(timestamp, _)| *timestamp >= since)
+        .fold(0.0, |total, (_, profit)| total + profit)
+}
+
+/// SOL paid for the tokens of the open positions in `token_address`, across tracked wallets.
+pub fn token_exposure_sol(positions: &BTreeMap<Option<Uuid>, Vec<Position>>, token_address: &str) -> f64 {
+    positions.values()
+        .flatten()
+        .filter(|position| position.token_address == token_address)
+        .fold(0.0, |total, position| total + position.cost_sol)
+}
+
+/// SOL paid for the tokens of every open position.
+pub fn deployed_sol(transactions: &[Transaction]) -> f64 {
+    replay(transactions).values().fold(0.0, |total, ledger| total + ledger.cost_sol)
+}
+
+/// Profit and loss per tracked wallet and token.
+pub fn pnl(transactions: &[Transaction]) -> Vec<TokenPnl> {
+    replay(transactions)
+        .into_iter()
+        .map(|((tracked_wallet_id, token_address), ledger)| TokenPnl {
+            tracked_wallet_id,
+            token_address,
+            buys: ledger.buys,
+            sells: ledger.sells,
+            open_amount: ledger.amount,
+            open_cost_sol: ledger.cost_sol,
+            realized_pnl_sol: ledger.realized_pnl_sol,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ScheduleWindow;
+    use crate::testing::{self, settings};
+
+    const TOKEN: &str = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R";
+
+    fn trade(kind: TradeKind, token_amount: f64, balance_before: f64) -> ParsedTrade {
+        ParsedTrade {
+            token_balance_before: balance_before,
+            token_balance_after: balance_before + if kind == TradeKind::Sell { -token_amount } else { token_amount },
+            ..testing::trade(kind, TOKEN, token_amount, 1.0)
+        }
+    }
+
+    fn position(token_address: &str, amount: f64) -> Position {
+        Position { token_address: token_address.to_string(), amount, cost_sol: 0.1 }
+    }
+
+    fn context(positions: &[Position], sol_balance: f64) -> EngineContext<'_> {
+        EngineContext {
+            positions,
+            sol_balance,
+            leader_holding: None,
+            holds_token_account: false,
+            priority_fee_sol: 0.0,
+            halted: false,
+            deployable_sol: None,
+            token_exposure_sol: 0.0,
+            history: &[],
+            now: Utc::now(),
+            token_risk: None,
+        }
+    }
+
+    fn decide_with(settings: &CopyTradeSettings, trade: &ParsedTrade, positions: &[Position], sol_balance: f64) -> CopyDecision {
+        decide(settings, trade, &context(positions, sol_balance))
+    }
+
+    #[test]
+    fn copies_buy_with_configured_amount() {
+        let decision = decide_with(&settings(), &trade(TradeKind::Buy, 1000.0, 0.0), &[], 1.0);
+        assert_eq!(decision, CopyDecision::Buy { token_address: TOKEN.to_string(), sol_amount: 0.1 });
+    }
+
+    #[test]
+    fn skips_when_disabled() {
+        let settings = CopyTradeSettings { is_enabled: false, ..settings() };
+        let decision = decide_with(&settings, &trade(TradeKind::Buy, 1000.0, 0.0), &[], 1.0);
+        assert_eq!(decision, skip(SkipReason::Disabled));
+    }
+
+    #[test]
+    fn skips_buys_outside_schedule() {
+        let window = ScheduleWindow { days: vec![], start: "09:00".to_string(), end: "17:00".to_string() };
+        let settings = CopyTradeSettings { schedule: Some(vec![window]), ..settings() };
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let sell = trade(TradeKind::Sell, 1000.0, 1000.0);
+        let positions = [position(TOKEN, 1000.0)];
+
+        let evening = EngineContext { now: "2026-03-02T18:00:00Z".parse().unwrap(), ..context(&[], 1.0) };
+        assert_eq!(decide(&settings, &buy, &evening), skip(SkipReason::OutsideSchedule));
+        let morning = EngineContext { now: "2026-03-02T10:00:00Z".parse().unwrap(), ..context(&[], 1.0) };
+        assert!(matches!(decide(&settings, &buy, &morning), CopyDecision::Buy { .. }));
+
+        // The position still closes when the wallet sells after hours.
+        let evening = EngineContext { now: "2026-03-02T18:00:00Z".parse().unwrap(), ..context(&positions, 1.0) };
+        assert!(matches!(decide(&settings, &sell, &evening), CopyDecision::Sell { .. }));
+    }
+
+    #[test]
+    fn skips_transfers_and_token_for_token_swaps() {
+        let transfer = trade(TradeKind::Transfer, 1000.0, 0.0);
+        assert_eq!(decide_with(&settings(), &transfer, &[], 1.0), skip(SkipReason::NotATrade));
+
+        let swap = ParsedTrade { price_sol: None, ..trade(TradeKind::Swap, 1000.0, 0.0) };
+        assert_eq!(decide_with(&settings(), &swap, &[], 1.0), skip(SkipReason::NoSolLeg));
+    }
+
+    #[test]
+    fn enforces_allowed_tokens_list() {
+        let mut settings = CopyTradeSettings { use_allowed_tokens_list: true, ..settings() };
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        assert_eq!(decide_with(&settings, &buy, &[], 1.0), skip(SkipReason::TokenNotAllowed));
+
+        settings.allowed_tokens = Some(vec![TOKEN.to_string()]);
+        assert!(matches!(decide_with(&settings, &buy, &[], 1.0), CopyDecision::Buy { .. }));
+    }
+
+    #[test]
+    fn additional_buys_need_to_be_allowed() {
+        let positions = [position(TOKEN, 500.0)];
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        assert_eq!(decide_with(&settings(), &buy, &positions, 1.0), skip(SkipReason::AlreadyHolding));
+
+        let settings = CopyTradeSettings { allow_additional_buys: true, max_open_positions: 1, ..settings() };
+        assert!(matches!(decide_with(&settings, &buy, &positions, 1.0), CopyDecision::Buy { .. }));
+    }
+
+    #[test]
+    fn caps_open_positions() {
+        let positions = [position("a", 1.0), position("b", 1.0)];
+        let decision = decide_with(&settings(), &trade(TradeKind::Buy, 1000.0, 0.0), &positions, 1.0);
+        assert_eq!(decision, skip(SkipReason::MaxOpenPositions { open: 2 }));
+    }
+
+    #[test]
+    fn keeps_min_sol_balance() {
+        let decision = decide_with(&settings(), &trade(TradeKind::Buy, 1000.0, 0.0), &[], 0.12);
+        assert!(matches!(decision, CopyDecision::Skip { reason: SkipReason::InsufficientSol { .. } }));
+    }
+
+    #[test]
+    fn min_sol_balance_covers_fees_and_rent() {
+        // 0.1 SOL to swap and 0.05 to keep is not enough once fees and rent are paid.
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let required = 0.15 + 0.000_005 + 2.0 * TOKEN_ACCOUNT_RENT_SOL;
+        assert_eq!(
+            decide_with(&settings(), &buy, &[], 0.15),
+            skip(SkipReason::InsufficientSol { balance: 0.15, required }),
+        );
+
+        // No token account rent when the wallet already has one.
+        let ctx = EngineContext { holds_token_account: true, ..context(&[], 0.153) };
+        assert!(matches!(decide(&settings(), &buy, &ctx), CopyDecision::Buy { .. }));
+        assert!(matches!(decide_with(&settings(), &buy, &[], 0.153), CopyDecision::Skip { .. }));
+
+        // Priority fees are paid on top.
+        let ctx = EngineContext { priority_fee_sol: 0.01, ..ctx };
+        assert!(matches!(decide(&settings(), &buy, &ctx), CopyDecision::Skip { .. }));
+    }
+
+    #[test]
+    fn sell_closes_position_unless_matching_percentage() {
+        let positions = [position(TOKEN, 500.0)];
+        let sell = trade(TradeKind::Sell, 400.0, 1000.0);
+        assert_eq!(
+            decide_with(&settings(), &sell, &positions, 0.0),
+            CopyDecision::Sell { token_address: TOKEN.to_string(), token_amount: 500.0 },
+        );
+
+        let settings = CopyTradeSettings { match_sell_percentage: true, ..settings() };
+        assert_eq!(
+            decide_with(&settings, &sell, &positions, 0.0),
+            CopyDecision::Sell { token_address: TOKEN.to_string(), token_amount: 200.0 },
+        );
+    }
+
+    #[test]
+    fn sell_fraction_uses_tracked_holding() {
+        // The transaction shows 1000 tokens before the sell, but earlier trades put 2000 in
+        // another of the wallet's accounts, so the 400 sold are 400 / 3000 of the bag.
+        let settings = CopyTradeSettings { match_sell_percentage: true, ..settings() };
+        let positions = [position(TOKEN, 300.0)];
+        let sell = trade(TradeKind::Sell, 400.0, 1000.0);
+        let ctx = EngineContext { leader_holding: Some(3000.0), holds_token_account: true, ..context(&positions, 0.0) };
+        assert_eq!(
+            decide(&settings, &sell, &ctx),
+            CopyDecision::Sell { token_address: TOKEN.to_string(), token_amount: 40.0 },
+        );
+        assert_eq!(leader_holding_after(Some(3000.0), &sell), 2600.0);
+        assert_eq!(leader_holding_after(None, &sell), 600.0);
+    }
+
+    #[test]
+    fn skips_sell_without_position() {
+        let decision = decide_with(&settings(), &trade(TradeKind::Sell, 400.0, 1000.0), &[], 1.0);
+        assert_eq!(decision, skip(SkipReason::NoPosition));
+    }
+
+    #[test]
+    fn exits_on_thresholds() {
+        // 1000 tokens bought for 0.1 SOL: an entry price of 0.0001.
+        let held = Position { token_address: TOKEN.to_string(), amount: 1000.0, cost_sol: 0.1 };
+        let exits = CopyTradeSettings {
+            take_profit_pct: Some(100.0),
+            stop_loss_pct: Some(30.0),
+            trailing_stop_pct: Some(20.0),
+            ..settings()
+        };
+
+        assert_eq!(exit_due(&exits, &held, 0.00011, 0.00011), None);
+        assert_eq!(exit_due(&exits, &held, 0.0002, 0.0002), Some(ExitReason::TakeProfit));
+        assert_eq!(exit_due(&exits, &held, 0.00007, 0.0001), Some(ExitReason::StopLoss));
+        // Over 20% off the 0.00015 peak, though still above entry.
+        assert_eq!(exit_due(&exits, &held, 0.000119, 0.00015), Some(ExitReason::TrailingStop));
+        assert_eq!(exit_due(&settings(), &held, 0.00001, 0.00015), None);
+    }
+
+    #[test]
+    fn rejects_exits_that_fire_at_once_or_never() {
+        assert!(validate_exits(Some(500.0), Some(30.0), Some(100.0)).is_ok());
+        assert!(validate_exits(None, None, None).is_ok());
+        assert!(matches!(validate_exits(None, Some(-10.0), None), Err(AppError::BadRequest(_))));
+        assert!(validate_exits(Some(0.0), None, None).is_err());
+        assert!(validate_exits(None, None, Some(120.0)).is_err());
+        assert!(validate_exits(None, Some(f64::NAN), None).is_err());
+    }
+
+    #[test]
+    fn respects_account_limits() {
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let mut ctx = EngineContext { halted: true, ..context(&[], 1.0) };
+        assert_eq!(decide(&settings(), &buy, &ctx), skip(SkipReason::Halted));
+
+        ctx.halted = false;
+        ctx.deployable_sol = Some(0.05);
+        assert_eq!(decide(&settings(), &buy, &ctx), skip(SkipReason::MaxDeployed { deployable: 0.05 }));
+        ctx.deployable_sol = Some(0.1);
+        assert!(matches!(decide(&settings(), &buy, &ctx), CopyDecision::Buy { .. }));
+    }
+
+    #[test]
+    fn caps_exposure() {
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let settings = CopyTradeSettings {
+            allow_additional_buys: true,
+            max_token_exposure_sol: Some(0.25),
+            max_wallet_exposure_sol: Some(0.3),
+            ..settings()
+        };
+
+        // Another wallet's copies already put 0.2 SOL into the token.
+        let ctx = EngineContext { token_exposure_sol: 0.2, ..context(&[], 1.0) };
+        assert_eq!(decide(&settings, &buy, &ctx), skip(SkipReason::TokenExposureCap { exposure: 0.2, cap: 0.25 }));
+
+        // 0.1 + 0.15 SOL copied from this wallet; one more 0.1 SOL buy would make 0.35.
+        let positions = [position(TOKEN, 1000.0), Position { cost_sol: 0.15, ..position("other", 10.0) }];
+        let ctx = EngineContext { token_exposure_sol: 0.1, ..context(&positions, 1.0) };
+        assert_eq!(decide(&settings, &buy, &ctx), skip(SkipReason::WalletExposureCap { exposure: 0.25, cap: 0.3 }));
+    }
+
+    #[test]
+    fn paces_buys() {
+        let now = Utc::now();
+        let copied = |kind: &str, token: &str, minutes_ago: i64| Transaction {
+            id: Uuid::new_v4(),
+            user_id: "user".to_string(),
+            tracked_wallet_id: Some(Uuid::nil()),
+            signature: "sig".to_string(),
+            transaction_type: kind.to_string(),
+            token_address: token.to_string(),
+            amount: 1000.0,
+            price_sol: 0.0001,
+            timestamp: now - Duration::minutes(minutes_ago),
+        };
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let history = [copied("buy", TOKEN, 50), copied("sell", TOKEN, 20), copied("buy", "other", 1)];
+        let ctx = EngineContext { history: &history, now, ..context(&[], 1.0) };
+
+        let paced = CopyTradeSettings { min_seconds_between_trades: Some(120), ..settings() };
+        assert_eq!(decide(&paced, &buy, &ctx), skip(SkipReason::Cooldown { seconds_since_last: 60 }));
+
+        let limited = CopyTradeSettings { max_trades_per_hour: Some(3), ..settings() };
+        assert_eq!(decide(&limited, &buy, &ctx), skip(SkipReason::HourlyTradeLimit { trades: 3 }));
+
+        let cooling = CopyTradeSettings { rebuy_cooldown_minutes: Some(30), ..settings() };
+        assert_eq!(decide(&cooling, &buy, &ctx), skip(SkipReason::RebuyCooldown { minutes_since_sell: 20 }));
+        let cooled = CopyTradeSettings { rebuy_cooldown_minutes: Some(15), ..settings() };
+        assert!(matches!(decide(&cooled, &buy, &ctx), CopyDecision::Buy { .. }));
+
+        // Sells are copied regardless.
+        let positions = [position(TOKEN, 300.0)];
+        let ctx = EngineContext { history: &history, now, ..context(&positions, 1.0) };
+        assert!(matches!(decide(&paced, &trade(TradeKind::Sell, 400.0, 1000.0), &ctx), CopyDecision::Sell { .. }));
+    }
+
+    #[test]
+    fn sizes_buys() {
+        // The wallet spends 1 of its 10 SOL.
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let sized = |sizing: Sizing, min: Option<f64>, max: Option<f64>, sol_balance: f64| {
+            let settings = CopyTradeSettings { sizing: Some(sizing), min_trade_sol: min, max_trade_sol: max, ..settings() };
+            match decide_with(&settings, &buy, &[], sol_balance) {
+                CopyDecision::Buy { sol_amount, .. } => sol_amount,
+                decision => panic!("expected a buy, got {:?}", decision),
+            }
+        };
+
+        assert!((sized(Sizing::LeaderPercent { percent: 25.0 }, None, None, 2.0) - 0.25).abs() < 1e-9);
+        let spendable = 2.0 - 0.05 - buy_overhead_sol(false, 0.0);
+        assert!((sized(Sizing::BalancePercent { percent: 50.0 }, None, None, 2.0) - spendable / 2.0).abs() < 1e-9);
+        assert!((sized(Sizing::SolBalanceRatio { multiplier: 2.0 }, None, None, 2.0) - 0.4).abs() < 1e-9);
+        assert!((sized(Sizing::LeaderPercent { percent: 100.0 }, None, Some(0.5), 2.0) - 0.5).abs() < 1e-9);
+        assert!((sized(Sizing::LeaderPercent { percent: 1.0 }, Some(0.05), None, 2.0) - 0.05).abs() < 1e-9);
+
+        let settings = CopyTradeSettings { sizing: Some(Sizing::BalancePercent { percent: 50.0 }), ..settings() };
+        assert_eq!(decide_with(&settings, &buy, &[], 0.01), skip(SkipReason::TooSmall));
+    }
+
+    #[test]
+    fn skips_risky_tokens() {
+        let now = Utc::now();
+        let risk = TokenRisk {
+            mint: TOKEN.to_string(),
+            mint_authority: None,
+            freeze_authority: Some("authority".to_string()),
+            supply: 1_000_000.0,
+            decimals: 6,
+            top_holders_pct: 45.0,
+            liquidity_sol: Some(20.0),
+            pool_created_at: Some(now - Duration::minutes(30)),
+            assessed_at: now,
+        };
+        let buy = trade(TradeKind::Buy, 1000.0, 0.0);
+        let ctx = EngineContext { token_risk: Some(&risk), now, ..context(&[], 1.0) };
+        let checked = |settings: CopyTradeSettings| decide(&settings, &buy, &ctx);
+
+        assert!(matches!(checked(CopyTradeSettings { reject_mint_authority: true, ..settings() }), CopyDecision::Buy { .. }));
+        assert_eq!(checked(CopyTradeSettings { reject_freeze_authority: true, ..settings() }), skip(SkipReason::FreezeAuthority));
+        assert_eq!(
+            checked(CopyTradeSettings { max_top_holders_pct: Some(40.0), ..settings() }),
+            skip(SkipReason::HolderConcentration { top_holders_pct: 45.0, max: 40.0 })
+        );
+        assert_eq!(
+            checked(CopyTradeSettings { min_liquidity_sol: Some(50.0), ..settings() }),
+            skip(SkipReason::LowLiquidity { liquidity_sol: 20.0, min: 50.0 })
+        );
+        assert_eq!(checked(CopyTradeSettings { min_pool_age_minutes: Some(60), ..settings() }), skip(SkipReason::NewPool { age_minutes: Some(30) }));
+        assert!(matches!(checked(CopyTradeSettings { min_pool_age_minutes: Some(20), ..settings() }), CopyDecision::Buy { .. }));
+    }
+}
@@ -3,6 +3,7 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use thiserror::Error;
+use tracing::{error, warn};
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -22,6 +23,12 @@ pub enum AppError {
     #[error("Request error: {0}")]
     RequestError(String),
 
+    #[error("Solana RPC error: {0}")]
+    RpcError(String),
+
+    #[error("Execution error: {0}")]
+    ExecutionError(String),
+
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
@@ -31,8 +38,8 @@ pub enum AppError {
     #[error("Port parse error: {0}")]
     PortParseError(#[from] std::num::ParseIntError),
 
-    // #[error("Not found: {0}")]
-    // NotFound(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
     
     // #[error("Internal server error")]
     // InternalServerError,
@@ -46,13 +53,21 @@ impl IntoResponse for AppError {
             AppError::PostgrestError(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
             AppError::JsonParseError(message) => (StatusCode::BAD_REQUEST, message),
             AppError::RequestError(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::RpcError(message) => (StatusCode::BAD_GATEWAY, message),
+            AppError::ExecutionError(message) => (StatusCode::BAD_GATEWAY, message),
             AppError::ConfigError(message) => (StatusCode::BAD_REQUEST, message),
             AppError::ServerError(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
             AppError::PortParseError(err) => (StatusCode::BAD_REQUEST, err.to_string()),
-            // AppError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            AppError::NotFound(message) => (StatusCode::NOT_FOUND, message),
             // AppError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
         };
 
+        if status.is_server_error() {
+            error!(%status, error = %error_message, "Request failed");
+        } else {
+            warn!(%status, error = %error_message, "Request rejected");
+        }
+
         (status, error_message).into_response()
     }
 }
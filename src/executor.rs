@@ -0,0 +1,656 @@
+//! Executes copy decisions as swaps through a Jupiter-compatible quote/swap API, with the compute
+//! budget of the wallet's settings, submitted over RPC or as a bundle, then records the fill in
+//! `transactions`.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::balances::BalanceService;
+use crate::bundles::BundleSender;
+use crate::engine::{CopyDecision, ExitReason};
+use crate::error::AppError;
+use crate::ledger::{Ledger, TradeLock};
+use crate::models::{CopyTradeSettings, PriorityFee, Submission, Transaction};
+use crate::parser::{self, TradeKind, LAMPORTS_PER_SOL, WSOL_MINT};
+use crate::policy::{self, MAX_TRANSACTION_UNITS};
+use crate::risk::RiskGuard;
+use crate::rpc::{SignatureStatus, SolanaRpc};
+use crate::shutdown::BackgroundTasks;
+use crate::signer::Signer;
+
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Lamports per signature.
+const SIGNATURE_FEE: u64 = 5_000;
+
+#[derive(Debug, Clone)]
+pub struct ExecutorSettings {
+    /// Base URL of the aggregator; `/quote` and `/swap` are appended.
+    pub swap_api_url: String,
+    pub confirm_timeout: Duration,
+    /// Used for wallets whose settings do not set their own.
+    pub compute_budget: ComputeBudget,
+    /// JSON-RPC endpoint of the block engine bundles are sent to.
+    pub block_engine_url: String,
+    pub tip_accounts: Vec<String>,
+    /// Tip of wallets whose settings do not set their own, in lamports.
+    pub tip_lamports: u64,
+}
+
+/// How the compute unit price and limit of a swap are set.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ComputeBudget {
+    /// `None` pays no priority fee.
+    pub priority_fee: Option<PriorityFee>,
+    /// Highest compute unit price, in micro-lamports.
+    pub max_micro_lamports: Option<u64>,
+    /// `None` requests the units the swap API simulates the swap to need.
+    pub unit_limit: Option<u32>,
+}
+
+impl ComputeBudget {
+    /// This budget with what `settings` set in its place.
+    pub fn with_settings(&self, settings: &CopyTradeSettings) -> ComputeBudget {
+        ComputeBudget {
+            priority_fee: settings.priority_fee.or(self.priority_fee),
+            max_micro_lamports: settings.max_priority_fee_micro_lamports
+                .and_then(|max| u64::try_from(max).ok())
+                .or(self.max_micro_lamports),
+            unit_limit: settings.compute_unit_limit
+                .and_then(|limit| u32::try_from(limit).ok())
+                .or(self.unit_limit),
+        }
+    }
+
+    /// The most a swap can pay in priority fees with this budget, in SOL. Prices from recent fees
+    /// are only bounded by `max_micro_lamports`, which `validate_priority_fee` and the config
+    /// require along with them.
+    pub fn max_fee_sol(&self) -> f64 {
+        let unit_price = match self.priority_fee {
+            Some(PriorityFee::Fixed { micro_lamports }) => micro_lamports.min(self.max_micro_lamports.unwrap_or(u64::MAX)),
+            Some(PriorityFee::Percentile { .. }) => self.max_micro_lamports.unwrap_or_default(),
+            None => 0,
+        };
+        let units = self.unit_limit.map_or(MAX_TRANSACTION_UNITS, u64::from);
+        (unit_price as f64 * units as f64 / 1_000_000.0) / LAMPORTS_PER_SOL
+    }
+}
+
+/// Rejects a priority fee taken from recent fees that neither `max_micro_lamports` nor the cap of
+/// `defaults`, which settings fall back to, bounds: nothing would bound what a swap pays, nor what
+/// a buy has to leave for it.
+pub fn validate_priority_fee(priority_fee: Option<&PriorityFee>, max_micro_lamports: Option<i64>, defaults: &ComputeBudget) -> Result<(), AppError> {
+    if max_micro_lamports.is_some_and(|max| max < 0) {
+        return Err(AppError::BadRequest("max_priority_fee_micro_lamports must not be negative".to_string()));
+    }
+    match priority_fee {
+        Some(PriorityFee::Percentile { percentile }) if !(0.0..=100.0).contains(percentile) => {
+            Err(AppError::BadRequest("priority_fee percentile must be between 0 and 100".to_string()))
+        }
+        Some(PriorityFee::Percentile { .. }) if max_micro_lamports.is_none() && defaults.max_micro_lamports.is_none() => {
+            Err(AppError::BadRequest(
+                "priority_fee percentile requires max_priority_fee_micro_lamports, here or in the config file".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A submitted swap that was not confirmed in time.
+struct Unconfirmed {
+    order: SwapOrder,
+    quote: Value,
+    signature: String,
+    tracked_wallet_id: Uuid,
+    blockhash: [u8; 32],
+}
+
+/// A swap to make, in the token's smallest units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapOrder {
+    pub kind: TradeKind,
+    pub token_address: String,
+    pub token_decimals: u8,
+    pub input_mint: String,
+    pub output_mint: String,
+    pub amount: u64,
+    /// Set when the order closes a position on an exit threshold rather than copying a trade.
+    pub exit: Option<ExitReason>,
+}
+
+impl SwapOrder {
+    /// The swap that carries out `decision`; `None` for a skip.
+    pub fn from_decision(decision: &CopyDecision, token_decimals: u8) -> Option<SwapOrder> {
+        match decision {
+            CopyDecision::Buy { token_address, sol_amount } => Some(SwapOrder {
+                kind: TradeKind::Buy,
+                token_address: token_address.clone(),
+                token_decimals,
+                input_mint: WSOL_MINT.to_string(),
+                output_mint: token_address.clone(),
+                amount: (sol_amount * LAMPORTS_PER_SOL).round() as u64,
+                exit: None,
+            }),
+            CopyDecision::Sell { token_address, token_amount } => Some(SwapOrder {
+                kind: TradeKind::Sell,
+                token_address: token_address.clone(),
+                token_decimals,
+                input_mint: token_address.clone(),
+                output_mint: WSOL_MINT.to_string(),
+                amount: (token_amount * 10f64.powi(token_decimals as i32)).floor() as u64,
+                exit: None,
+            }),
+            CopyDecision::Skip { .. } => None,
+        }
+    }
+
+    /// The `transaction_type` the fill is recorded with.
+    pub fn transaction_type(&self) -> &'static str {
+        self.exit.map_or(self.kind.as_str(), |reason| reason.as_str())
+    }
+}
+
+#[derive(Clone)]
+pub struct Executor {
+    http: reqwest::Client,
+    settings: ExecutorSettings,
+    rpc: SolanaRpc,
+    bundles: BundleSender,
+    signer: Signer,
+    ledger: Ledger,
+    /// Invalidated after every swap, which changes them even when it fails.
+    balances: BalanceService,
+    /// Checked after every fill, which can breach the daily loss limit.
+    risk: RiskGuard,
+    /// Watches swaps that were not confirmed in time.
+    tasks: BackgroundTasks,
+}
+
+impl Executor {
+    pub fn new(settings: ExecutorSettings, rpc: SolanaRpc, signer: Signer, ledger: Ledger, balances: BalanceService, risk: RiskGuard, tasks: &BackgroundTasks) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            bundles: BundleSender::new(&settings.block_engine_url, settings.tip_accounts.clone()),
+            settings,
+            rpc,
+            signer,
+            ledger,
+            balances,
+            risk,
+            tasks: tasks.clone(),
+        }
+    }
+
+    /// The compute budget swaps for `settings` are made with.
+    pub fn compute_budget(&self, settings: &CopyTradeSettings) -> ComputeBudget {
+        self.settings.compute_budget.with_settings(settings)
+    }
+
+    /// The bundle tip swaps for `settings` pay, in lamports; `None` when they are not bundled.
+    pub fn tip_lamports(&self, settings: &CopyTradeSettings) -> Option<u64> {
+        (settings.submission.unwrap_or_default() == Submission::Bundle).then(|| {
+            settings.bundle_tip_lamports
+                .and_then(|tip| u64::try_from(tip).ok())
+                .unwrap_or(self.settings.tip_lamports)
+        })
+    }
+
+    /// The most a swap for `settings` can pay in priority fees and bundle tips, in SOL.
+    pub fn max_fees_sol(&self, settings: &CopyTradeSettings) -> f64 {
+        // A tip comes in a transaction of its own, with its own signature fee.
+        let tip_sol = self.tip_lamports(settings).map_or(0.0, |tip| (tip + SIGNATURE_FEE) as f64 / LAMPORTS_PER_SOL);
+        self.compute_budget(settings).max_fee_sol() + tip_sol
+    }
+
+    /// Quotes, signs, submits and confirms `order` for the wallet of `settings`, refusing quotes
+    /// worse than its `max_slippage` percent, and records the resulting transaction. A swap not
+    /// confirmed within the confirm timeout fails, but is still recorded if it lands before its
+    /// blockhash expires.
+    ///
+    /// `lock` is released once the fill is recorded or the swap has failed, which for a swap
+    /// still being watched is when it lands or expires.
+    pub async fn execute(&self, order: &SwapOrder, settings: &CopyTradeSettings, lock: TradeLock) -> Result<Transaction, AppError> {
+        let mut lock = Some(lock);
+        let executed = self.swap(order, settings, &mut lock).await;
+        // Before the lock is released, so the next trade is decided on the balances after this.
+        self.balances.invalidate().await;
+        executed
+    }
+
+    /// Takes `lock` if the swap is still being watched when this returns.
+    async fn swap(&self, order: &SwapOrder, settings: &CopyTradeSettings, lock: &mut Option<TradeLock>) -> Result<Transaction, AppError> {
+        if order.amount == 0 {
+            return Err(AppError::ExecutionError("Order amount rounds down to zero".to_string()));
+        }
+        let tracked_wallet_id = settings.tracked_wallet_id;
+        let budget = self.compute_budget(settings);
+
+        let quote = self.quote(order, settings.max_slippage).await?;
+        let unit_price = self.unit_price(&budget, &quote).await?;
+        let unit_limit = budget.unit_limit.map(|limit| limit.min(MAX_TRANSACTION_UNITS as u32));
+        let mut swap_tx = self.swap_transaction(&quote, unit_price, unit_limit).await?;
+        // The swap API is asked for the budget, but what it builds is not taken on trust.
+        policy::set_compute_budget(&mut swap_tx, unit_price, unit_limit)
+            .map_err(|e| AppError::ExecutionError(format!("Cannot set the swap's compute budget: {}", e)))?;
+        let signed = self.signer.sign_transaction(&swap_tx).await?;
+        let signature = match self.tip_lamports(settings) {
+            Some(tip_lamports) => self.bundles.send(&signed, &self.signer, tip_lamports).await?,
+            None => self.rpc.send_transaction(&BASE64.encode(&signed)).await?,
+        };
+        info!(%signature, kind = order.transaction_type(), token = %order.token_address, "Swap submitted");
+
+        if !self.confirm(&signature).await? {
+            let blockhash = policy::recent_blockhash(&signed)
+                .map_err(|e| AppError::ExecutionError(format!("Invalid swap transaction: {}", e)))?;
+            let watch = Unconfirmed { order: order.clone(), quote, signature: signature.clone(), tracked_wallet_id, blockhash };
+            self.tasks.spawn(self.clone().watch_unconfirmed(watch, lock.take()));
+            return Err(AppError::ExecutionError(format!(
+                "Swap {} not confirmed within {:?}", signature, self.settings.confirm_timeout
+            )));
+        }
+
+        self.record_fill(order, &quote, &signature, tracked_wallet_id).await
+    }
+
+    async fn record_fill(&self, order: &SwapOrder, quote: &Value, signature: &str, tracked_wallet_id: Uuid) -> Result<Transaction, AppError> {
+        let transaction = self.filled_transaction(order, quote, signature, tracked_wallet_id).await?;
+        self.ledger.record(&transaction).await;
+        info!(%signature, amount = transaction.amount, price_sol = transaction.price_sol, "Swap confirmed");
+        // Halts copying now rather than when the next trade is copied.
+        if let Err(e) = self.check_losses().await {
+            warn!(error = %e, %signature, "Failed to check the daily loss limit after a swap");
+        }
+        Ok(transaction)
+    }
+
+    async fn check_losses(&self) -> Result<(), AppError> {
+        self.risk.check(&self.ledger.history().await?).await?;
+        Ok(())
+    }
+
+    /// Keeps polling a swap that was not confirmed in time until it lands, fails, or can no
+    /// longer land because its blockhash expired, so a late fill still opens or closes its
+    /// position. `lock` is held until then.
+    async fn watch_unconfirmed(self, swap: Unconfirmed, lock: Option<TradeLock>) {
+        self.watch(swap).await;
+        self.balances.invalidate().await;
+        drop(lock);
+    }
+
+    async fn watch(&self, swap: Unconfirmed) {
+        let Unconfirmed { order, quote, signature, tracked_wallet_id, blockhash } = swap;
+        let blockhash = bs58::encode(blockhash).into_string();
+        let shutdown = self.tasks.shutdown_token();
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    warn!(%signature, "Stopped watching an unconfirmed swap at shutdown; it is not recorded if it lands");
+                    return;
+                }
+                _ = tokio::time::sleep(CONFIRM_POLL_INTERVAL) => {}
+            }
+            // Checked before the status, so a swap landing just before the blockhash expires
+            // is still seen.
+            let expired = match self.rpc.is_blockhash_valid(&blockhash).await {
+                Ok(valid) => !valid,
+                Err(e) => {
+                    debug!(error = %e, %signature, "Could not check the swap's blockhash");
+                    false
+                }
+            };
+            match self.rpc.get_signature_status(&signature).await {
+                Ok(SignatureStatus::Landed(status)) if status != "processed" => {
+                    info!(%signature, "Swap landed after the confirm timeout");
+                    if let Err(e) = self.record_fill(&order, &quote, &signature, tracked_wallet_id).await {
+                        warn!(error = %e, %signature, "Failed to record a late swap");
+                    }
+                    return;
+                }
+                Ok(SignatureStatus::Failed(error)) => {
+                    info!(%signature, %error, "Unconfirmed swap failed");
+                    return;
+                }
+                Ok(_) if expired => {
+                    info!(%signature, "Unconfirmed swap expired without landing");
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => debug!(error = %e, %signature, "Could not check an unconfirmed swap"),
+            }
+        }
+    }
+
+    async fn quote(&self, order: &SwapOrder, max_slippage: f64) -> Result<Value, AppError> {
+        let slippage_bps = (max_slippage * 100.0).round() as u64;
+        let url = format!("{}/quote", self.settings.swap_api_url.trim_end_matches('/'));
+        let resp = self.http
+            .get(&url)
+            .query(&[
+                ("inputMint", order.input_mint.clone()),
+                ("outputMint", order.output_mint.clone()),
+                ("amount", order.amount.to_string()),
+                ("slippageBps", slippage_bps.to_string()),
+            ])
+            .send()
+            .await
+            .map_err(|e| AppError::ExecutionError(format!("Quote request failed: {}", e)))?;
+        let quote = json_body(resp, "Quote").await?;
+
+        // The aggregator may adjust slippage or quote a route that moves the price more than
+        // the settings allow; neither is accepted.
+        let quoted_bps = quote["slippageBps"].as_u64().unwrap_or_default();
+        if quoted_bps > slippage_bps {
+            return Err(AppError::ExecutionError(format!(
+                "Quote slippage {} bps exceeds max_slippage of {} bps", quoted_bps, slippage_bps
+            )));
+        }
+        let price_impact = quote["priceImpactPct"].as_str()
+            .and_then(|impact| impact.parse::<f64>().ok())
+            .unwrap_or_default() * 100.0;
+        if price_impact > max_slippage {
+            return Err(AppError::ExecutionError(format!(
+                "Quote price impact {:.2}% exceeds max_slippage of {}%", price_impact, max_slippage
+            )));
+        }
+
+        debug!(in_amount = %quote["inAmount"], out_amount = %quote["outAmount"], "Quote received");
+        Ok(quote)
+    }
+
+    /// The compute unit price of the swap `quote` is for, in micro-lamports. `None` when the
+    /// budget sets no priority fee.
+    async fn unit_price(&self, budget: &ComputeBudget, quote: &Value) -> Result<Option<u64>, AppError> {
+        let price = match budget.priority_fee {
+            None => return Ok(None),
+            Some(PriorityFee::Fixed { micro_lamports }) => micro_lamports,
+            Some(PriorityFee::Percentile { percentile: p }) => {
+                // Swaps compete for their pools, so fees are read for those.
+                let pools: Vec<String> = quote["routePlan"].as_array().into_iter().flatten()
+                    .filter_map(|step| step["swapInfo"]["ammKey"].as_str().map(str::to_string))
+                    .collect();
+                let fees = self.rpc.get_recent_prioritization_fees(&pools).await?;
+                percentile(fees, p)
+            }
+        };
+        let price = price.min(budget.max_micro_lamports.unwrap_or(u64::MAX));
+        debug!(micro_lamports = price, "Compute unit price set");
+        Ok(Some(price))
+    }
+
+    async fn swap_transaction(&self, quote: &Value, unit_price: Option<u64>, unit_limit: Option<u32>) -> Result<Vec<u8>, AppError> {
+        let url = format!("{}/swap", self.settings.swap_api_url.trim_end_matches('/'));
+        let mut request = json!({
+            "quoteResponse": quote,
+            "userPublicKey": self.signer.pubkey(),
+            "wrapAndUnwrapSol": true,
+            "dynamicComputeUnitLimit": unit_limit.is_none(),
+        });
+        if let Some(unit_price) = unit_price {
+            request["computeUnitPriceMicroLamports"] = json!(unit_price);
+        }
+        let resp = self.http
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(request.to_string())
+            .send()
+            .await
+            .map_err(|e| AppError::ExecutionError(format!("Swap request failed: {}", e)))?;
+        let swap = json_body(resp, "Swap").await?;
+
+        let encoded = swap["swapTransaction"].as_str()
+            .ok_or_else(|| AppError::ExecutionError("Swap response has no swapTransaction".to_string()))?;
+        BASE64.decode(encoded)
+            .map_err(|e| AppError::ExecutionError(format!("Invalid swapTransaction: {}", e)))
+    }
+
+    /// Waits until the transaction is confirmed, failing if it errors on chain. `false` when it
+    /// does not land before the confirm timeout.
+    async fn confirm(&self, signature: &str) -> Result<bool, AppError> {
+        let deadline = Instant::now() + self.settings.confirm_timeout;
+        loop {
+            match self.rpc.get_signature_status(signature).await? {
+                SignatureStatus::Landed(status) if status != "processed" => return Ok(true),
+                SignatureStatus::Failed(error) => {
+                    return Err(AppError::ExecutionError(format!("Swap {} failed: {}", signature, error)));
+                }
+                _ => {}
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep(CONFIRM_POLL_INTERVAL).await;
+        }
+    }
+
+    /// The fill as decoded from the landed transaction, or as quoted if it cannot be decoded.
+    async fn filled_transaction(&self, order: &SwapOrder, quote: &Value, signature: &str, tracked_wallet_id: Uuid) -> Result<Transaction, AppError> {
+        let wallet = self.signer.pubkey();
+        if let Some(tx) = self.rpc.get_transaction(signature).await? {
+            if let Some(fill) = parser::parse_transaction(&tx, &wallet)? {
+                if fill.token_address == order.token_address {
+                    let mut transaction = fill.to_transaction(&wallet, Some(tracked_wallet_id));
+                    transaction.transaction_type = order.transaction_type().to_string();
+                    return Ok(transaction);
+                }
+            }
+        }
+
+        debug!(%signature, "Could not decode the swap; recording the quoted amounts");
+        let raw = |field: &str| quote[field].as_str().and_then(|amount| amount.parse::<f64>().ok()).unwrap_or_default();
+        let token_scale = 10f64.powi(order.token_decimals as i32);
+        let (token_amount, sol_amount) = match order.kind {
+            TradeKind::Buy => (raw("outAmount") / token_scale, raw("inAmount") / LAMPORTS_PER_SOL),
+            _ => (raw("inAmount") / token_scale, raw("outAmount") / LAMPORTS_PER_SOL),
+        };
+
+        Ok(Transaction {
+            id: Uuid::new_v4(),
+            user_id: wallet,
+            tracked_wallet_id: Some(tracked_wallet_id),
+            signature: signature.to_string(),
+            transaction_type: order.transaction_type().to_string(),
+            token_address: order.token_address.clone(),
+            amount: token_amount,
+            price_sol: if token_amount > 0.0 { sol_amount / token_amount } else { 0.0 },
+            timestamp: Utc::now(),
+        })
+    }
+}
+
+/// The `p`th percentile of `fees`, by the nearest-rank method; 0 without any.
+fn percentile(mut fees: Vec<u64>, p: f64) -> u64 {
+    fees.sort_unstable();
+    let rank = (p.clamp(0.0, 100.0) / 100.0 * fees.len() as f64).ceil() as usize;
+    fees.get(rank.saturating_sub(1)).copied().unwrap_or_default()
+}
+
+async fn json_body(resp: reqwest::Response, what: &str) -> Result<Value, AppError> {
+    let status = resp.status();
+    let body = resp.text().await
+        .map_err(|e| AppError::ExecutionError(format!("{} request failed: {}", what, e)))?;
+    if !status.is_success() {
+        return Err(AppError::ExecutionError(format!("{} request failed with status {}: {}", what, status, body)));
+    }
+    serde_json::from_str(&body)
+        .map_err(|e| AppError::JsonParseError(format!("Invalid {} response: {}", what.to_lowercase(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundles;
+    use crate::db::SupabaseClient;
+    use crate::risk::RiskLimits;
+    use crate::testing;
+    use axum::{routing::{get, post}, Json, Router};
+    use ed25519_dalek::SigningKey;
+    use std::sync::{Arc, Mutex};
+
+    const TOKEN: &str = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R";
+    const SIGNATURE: &str = "5wHu1qwD7q5ifaN5nwdcDqNFo53GJqa7nLp2BeeEpcHCusb4GzARz4GjgzsEHMkBMgCJMGa6GSQ5VG8mwvx3ZQkK";
+
+    /// An executor swapping through an aggregator that answers every quote with `quote`, on an
+    /// RPC node that confirms every swap at once, with `history` in `transactions` before, and
+    /// the rows written to it.
+    async fn executor(quote: Value, history: Value, limits: RiskLimits) -> (Executor, Arc<Mutex<Vec<Value>>>) {
+        let signer = Signer::Local(Arc::new(SigningKey::from_bytes(&[9; 32])));
+        // Any transaction stands in for the swap.
+        let swap = bundles::transfer_transaction(&signer.pubkey(), TOKEN, 1, &[7; 32]).unwrap();
+        let aggregator = testing::serve(Router::new()
+            .route("/quote", get(move || { let quote = quote.clone(); async move { Json(quote) } }))
+            .route("/swap", post(move || {
+                let swap = BASE64.encode(&swap);
+                async move { Json(json!({ "swapTransaction": swap })) }
+            }))).await;
+        let rpc = testing::serve(Router::new().route("/", post(|request: String| async move {
+            let request: Value = serde_json::from_str(&request).unwrap();
+            let result = match request["method"].as_str().unwrap() {
+                "sendTransaction" => json!(SIGNATURE),
+                "getSignatureStatuses" => json!({ "value": [{ "err": null, "confirmationStatus": "confirmed" }] }),
+                // Not decodable, so the quoted amounts are recorded.
+                "getTransaction" => Value::Null,
+                method => panic!("unexpected {}", method),
+            };
+            Json(json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+        }))).await;
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let written = rows.clone();
+        let supabase = testing::serve(Router::new()
+            .route("/transactions", get(move || async move { Json(history) }).post(move |row: String| {
+                let row: Value = serde_json::from_str(&row).unwrap();
+                written.lock().unwrap().push(row.clone());
+                async move { Json(json!([row])) }
+            }))
+            .route("/risk_halts", get(|| async { Json(json!([])) }).post(|halt: String| async move {
+                Json(json!([serde_json::from_str::<Value>(&halt).unwrap()]))
+            }))).await;
+
+        let tasks = BackgroundTasks::new();
+        let rpc = SolanaRpc::new(&rpc, "confirmed");
+        let settings = ExecutorSettings {
+            swap_api_url: aggregator,
+            confirm_timeout: Duration::from_secs(5),
+            compute_budget: ComputeBudget::default(),
+            block_engine_url: "http://127.0.0.1:1".to_string(),
+            tip_accounts: Vec::new(),
+            tip_lamports: 0,
+        };
+        let db = SupabaseClient::new(&supabase, "", "", "me");
+        let ledger = Ledger::new(db.clone(), &tasks);
+        let balances = BalanceService::new(rpc.clone(), signer.pubkey());
+        let risk = RiskGuard::load(db, limits).await.unwrap();
+        (Executor::new(settings, rpc, signer, ledger, balances, risk, &tasks), rows)
+    }
+
+    fn buy() -> SwapOrder {
+        let decision = CopyDecision::Buy { token_address: TOKEN.to_string(), sol_amount: 0.1 };
+        SwapOrder::from_decision(&decision, 6).unwrap()
+    }
+
+    async fn execute(executor: &Executor, order: &SwapOrder, settings: &CopyTradeSettings) -> Result<Transaction, AppError> {
+        let lock = executor.ledger.lock(settings.tracked_wallet_id, order.kind == TradeKind::Buy).await;
+        executor.execute(order, settings, lock).await
+    }
+
+    #[test]
+    fn prices_compute_units() {
+        let fees = vec![0, 5_000, 1_000, 20_000, 0, 3_000, 0, 10_000, 2_000, 0];
+        assert_eq!(percentile(fees.clone(), 50.0), 1_000);
+        assert_eq!(percentile(fees.clone(), 75.0), 5_000);
+        assert_eq!(percentile(fees.clone(), 100.0), 20_000);
+        assert_eq!(percentile(fees, 0.0), 0);
+        assert_eq!(percentile(Vec::new(), 75.0), 0);
+
+        let budget = ComputeBudget {
+            priority_fee: Some(PriorityFee::Fixed { micro_lamports: 2_000_000 }),
+            max_micro_lamports: Some(1_000_000),
+            unit_limit: Some(200_000),
+        };
+        // 200_000 units at the 1 lamport cap.
+        assert!((budget.max_fee_sol() - 0.000_2).abs() < 1e-12);
+        let budget = ComputeBudget { priority_fee: Some(PriorityFee::Percentile { percentile: 75.0 }), ..budget };
+        assert!((budget.max_fee_sol() - 0.000_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn requires_a_cap_on_percentile_fees() {
+        let uncapped = ComputeBudget::default();
+        let percentile = PriorityFee::Percentile { percentile: 75.0 };
+        assert!(matches!(validate_priority_fee(Some(&percentile), None, &uncapped), Err(AppError::BadRequest(_))));
+        assert!(validate_priority_fee(Some(&percentile), Some(1_000_000), &uncapped).is_ok());
+        assert!(validate_priority_fee(Some(&PriorityFee::Fixed { micro_lamports: 100_000 }), None, &uncapped).is_ok());
+        assert!(validate_priority_fee(None, None, &uncapped).is_ok());
+
+        // Capped by the config instead.
+        let capped = ComputeBudget { max_micro_lamports: Some(1_000_000), ..uncapped };
+        assert!(validate_priority_fee(Some(&percentile), None, &capped).is_ok());
+
+        let above = PriorityFee::Percentile { percentile: 101.0 };
+        assert!(validate_priority_fee(Some(&above), Some(1_000_000), &uncapped).is_err());
+        assert!(validate_priority_fee(None, Some(-1), &uncapped).is_err());
+    }
+    #[tokio::test]
+    async fn records_the_quoted_fill() {
+        let quote = json!({ "inAmount": "100000000", "outAmount": "1000000000", "slippageBps": 100, "priceImpactPct": "0.005" });
+        let (executor, rows) = executor(quote, json!([]), RiskLimits::default()).await;
+        let settings = testing::settings();
+
+        let transaction = execute(&executor, &buy(), &settings).await.unwrap();
+        assert_eq!(transaction.signature, SIGNATURE);
+        assert_eq!(transaction.transaction_type, "buy");
+        assert_eq!(transaction.token_address, TOKEN);
+        assert_eq!(transaction.tracked_wallet_id, Some(settings.tracked_wallet_id));
+        // 1000 tokens for 0.1 SOL.
+        assert!((transaction.amount - 1000.0).abs() < 1e-9);
+        assert!((transaction.price_sol - 0.0001).abs() < 1e-12);
+
+        let mut row = serde_json::to_value(&transaction).unwrap();
+        // Written for the user the database client acts for.
+        row["user_id"] = json!("me");
+        assert_eq!(*rows.lock().unwrap(), vec![row]);
+        let history = executor.ledger.history().await.unwrap();
+        assert_eq!(history.iter().map(|fill| fill.id).collect::<Vec<_>>(), vec![transaction.id]);
+    }
+
+    #[tokio::test]
+    async fn refuses_quotes_beyond_max_slippage() {
+        let settings = testing::settings();
+        let slipping = json!({ "inAmount": "100000000", "outAmount": "1000000000", "slippageBps": 150, "priceImpactPct": "0" });
+        // 2% against the 1% max_slippage.
+        let moving = json!({ "inAmount": "100000000", "outAmount": "1000000000", "slippageBps": 50, "priceImpactPct": "0.02" });
+        for (quote, refused) in [(slipping, "slippage 150 bps"), (moving, "price impact 2.00%")] {
+            let (executor, rows) = executor(quote, json!([]), RiskLimits::default()).await;
+            assert!(matches!(execute(&executor, &buy(), &settings).await, Err(AppError::ExecutionError(e)) if e.contains(refused)));
+            assert!(rows.lock().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn halts_on_a_fill_breaching_the_daily_loss_limit() {
+        let settings = testing::settings();
+        let bought = json!([{
+            "id": Uuid::new_v4(),
+            "user_id": "me",
+            "tracked_wallet_id": settings.tracked_wallet_id,
+            "signature": "bought",
+            "transaction_type": "buy",
+            "token_address": TOKEN,
+            "amount": 1000.0,
+            "price_sol": 0.0002,
+            "timestamp": Utc::now(),
+        }]);
+        // Sells the 1000 tokens bought for 0.2 SOL for 0.1.
+        let quote = json!({ "inAmount": "1000000000", "outAmount": "100000000", "slippageBps": 100, "priceImpactPct": "0" });
+        let limits = RiskLimits { max_daily_loss_sol: Some(0.05), max_deployed_sol: None };
+        let (executor, _) = executor(quote, bought, limits).await;
+
+        let decision = CopyDecision::Sell { token_address: TOKEN.to_string(), token_amount: 1000.0 };
+        let sell = SwapOrder::from_decision(&decision, 6).unwrap();
+        execute(&executor, &sell, &settings).await.unwrap();
+        assert!(executor.risk.active_halt().is_some());
+    }
+}
@@ -0,0 +1,220 @@
+//! Encrypted-at-rest storage of the trading wallet's keypair. The keypair is encrypted with
+//! AES-256-GCM under a key derived from a password with Argon2id, or under a key-encryption key
+//! given directly, and only ever leaves the keystore as a `Signer`.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, OsRng, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, KeyInit, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::error::AppError;
+use crate::signer::{self, Signer};
+
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+
+/// What unlocks the keystore.
+pub enum KeystoreSecret {
+    Password(Zeroizing<String>),
+    /// A 32 byte key-encryption key, used as the AES key as is.
+    Key(Zeroizing<[u8; 32]>),
+}
+
+impl KeystoreSecret {
+    /// `WALLET_KEYSTORE_KEY` (base64 of 32 bytes) if set, else `WALLET_KEYSTORE_PASSWORD`.
+    pub fn from_env() -> Result<KeystoreSecret, AppError> {
+        if let Ok(encoded) = env::var("WALLET_KEYSTORE_KEY") {
+            let bytes = Zeroizing::new(BASE64.decode(encoded.trim())
+                .map_err(|_| AppError::ConfigError("WALLET_KEYSTORE_KEY is not base64".to_string()))?);
+            let mut key = Zeroizing::new([0; 32]);
+            if bytes.len() != key.len() {
+                return Err(AppError::ConfigError(format!("WALLET_KEYSTORE_KEY must be 32 bytes, got {}", bytes.len())));
+            }
+            key.copy_from_slice(&bytes);
+            return Ok(KeystoreSecret::Key(key));
+        }
+        match env::var("WALLET_KEYSTORE_PASSWORD") {
+            Ok(password) if !password.is_empty() => Ok(KeystoreSecret::Password(Zeroizing::new(password))),
+            _ => Err(AppError::ConfigError(
+                "WALLET_KEYSTORE_PASSWORD or WALLET_KEYSTORE_KEY must be set to unlock the keystore".to_string(),
+            )),
+        }
+    }
+}
+
+/// How the AES key is obtained from the secret.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+enum Kdf {
+    Argon2id { salt: String, m_cost: u32, t_cost: u32, p_cost: u32 },
+    /// The secret is the key.
+    Kek,
+}
+
+/// The keystore file. The address is stored in the clear so the file can be identified, and is
+/// authenticated along with the ciphertext.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Keystore {
+    version: u8,
+    pub address: String,
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    /// Encrypts `keypair` (in any format `Signer::from_keypair` accepts) under `secret`.
+    pub fn encrypt(keypair: &str, secret: &KeystoreSecret) -> Result<Keystore, AppError> {
+        let keypair = signer::parse_keypair(keypair)?;
+        let address = Signer::from_keypair_bytes(&keypair)?.pubkey();
+
+        let kdf = match secret {
+            KeystoreSecret::Password(_) => {
+                let mut salt = [0; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let params = Params::default();
+                Kdf::Argon2id {
+                    salt: BASE64.encode(salt),
+                    m_cost: params.m_cost(),
+                    t_cost: params.t_cost(),
+                    p_cost: params.p_cost(),
+                }
+            }
+            KeystoreSecret::Key(_) => Kdf::Kek,
+        };
+        let cipher = cipher(&kdf, secret)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, Payload { msg: keypair.as_slice(), aad: address.as_bytes() })
+            .map_err(|_| AppError::ConfigError("Failed to encrypt the keypair".to_string()))?;
+
+        Ok(Keystore {
+            version: VERSION,
+            address,
+            kdf,
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Keystore, AppError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        let keystore: Keystore = serde_json::from_str(&contents)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        if keystore.version != VERSION {
+            return Err(AppError::ConfigError(format!(
+                "{}: unsupported keystore version {}", path.display(), keystore.version
+            )));
+        }
+        Ok(keystore)
+    }
+
+    /// Writes the keystore to a new file, readable only by the owner on Unix.
+    pub fn write(&self, path: &Path) -> Result<(), AppError> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let contents = serde_json::to_string_pretty(self)?;
+        let mut file = options.open(path)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        std::io::Write::write_all(&mut file, contents.as_bytes())
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn unlock(&self, secret: &KeystoreSecret) -> Result<Signer, AppError> {
+        let wrong_secret = || AppError::ConfigError("Keystore could not be decrypted: wrong password or key".to_string());
+
+        let nonce = BASE64.decode(&self.nonce)
+            .ok()
+            .filter(|nonce| nonce.len() == 12)
+            .ok_or_else(|| AppError::ConfigError("Keystore has an invalid nonce".to_string()))?;
+        let ciphertext = BASE64.decode(&self.ciphertext)
+            .map_err(|_| AppError::ConfigError("Keystore has an invalid ciphertext".to_string()))?;
+
+        let plaintext = Zeroizing::new(cipher(&self.kdf, secret)?
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: self.address.as_bytes() })
+            .map_err(|_| wrong_secret())?);
+        let mut keypair = Zeroizing::new([0; 64]);
+        if plaintext.len() != keypair.len() {
+            return Err(wrong_secret());
+        }
+        keypair.copy_from_slice(&plaintext);
+
+        let signer = Signer::from_keypair_bytes(&keypair)?;
+        if signer.pubkey() != self.address {
+            return Err(AppError::ConfigError("Keystore keypair does not match its address".to_string()));
+        }
+        Ok(signer)
+    }
+}
+
+fn cipher(kdf: &Kdf, secret: &KeystoreSecret) -> Result<Aes256Gcm, AppError> {
+    let key = match (kdf, secret) {
+        (Kdf::Argon2id { salt, m_cost, t_cost, p_cost }, KeystoreSecret::Password(password)) => {
+            let salt = BASE64.decode(salt)
+                .map_err(|_| AppError::ConfigError("Keystore has an invalid salt".to_string()))?;
+            let params = Params::new(*m_cost, *t_cost, *p_cost, Some(32))
+                .map_err(|e| AppError::ConfigError(format!("Keystore has invalid KDF parameters: {}", e)))?;
+            let mut key = Zeroizing::new([0; 32]);
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(password.as_bytes(), &salt, key.as_mut_slice())
+                .map_err(|e| AppError::ConfigError(format!("Key derivation failed: {}", e)))?;
+            key
+        }
+        (Kdf::Kek, KeystoreSecret::Key(key)) => key.clone(),
+        (Kdf::Argon2id { .. }, KeystoreSecret::Key(_)) => {
+            return Err(AppError::ConfigError("Keystore is protected by a password, not WALLET_KEYSTORE_KEY".to_string()));
+        }
+        (Kdf::Kek, KeystoreSecret::Password(_)) => {
+            return Err(AppError::ConfigError("Keystore is protected by WALLET_KEYSTORE_KEY, not a password".to_string()));
+        }
+    };
+    Ok(Aes256Gcm::new_from_slice(key.as_slice()).expect("AES-256 keys are 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn keypair() -> (String, String) {
+        let wallet = SigningKey::from_bytes(&[9; 32]);
+        (
+            bs58::encode(wallet.to_keypair_bytes()).into_string(),
+            bs58::encode(wallet.verifying_key().as_bytes()).into_string(),
+        )
+    }
+
+    #[test]
+    fn round_trips_with_password_and_rejects_wrong_one() {
+        let (keypair, address) = keypair();
+        let secret = KeystoreSecret::Password(Zeroizing::new("correct horse".to_string()));
+        let keystore = Keystore::encrypt(&keypair, &secret).unwrap();
+        assert_eq!(keystore.address, address);
+
+        let json = serde_json::to_string(&keystore).unwrap();
+        assert!(!json.contains(&keypair));
+        let keystore: Keystore = serde_json::from_str(&json).unwrap();
+        assert_eq!(keystore.unlock(&secret).unwrap().pubkey(), address);
+
+        let wrong = KeystoreSecret::Password(Zeroizing::new("wrong".to_string()));
+        assert!(keystore.unlock(&wrong).is_err());
+    }
+
+    #[test]
+    fn address_is_authenticated() {
+        let (keypair, _) = keypair();
+        let secret = KeystoreSecret::Key(Zeroizing::new([1; 32]));
+        let mut keystore = Keystore::encrypt(&keypair, &secret).unwrap();
+        keystore.address = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU".to_string();
+        assert!(keystore.unlock(&secret).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! The copy trades in `transactions` and the simulated fills in `paper_transactions`, loaded
+//! once and kept up to date with the fills this process records, so that deciding on a trade
+//! does not reload either table. Rows written by anything else show up after a restart.
+//!
+//! A fill counts from the moment it is recorded, whether or not its row could be written yet:
+//! a failed write is retried in the background until it succeeds or the process shuts down.
+//!
+//! Trades are decided on the ledger and the wallet's balances, so each holds a `TradeLock` from
+//! its decision until its fill is recorded or its swap fails: otherwise two trades decided at
+//! once would both pass limits that only one of them fits in.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::db::SupabaseClient;
+use crate::error::AppError;
+use crate::models::Transaction;
+use crate::shutdown::BackgroundTasks;
+
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Table {
+    Transactions,
+    PaperTransactions,
+}
+
+impl Table {
+    fn as_str(self) -> &'static str {
+        match self {
+            Table::Transactions => "transactions",
+            Table::PaperTransactions => "paper_transactions",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Fills {
+    /// `None` until first read.
+    loaded: Option<Vec<Transaction>>,
+    /// Fills recorded before the first read, whose rows may not have been written by then.
+    recorded: Vec<Transaction>,
+}
+
+/// Held while a trade is decided and carried out. Dropping it lets the next trade go ahead.
+pub struct TradeLock {
+    _wallet: OwnedMutexGuard<()>,
+    _buys: Option<OwnedMutexGuard<()>>,
+}
+
+#[derive(Clone)]
+pub struct Ledger {
+    db: SupabaseClient,
+    live: Arc<RwLock<Fills>>,
+    paper: Arc<RwLock<Fills>>,
+    tasks: BackgroundTasks,
+    /// One lock per tracked wallet, so its copied trades and exits do not sell the same tokens.
+    wallets: Arc<StdMutex<HashMap<Uuid, Arc<Mutex<()>>>>>,
+    /// Shared by every live buy, as they all spend the same balance within the same limits.
+    buys: Arc<Mutex<()>>,
+}
+
+impl Ledger {
+    pub fn new(db: SupabaseClient, tasks: &BackgroundTasks) -> Self {
+        Self {
+            db,
+            live: Arc::default(),
+            paper: Arc::default(),
+            tasks: tasks.clone(),
+            wallets: Arc::default(),
+            buys: Arc::default(),
+        }
+    }
+
+    /// Waits until no other trade of `tracked_wallet_id` is in progress and, for a live `buy`,
+    /// no other live buy either. Read the ledger and balances for the decision only after.
+    pub async fn lock(&self, tracked_wallet_id: Uuid, buy: bool) -> TradeLock {
+        let wallet = self.wallets.lock().expect("trade locks poisoned")
+            .entry(tracked_wallet_id)
+            .or_default()
+            .clone();
+        // Always the wallet first, so two trades never wait for each other.
+        let wallet = wallet.lock_owned().await;
+        let buys = if buy { Some(self.buys.clone().lock_owned().await) } else { None };
+        TradeLock { _wallet: wallet, _buys: buys }
+    }
+
+    pub async fn history(&self) -> Result<Vec<Transaction>, AppError> {
+        self.read(Table::Transactions).await
+    }
+
+    pub async fn paper_history(&self) -> Result<Vec<Transaction>, AppError> {
+        self.read(Table::PaperTransactions).await
+    }
+
+    /// Records a copy trade. It counts at once; its row is written in the background if it
+    /// cannot be written now.
+    pub async fn record(&self, transaction: &Transaction) {
+        self.add(Table::Transactions, transaction).await;
+    }
+
+    pub async fn record_paper(&self, transaction: &Transaction) {
+        self.add(Table::PaperTransactions, transaction).await;
+    }
+
+    fn fills(&self, table: Table) -> &RwLock<Fills> {
+        match table {
+            Table::Transactions => &self.live,
+            Table::PaperTransactions => &self.paper,
+        }
+    }
+
+    async fn read(&self, table: Table) -> Result<Vec<Transaction>, AppError> {
+        if let Some(history) = &self.fills(table).read().await.loaded {
+            return Ok(history.clone());
+        }
+        let mut fills = self.fills(table).write().await;
+        if fills.loaded.is_none() {
+            let mut history = match table {
+                Table::Transactions => self.db.get_transaction_history().await?,
+                Table::PaperTransactions => self.db.get_paper_transaction_history().await?,
+            };
+            for fill in std::mem::take(&mut fills.recorded) {
+                if !history.iter().any(|row| row.id == fill.id) {
+                    history.push(fill);
+                }
+            }
+            fills.loaded = Some(history);
+        }
+        Ok(fills.loaded.clone().unwrap_or_default())
+    }
+
+    async fn add(&self, table: Table, transaction: &Transaction) {
+        {
+            let mut fills = self.fills(table).write().await;
+            match &mut fills.loaded {
+                Some(history) => history.push(transaction.clone()),
+                None => fills.recorded.push(transaction.clone()),
+            }
+        }
+
+        if let Err(e) = self.write(table, transaction).await {
+            error!(
+                error = %e,
+                signature = %transaction.signature,
+                table = table.as_str(),
+                "Failed to write a fill; retrying in the background"
+            );
+            self.tasks.spawn(self.clone().retry_write(table, transaction.clone()));
+        }
+    }
+
+    async fn write(&self, table: Table, transaction: &Transaction) -> Result<(), AppError> {
+        match table {
+            Table::Transactions => self.db.log_transaction(transaction).await?,
+            Table::PaperTransactions => self.db.log_paper_transaction(transaction).await?,
+        };
+        Ok(())
+    }
+
+    /// Writes `transaction` with growing delays until it is written. At shutdown it is tried
+    /// once more, then given up on.
+    async fn retry_write(self, table: Table, transaction: Transaction) {
+        let shutdown = self.tasks.shutdown_token();
+        let mut delay = FIRST_RETRY_DELAY;
+        loop {
+            let stopping = tokio::select! {
+                _ = shutdown.cancelled() => true,
+                _ = tokio::time::sleep(delay) => false,
+            };
+            match self.write(table, &transaction).await {
+                Ok(()) => {
+                    info!(signature = %transaction.signature, table = table.as_str(), "Fill written after retrying");
+                    return;
+                }
+                Err(e) if stopping => {
+                    error!(
+                        error = %e,
+                        signature = %transaction.signature,
+                        table = table.as_str(),
+                        "Fill was never written; it is missing from the table"
+                    );
+                    return;
+                }
+                Err(e) => error!(error = %e, signature = %transaction.signature, table = table.as_str(), "Failed to write a fill"),
+            }
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::{http::StatusCode, routing::get, Json, Router};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use uuid::Uuid;
+
+    fn fill() -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: "me".to_string(),
+            tracked_wallet_id: Some(Uuid::new_v4()),
+            signature: "sig".to_string(),
+            transaction_type: "buy".to_string(),
+            token_address: "mint".to_string(),
+            amount: 1000.0,
+            price_sol: 0.0001,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_once_and_keeps_recorded_fills() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counter = loads.clone();
+        let supabase = testing::serve(Router::new().route(
+            "/transactions",
+            get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { Json(json!([])) }
+            })
+            .post(|row: String| async move { Json(json!([serde_json::from_str::<Value>(&row).unwrap()])) }),
+        )).await;
+        let ledger = Ledger::new(SupabaseClient::new(&supabase, "", "", "me"), &BackgroundTasks::new());
+
+        assert!(ledger.history().await.unwrap().is_empty());
+        ledger.record(&fill()).await;
+
+        let history = ledger.history().await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].signature, "sig");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn counts_fills_whose_write_failed_and_retries_it() {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let counter = writes.clone();
+        let supabase = testing::serve(Router::new().route(
+            "/transactions",
+            get(|| async { Json(json!([])) }).post(move |row: String| {
+                // The first write fails, as during an outage.
+                let failed = counter.fetch_add(1, Ordering::SeqCst) == 0;
+                async move {
+                    if failed {
+                        Err(StatusCode::SERVICE_UNAVAILABLE)
+                    } else {
+                        Ok(Json(json!([serde_json::from_str::<Value>(&row).unwrap()])))
+                    }
+                }
+            }),
+        )).await;
+        let tasks = BackgroundTasks::new();
+        let ledger = Ledger::new(SupabaseClient::new(&supabase, "", "", "me"), &tasks);
+
+        // Recorded before the table is first read.
+        ledger.record(&fill()).await;
+        assert_eq!(ledger.history().await.unwrap().len(), 1);
+
+        tasks.shutdown(Duration::from_secs(5)).await;
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn live_buys_and_trades_of_a_wallet_wait_for_each_other() {
+        let ledger = Ledger::new(SupabaseClient::new("http://127.0.0.1:1", "", "", "me"), &BackgroundTasks::new());
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let waits = |lock| async move { tokio::time::timeout(Duration::from_millis(50), lock).await.is_err() };
+
+        let buying = ledger.lock(first, true).await;
+        assert!(waits(ledger.lock(second, true)).await);
+        assert!(waits(ledger.lock(first, false)).await);
+        assert!(!waits(ledger.lock(second, false)).await);
+
+        drop(buying);
+        assert!(!waits(ledger.lock(second, true)).await);
+    }
+}
@@ -1,35 +1,392 @@
 use axum::{
+    extract::FromRef,
+    http::{HeaderValue, StatusCode},
+    middleware,
     routing::{get, post, put, delete},
     Router,
 };
+use clap::Parser;
 use dotenv::dotenv;
-use std::env;
-use std::net::SocketAddr;
+use std::future::IntoFuture;
 use tokio::net::TcpListener;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{info, warn, Level};
+use crate::config::{BacktestArgs, Cli, Command, Config, ConfigCommand, CorsConfig, KeystoreCommand, PaperConfig};
+use crate::balances::BalanceService;
+use crate::copier::Copier;
+use crate::watcher::PositionWatcher;
 use crate::db::SupabaseClient;
 use crate::error::AppError;
+use crate::models::{CopyTradeSettings, PriorityFee};
+use crate::monitor::{MonitorHandle, MonitorSettings};
+use crate::executor::{ComputeBudget, Executor, ExecutorSettings};
+use crate::paper::PaperSettings;
+use crate::rpc::SolanaRpc;
+use crate::keystore::{Keystore, KeystoreSecret};
+use crate::policy::SignerPolicy;
+use crate::prices::JupiterPrices;
+use crate::ledger::Ledger;
+use crate::risk::{RiskGuard, RiskLimits};
+use crate::safety::SafetyService;
+#[cfg(unix)]
+use crate::remote_signer::RemoteSigner;
+use crate::signer::Signer;
+use crate::shutdown::BackgroundTasks;
+use crate::tokens::TokenMetadataService;
 
 mod routes;
 mod models;
 mod db;
 mod error;
+mod config;
+mod monitor;
+mod openapi;
+mod parser;
+mod rpc;
+mod backtest;
+mod balances;
+mod bundles;
+mod copier;
+mod engine;
+mod executor;
+mod keystore;
+mod ledger;
+mod paper;
+mod policy;
+mod prices;
+mod risk;
+mod safety;
+mod schedule;
+#[cfg(unix)]
+mod remote_signer;
+mod signer;
+mod v1;
+mod shutdown;
+mod telemetry;
+#[cfg(test)]
+mod testing;
+mod tokens;
+mod watcher;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    db: SupabaseClient,
+    monitor: MonitorHandle,
+    tasks: BackgroundTasks,
+    paper: PaperSettings,
+    balances: BalanceService,
+    risk: RiskGuard,
+    safety: SafetyService,
+    tokens: TokenMetadataService,
+    /// The config file's, which copy trade settings are validated against.
+    compute_budget: ComputeBudget,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
     dotenv().ok();
 
-    let supabase_url = env::var("SUPABASE_URL")
-        .map_err(|_| AppError::ConfigError("SUPABASE_URL must be set".to_string()))?;
-    let supabase_service_role_key = env::var("SUPABASE_SERVICE_ROLE_KEY")
-        .map_err(|_| AppError::ConfigError("SUPABASE_SERVICE_ROLE_KEY must be set".to_string()))?;
-    let supabase_key = env::var("SUPABASE_API_KEY")
-        .map_err(|_| AppError::ConfigError("SUPABASE_API_KEY must be set".to_string()))?;
-    let user_id = env::var("USER_ID")
-        .map_err(|_| AppError::ConfigError("USER_ID must be set".to_string()))?;
+    let cli = Cli::parse();
+    let config = match cli.command {
+        Some(Command::SignerServer) => Config::load_for_signer(&cli),
+        _ => Config::load(&cli),
+    };
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Config { command: ConfigCommand::Check } => config::check(config),
+        Command::Serve => {
+            let config = config.map_err(|problems| AppError::ConfigError(problems.join("; ")))?;
+            serve(config).await
+        }
+        Command::Keystore { command: KeystoreCommand::Create { output, keypair } } => create_keystore(&output, keypair.as_deref()),
+        Command::SignerServer => {
+            let config = config.map_err(|problems| AppError::ConfigError(problems.join("; ")))?;
+            run_signer_server(config).await
+        }
+        Command::Backtest(args) => {
+            let config = config.map_err(|problems| AppError::ConfigError(problems.join("; ")))?;
+            run_backtest(&args, &config.paper)
+        }
+    }
+}
+
+/// Holds the wallet keypair and signs for the API over `signer.socket` until SIGINT/SIGTERM.
+#[cfg(unix)]
+async fn run_signer_server(config: Config) -> Result<(), AppError> {
+    telemetry::init(&config.logging);
+
+    // `Config::load_for_signer` has already checked that both are set.
+    let (Some(socket), Some(signer)) = (&config.signer.socket, config.wallet.signer) else {
+        return Err(AppError::ConfigError("signer-server requires signer.socket and the wallet keypair".to_string()));
+    };
+    let policy = SignerPolicy {
+        max_sol_out: config.signer.max_sol_out,
+        allowed_programs: config.signer.allowed_programs,
+    };
+    remote_signer::serve(socket, signer, policy, shutdown::signal()).await
+}
+
+#[cfg(not(unix))]
+async fn run_signer_server(_config: Config) -> Result<(), AppError> {
+    Err(AppError::ConfigError("signer-server is only supported on Unix".to_string()))
+}
+
+/// The signer process at `socket`, which must hold the keypair of `user_id`.
+#[cfg(unix)]
+async fn connect_signer(socket: &std::path::Path, user_id: &str) -> Result<Signer, AppError> {
+    let remote = RemoteSigner::connect(socket).await?;
+    if remote.pubkey() != user_id {
+        return Err(AppError::ConfigError(format!(
+            "The signer at {} holds {}, not wallet.user_id (USER_ID)", socket.display(), remote.pubkey()
+        )));
+    }
+    Ok(Signer::Remote(std::sync::Arc::new(remote)))
+}
+
+#[cfg(not(unix))]
+async fn connect_signer(_socket: &std::path::Path, _user_id: &str) -> Result<Signer, AppError> {
+    Err(AppError::ConfigError("signer.socket is only supported on Unix".to_string()))
+}
+
+/// Encrypts the keypair in `keypair`, or in WALLET_PRIVATE_KEY, into a new keystore file.
+fn create_keystore(output: &std::path::Path, keypair: Option<&std::path::Path>) -> Result<(), AppError> {
+    let keypair = zeroize::Zeroizing::new(match keypair {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?,
+        None => std::env::var("WALLET_PRIVATE_KEY")
+            .map_err(|_| AppError::ConfigError("Pass --keypair or set WALLET_PRIVATE_KEY".to_string()))?,
+    });
+
+    let keystore = Keystore::encrypt(&keypair, &KeystoreSecret::from_env()?)?;
+    keystore.write(output)?;
+    println!("Keystore for {} written to {}", keystore.address, output.display());
+    println!("Set WALLET_KEYSTORE={} and remove WALLET_PRIVATE_KEY", output.display());
+    Ok(())
+}
+
+fn paper_settings(config: &PaperConfig) -> PaperSettings {
+    PaperSettings {
+        slippage_pct: config.slippage_pct,
+        fee_sol: config.fee_sol,
+        starting_balance_sol: config.starting_balance_sol,
+    }
+}
+
+/// Prints the backtest report of the settings and transactions in the given files.
+fn run_backtest(args: &BacktestArgs, paper: &PaperConfig) -> Result<(), AppError> {
+    let read = |path: &std::path::Path| std::fs::read_to_string(path)
+        .map_err(|e| AppError::BadRequest(format!("{}: {}", path.display(), e)));
+
+    let settings: CopyTradeSettings = serde_json::from_str(&read(&args.settings)?)?;
+    // A single recorded transaction, like the decoder fixtures, or an array of them.
+    let transactions = match serde_json::from_str(&read(&args.transactions)?)? {
+        serde_json::Value::Array(transactions) => transactions,
+        transaction => vec![transaction],
+    };
+
+    let mut paper = paper_settings(paper);
+    if let Some(balance) = args.starting_balance_sol {
+        paper.starting_balance_sol = balance;
+    }
+
+    let report = backtest::run(&settings, &args.wallet, &transactions, &paper)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+async fn serve(config: Config) -> Result<(), AppError> {
+    telemetry::init(&config.logging);
+
+    // `Config::load` has already checked that these are set for the selected backend.
+    let database = &config.database;
+    let client = SupabaseClient::new(
+        database.supabase_url.as_deref().unwrap_or_default(),
+        database.supabase_api_key.as_deref().unwrap_or_default(),
+        database.supabase_service_role_key.as_deref().unwrap_or_default(),
+        config.wallet.user_id.as_deref().unwrap_or_default(),
+    );
+
+    let rpc = SolanaRpc::new(&config.solana.rpc_url, &config.solana.commitment);
+    let balances = BalanceService::new(rpc.clone(), config.wallet.user_id.clone().unwrap_or_default());
+    let safety = SafetyService::new(rpc.clone(), &config.safety.pool_api_url);
+    let token_list = match &config.tokens.list_file {
+        Some(list_file) => TokenMetadataService::load_list(list_file)?,
+        None => Default::default(),
+    };
+    let tokens = TokenMetadataService::new(client.clone(), rpc.clone(), token_list);
+    let tasks = BackgroundTasks::new();
+    let ledger = Ledger::new(client.clone(), &tasks);
+    let risk = RiskGuard::load(client.clone(), RiskLimits {
+        max_daily_loss_sol: config.risk.max_daily_loss_sol,
+        max_deployed_sol: config.risk.max_deployed_sol,
+    }).await?;
 
-    let client = SupabaseClient::new(&supabase_url, &supabase_key, &supabase_service_role_key, &user_id);
+    // `Config::load` has already checked that execution has a signer.
+    let signer = match &config.signer.socket {
+        Some(socket) if config.execution.enabled => {
+            Some(connect_signer(socket, config.wallet.user_id.as_deref().unwrap_or_default()).await?)
+        }
+        _ => config.wallet.signer.clone(),
+    };
+    let compute_budget = ComputeBudget {
+        priority_fee: match (config.priority_fee.micro_lamports, config.priority_fee.percentile) {
+            (Some(micro_lamports), _) => Some(PriorityFee::Fixed { micro_lamports }),
+            (None, Some(percentile)) => Some(PriorityFee::Percentile { percentile }),
+            (None, None) => None,
+        },
+        max_micro_lamports: config.priority_fee.max_micro_lamports,
+        unit_limit: config.priority_fee.compute_unit_limit,
+    };
+    let executor = signer
+        .filter(|_| config.execution.enabled)
+        .map(|signer| Executor::new(ExecutorSettings {
+            swap_api_url: config.execution.swap_api_url.clone(),
+            confirm_timeout: config.execution.confirm_timeout(),
+            compute_budget,
+            block_engine_url: config.bundle.block_engine_url.clone(),
+            tip_accounts: config.bundle.tip_accounts.clone(),
+            tip_lamports: config.bundle.tip_lamports,
+        }, rpc.clone(), signer, ledger.clone(), balances.clone(), risk.clone(), &tasks));
 
-    let app = Router::new()
+    let monitor = if config.monitor.enabled {
+        let monitor = monitor::spawn(client.clone(), MonitorSettings {
+            ws_url: config.solana.ws_url(),
+            commitment: config.solana.commitment.clone(),
+            reconnect_delay: config.monitor.reconnect_delay(),
+        }, &tasks);
+        copier::spawn(Copier {
+            db: client.clone(),
+            ledger: ledger.clone(),
+            rpc,
+            balances: balances.clone(),
+            risk: risk.clone(),
+            safety: safety.clone(),
+            executor: executor.clone(),
+            paper: paper_settings(&config.paper),
+        }, &monitor, &tasks);
+        monitor
+    } else {
+        monitor::disabled()
+    };
+
+    if config.exits.enabled {
+        watcher::spawn(PositionWatcher {
+            db: client.clone(),
+            ledger,
+            prices: JupiterPrices::new(&config.exits.price_api_url),
+            balances: balances.clone(),
+            executor,
+            paper: paper_settings(&config.paper),
+            risk: risk.clone(),
+            poll_interval: config.exits.poll_interval(),
+        }, &tasks);
+    }
+
+    let state = AppState {
+        db: client,
+        monitor,
+        tasks: tasks.clone(),
+        paper: paper_settings(&config.paper),
+        balances,
+        risk,
+        safety,
+        tokens,
+        compute_budget,
+    };
+
+    let mut app = Router::new()
+        .nest("/v1", v1_routes())
+        .merge(legacy_routes())
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/docs", get(openapi::docs))
+        .layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, config.request_timeout()))
+        .layer(middleware::from_fn(telemetry::scope_request_id))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(telemetry::make_request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(PropagateRequestIdLayer::new(telemetry::REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(telemetry::REQUEST_ID_HEADER, MakeRequestUuid))
+        .with_state(state);
+
+    if let Some(cors) = cors_layer(&config.cors) {
+        app = app.layer(cors);
+    }
+
+    let addr = config.bind_addr()?;
+    let shutdown_token = tasks.shutdown_token();
+
+    info!(%addr, "Server running");
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| AppError::ServerError(format!("Failed to bind to address: {}", e)))?;
+
+    // On SIGINT/SIGTERM stop accepting connections and let in-flight requests finish,
+    // but no longer than the configured deadline.
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            shutdown::signal().await;
+            info!("Shutting down, draining in-flight requests");
+            shutdown_token.cancel();
+        })
+        .into_future();
+
+    let drain_deadline = async {
+        tasks.shutdown_token().cancelled().await;
+        tokio::time::sleep(config.shutdown_timeout()).await;
+    };
+
+    tokio::select! {
+        result = server => result
+            .map_err(|e| AppError::ServerError(format!("Server error: {}", e)))?,
+        _ = drain_deadline => warn!(
+            "In-flight requests did not finish within {:?}; closing them",
+            config.shutdown_timeout()
+        ),
+    }
+
+    tasks.shutdown(config.shutdown_timeout()).await;
+    info!("Server stopped");
+
+    Ok(())
+}
+
+fn v1_routes() -> Router<AppState> {
+    Router::new()
+        .route("/tracked_wallets", get(v1::list_tracked_wallets).post(v1::create_tracked_wallet))
+        .route(
+            "/tracked_wallets/:id",
+            get(v1::get_tracked_wallet)
+                .patch(v1::update_tracked_wallet)
+                .delete(v1::delete_tracked_wallet),
+        )
+        .route("/tracked_wallets/:id/holdings", get(v1::list_leader_holdings))
+        .route("/copy_trade_settings", get(v1::list_copy_trade_settings).post(v1::create_copy_trade_settings))
+        .route(
+            "/copy_trade_settings/:tracked_wallet_id",
+            get(v1::get_copy_trade_settings)
+                .patch(v1::update_copy_trade_settings)
+                .delete(v1::delete_copy_trade_settings),
+        )
+        .route("/transactions", get(v1::list_transactions))
+        .route("/positions", get(v1::list_positions))
+        .route("/allowed_tokens", get(v1::list_allowed_tokens))
+        .route("/pnl", get(v1::get_pnl))
+        .route("/backtest", post(v1::run_backtest))
+        .route("/balances", get(v1::get_balances))
+        .route("/risk", get(v1::get_risk_status))
+        .route("/risk/halt", post(v1::halt_copying))
+        .route("/risk/resume", post(v1::resume_copying))
+        .route("/tokens/:mint/risk", get(v1::get_token_risk))
+        .route("/activity", get(v1::stream_activity))
+}
+
+/// The original routes, kept as deprecated aliases of `/v1`.
+fn legacy_routes() -> Router<AppState> {
+    Router::new()
         .route("/tracked_wallets", get(routes::get_tracked_wallets))
         .route("/tracked_wallets", post(routes::add_tracked_wallet))
         .route("/tracked_wallets/archive/:wallet_address", put(routes::archive_tracked_wallet))
@@ -41,17 +398,24 @@ async fn main() -> Result<(), AppError> {
         .route("/copy_trade_settings", put(routes::update_copy_trade_settings))
         .route("/copy_trade_settings/:tracked_wallet_id", delete(routes::delete_copy_trade_settings))
         .route("/transaction_history", get(routes::get_transaction_history))
-        .with_state(client.clone());
+        .layer(middleware::map_response(routes::mark_deprecated))
+}
 
-    let port = env::var("APP_PORT").unwrap_or_else(|_| "3001".to_string());
-    let addr = SocketAddr::from(([0, 0, 0, 0], port.parse()?));
+fn cors_layer(config: &CorsConfig) -> Option<CorsLayer> {
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
 
-    println!("Server running on {}", addr);
-    let listener = TcpListener::bind(addr).await
-        .map_err(|e| AppError::ServerError(format!("Failed to bind to address: {}", e)))?;
-    
-    axum::serve(listener, app).await
-        .map_err(|e| AppError::ServerError(format!("Server error: {}", e)))?;
+    let allow_origin = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config.allowed_origins.iter().filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
 
-    Ok(())
-}
\ No newline at end of file
+    Some(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(Any)
+        .allow_headers(Any))
+}
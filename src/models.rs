@@ -1,44 +1,488 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct TrackedWallet {
+    /// Ignored on create. Required by `PUT /tracked_wallets/update` to select the wallet.
     pub id: Option<Uuid>,
+    /// Always set by the server to the configured user.
     pub user_id: Option<String>,
+    /// Solana address of the wallet to copy.
     pub wallet_address: String,
+    /// `false` once the wallet has been archived.
     pub is_active: bool,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CopyTradeSettings {
+    /// Set by the server.
     pub id: Option<Uuid>,
+    /// Always set by the server to the configured user.
     pub user_id: Option<String>,
+    /// The tracked wallet these settings apply to. `PUT /copy_trade_settings` uses it to
+    /// select which settings to update.
     pub tracked_wallet_id: Uuid,
     pub is_enabled: bool,
+    /// SOL spent on each copied buy with `fixed` sizing.
     pub trade_amount_sol: f64,
+    /// Maximum slippage, in percent.
     pub max_slippage: f64,
     pub max_open_positions: i32,
+    /// Token mints that may be copied when `use_allowed_tokens_list` is set.
     pub allowed_tokens: Option<Vec<String>>,
     pub use_allowed_tokens_list: bool,
     pub allow_additional_buys: bool,
     pub match_sell_percentage: bool,
+    /// SOL that must remain in the trading wallet after a buy.
     pub min_sol_balance: f64,
+    /// Simulate fills into `paper_transactions` instead of trading real SOL.
+    #[serde(default)]
+    pub paper_trading: bool,
+    /// Sell a position once its price is this many percent above the entry price.
+    #[serde(default)]
+    pub take_profit_pct: Option<f64>,
+    /// Sell a position once its price is this many percent below the entry price.
+    #[serde(default)]
+    pub stop_loss_pct: Option<f64>,
+    /// Sell a position once its price is this many percent below the highest price seen since
+    /// it was opened (or the entry price, if higher).
+    #[serde(default)]
+    pub trailing_stop_pct: Option<f64>,
+    /// Most SOL in any one token, counting positions copied from every tracked wallet.
+    #[serde(default)]
+    pub max_token_exposure_sol: Option<f64>,
+    /// Most SOL in positions copied from this wallet.
+    #[serde(default)]
+    pub max_wallet_exposure_sol: Option<f64>,
+    /// Seconds that must pass after a copied trade before another buy is copied.
+    #[serde(default)]
+    pub min_seconds_between_trades: Option<i32>,
+    /// Most copied trades in any hour; further buys are skipped.
+    #[serde(default)]
+    pub max_trades_per_hour: Option<i32>,
+    /// Minutes after selling a token during which buys of it are not copied.
+    #[serde(default)]
+    pub rebuy_cooldown_minutes: Option<i32>,
+    /// Windows during which buys are copied; outside of them only sells are. Unset to copy at
+    /// any time.
+    #[serde(default)]
+    pub schedule: Option<Vec<ScheduleWindow>>,
+    /// IANA timezone of the `schedule` times, such as `Europe/Berlin`. Defaults to UTC.
+    #[serde(default)]
+    pub schedule_timezone: Option<String>,
+    /// How much SOL each copied buy spends. Defaults to `fixed`.
+    #[serde(default)]
+    pub sizing: Option<Sizing>,
+    /// Smallest copied buy, in SOL; smaller sizes are raised to it.
+    #[serde(default)]
+    pub min_trade_sol: Option<f64>,
+    /// Largest copied buy, in SOL; larger sizes are lowered to it.
+    #[serde(default)]
+    pub max_trade_sol: Option<f64>,
+    /// Skip buys of tokens whose supply can still be increased.
+    #[serde(default)]
+    pub reject_mint_authority: bool,
+    /// Skip buys of tokens whose holders can be frozen.
+    #[serde(default)]
+    pub reject_freeze_authority: bool,
+    /// Skip buys of tokens whose ten largest holders hold more than this percentage of the supply.
+    #[serde(default)]
+    pub max_top_holders_pct: Option<f64>,
+    /// Skip buys of tokens with less than this much SOL in their SOL pools.
+    #[serde(default)]
+    pub min_liquidity_sol: Option<f64>,
+    /// Skip buys of tokens whose oldest SOL pool is younger than this.
+    #[serde(default)]
+    pub min_pool_age_minutes: Option<i32>,
+    /// How the compute unit price of copied swaps is set. Unset to use `priority_fee` in the
+    /// config file.
+    #[serde(default)]
+    pub priority_fee: Option<PriorityFee>,
+    /// Highest compute unit price of copied swaps, in micro-lamports. Unset to use the config's.
+    #[serde(default)]
+    pub max_priority_fee_micro_lamports: Option<i64>,
+    /// Compute units requested by copied swaps. Unset to use the config's, or else the units the
+    /// swap API simulates the swap to need.
+    #[serde(default)]
+    pub compute_unit_limit: Option<i32>,
+    /// How copied swaps are sent to the network. Defaults to `rpc`.
+    #[serde(default)]
+    pub submission: Option<Submission>,
+    /// Tip paid with each bundle when `submission` is `bundle`, in lamports. Unset to use the
+    /// config's.
+    #[serde(default)]
+    pub bundle_tip_lamports: Option<i64>,
+    /// Set by the server on reads: whether buys are copied right now, i.e. `is_enabled` is on
+    /// and the time is inside a `schedule` window.
+    #[serde(default)]
+    pub active_now: Option<bool>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How a copied buy is sized, before `min_trade_sol` and `max_trade_sol` are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Sizing {
+    /// `trade_amount_sol` on every buy.
+    Fixed,
+    /// A percentage of the SOL the tracked wallet spent.
+    LeaderPercent { percent: f64 },
+    /// A percentage of the SOL the trading wallet can spend, i.e. its balance less
+    /// `min_sol_balance` and the buy's fees and rent.
+    BalancePercent { percent: f64 },
+    /// The same share of the trading wallet's SOL balance as the tracked wallet's buy was of its
+    /// SOL balance, times `multiplier`. Tokens either wallet holds are not counted.
+    SolBalanceRatio {
+        #[serde(default = "default_multiplier")]
+        multiplier: f64,
+    },
+}
+
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+/// How a swap's compute unit price is set, in micro-lamports per compute unit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum PriorityFee {
+    /// The same price on every swap.
+    Fixed { micro_lamports: u64 },
+    /// A percentile (0 to 100) of the prices paid in recent slots by transactions writing to the
+    /// pools the swap goes through.
+    Percentile { percentile: f64 },
+}
+
+/// How a swap is sent to the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Submission {
+    /// `sendTransaction` to the Solana RPC node.
+    #[default]
+    Rpc,
+    /// A bundle of the swap and a tip to the block engine, which lands both or neither and keeps
+    /// the swap out of the public mempool.
+    Bundle,
+}
+
+/// A weekly window of `CopyTradeSettings::schedule`, in the settings' `schedule_timezone`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScheduleWindow {
+    /// Days the window opens on, such as `mon` or `friday`. Empty for every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// Opening time, `HH:MM`.
+    pub start: String,
+    /// Closing time, `HH:MM`. A window closing at or before its opening time runs past midnight.
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Transaction {
     pub id: Uuid,
     pub user_id: String,
     pub tracked_wallet_id: Option<Uuid>,
     pub signature: String,
     pub transaction_type: String,
+    /// Mint address of the traded token.
     pub token_address: String,
     pub amount: f64,
     pub price_sol: f64,
     pub timestamp: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+/// A transaction with the metadata of its token.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TransactionWithToken {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    /// `None` when the token could not be looked up.
+    pub token: Option<TokenMetadata>,
+}
+
+/// Symbol, name, decimals and logo of a token.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenMetadata {
+    /// Mint address of the token.
+    pub mint: String,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub decimals: u8,
+    pub logo_uri: Option<String>,
+    /// When the metadata was fetched; `None` for tokens from the token list file.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A tracked wallet's balance of one token, kept up to date from every trade of the wallet
+/// the copier decodes.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LeaderHolding {
+    pub tracked_wallet_id: Uuid,
+    /// Mint address of the token.
+    pub token_address: String,
+    pub amount: f64,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Partial update of a tracked wallet; omitted fields are left unchanged.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TrackedWalletPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wallet_address: Option<String>,
+    /// Set to `false` to archive the wallet, `true` to unarchive it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+}
+
+/// Partial update of copy trade settings; omitted fields are left unchanged, and optional
+/// settings sent as `null` are cleared.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CopyTradeSettingsPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trade_amount_sol: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_slippage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_open_positions: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub allowed_tokens: Option<Option<Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_allowed_tokens_list: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_additional_buys: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_sell_percentage: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_sol_balance: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paper_trading: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub take_profit_pct: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub stop_loss_pct: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub trailing_stop_pct: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub max_token_exposure_sol: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub max_wallet_exposure_sol: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub min_seconds_between_trades: Option<Option<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub max_trades_per_hour: Option<Option<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub rebuy_cooldown_minutes: Option<Option<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub schedule: Option<Option<Vec<ScheduleWindow>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub schedule_timezone: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub sizing: Option<Option<Sizing>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub min_trade_sol: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub max_trade_sol: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_mint_authority: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_freeze_authority: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub max_top_holders_pct: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub min_liquidity_sol: Option<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub min_pool_age_minutes: Option<Option<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub priority_fee: Option<Option<PriorityFee>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub max_priority_fee_micro_lamports: Option<Option<i64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub compute_unit_limit: Option<Option<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub submission: Option<Option<Submission>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "nullable")]
+    pub bundle_tip_lamports: Option<Option<i64>>,
+}
+
+/// A patch field that can be cleared: left out it is `None`, `null` is `Some(None)`.
+mod nullable {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+
+    pub fn serialize<T: Serialize, S: Serializer>(value: &Option<Option<T>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.as_ref().and_then(Option::as_ref).serialize(serializer)
+    }
+}
+
+/// Profit and loss of the trades copied from one tracked wallet in one token.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenPnl {
+    pub tracked_wallet_id: Option<Uuid>,
+    pub token_address: String,
+    pub buys: usize,
+    pub sells: usize,
+    /// Tokens still held.
+    pub open_amount: f64,
+    /// SOL paid for the tokens still held.
+    pub open_cost_sol: f64,
+    /// SOL received for sold tokens minus what they cost.
+    pub realized_pnl_sol: f64,
+}
+
+/// Tokens held from trades copied from one tracked wallet.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OpenPosition {
+    pub tracked_wallet_id: Option<Uuid>,
+    pub token_address: String,
+    pub amount: f64,
+    /// SOL paid for the tokens still held.
+    pub cost_sol: f64,
+    pub token: Option<TokenMetadata>,
+}
+
+/// A token in a tracked wallet's `allowed_tokens`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AllowedToken {
+    pub tracked_wallet_id: Uuid,
+    pub token_address: String,
+    /// Whether `use_allowed_tokens_list` is on, so the list is enforced.
+    pub enforced: bool,
+    pub token: Option<TokenMetadata>,
+}
+
+/// The trading wallet's balances as last fetched from the cluster.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WalletBalances {
+    pub wallet_address: String,
+    pub sol: f64,
+    /// Token accounts of the wallet, empty ones included.
+    pub tokens: Vec<WalletTokenBalance>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WalletTokenBalance {
+    /// Mint address of the token.
+    pub mint: String,
+    pub token_account: String,
+    pub amount: f64,
+    pub decimals: u8,
+}
+
+/// How much a token looks like a rug, as checked before a buy of it is copied.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TokenRisk {
+    pub mint: String,
+    /// Set while more of the token can be minted.
+    pub mint_authority: Option<String>,
+    /// Set while token accounts can be frozen, which would stop holders from selling.
+    pub freeze_authority: Option<String>,
+    pub supply: f64,
+    pub decimals: u8,
+    /// Percentage of the supply in the ten largest token accounts, pool vaults included.
+    pub top_holders_pct: f64,
+    /// SOL in the pools pairing the token with SOL. `None` when there is no such pool.
+    pub liquidity_sol: Option<f64>,
+    /// When the oldest of those pools was created.
+    pub pool_created_at: Option<DateTime<Utc>>,
+    pub assessed_at: DateTime<Utc>,
+}
+
+/// A stop of all copying, by `POST /v1/risk/halt` or on a breached risk limit.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RiskHalt {
+    pub id: Option<Uuid>,
+    pub user_id: Option<String>,
+    pub reason: String,
+    pub halted_at: Option<DateTime<Utc>>,
+    /// Set by `POST /v1/risk/resume`.
+    pub resumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct HaltRequest {
+    /// Recorded with the halt.
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RiskStatus {
+    /// The active halt; copying is stopped while set.
+    pub halt: Option<RiskHalt>,
+    pub max_daily_loss_sol: Option<f64>,
+    pub max_deployed_sol: Option<f64>,
+    /// Realized profit of copy trades since midnight UTC; negative for a loss.
+    pub realized_pnl_today_sol: f64,
+    /// SOL paid for the tokens of open positions.
+    pub deployed_sol: f64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BacktestRequest {
+    /// Address of the tracked wallet that made the transactions.
+    pub wallet_address: String,
+    /// The candidate settings to replay the transactions with.
+    pub settings: CopyTradeSettings,
+    /// Recorded `getTransaction` results of the wallet, in any order.
+    #[schema(value_type = Vec<Object>)]
+    pub transactions: Vec<serde_json::Value>,
+    /// Defaults to `paper.starting_balance_sol`.
+    pub starting_balance_sol: Option<f64>,
+}
+
+/// How a set of copy trade settings would have done against a wallet's past trades.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BacktestReport {
+    /// Trades of the wallet decoded from the transactions.
+    pub trades_replayed: usize,
+    /// Buys and sells that would have been copied.
+    pub buys: usize,
+    pub sells: usize,
+    /// Trades not copied, by skip reason.
+    pub skipped: BTreeMap<String, usize>,
+    pub realized_pnl_sol: f64,
+    /// Open positions valued at the last price the wallet traded them at, minus their cost.
+    pub unrealized_pnl_sol: f64,
+    pub pnl_sol: f64,
+    /// Largest fall of SOL balance plus open position value from a previous high.
+    pub max_drawdown_sol: f64,
+    pub final_balance_sol: f64,
+    pub tokens: Vec<TokenPnl>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// `true` for simulated paper trades instead of real ones.
+    #[serde(default)]
+    pub paper: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrackedWalletIdResponse {
+    pub success: bool,
+    pub tracked_wallet_id: Uuid,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SettingsIdResponse {
+    pub success: bool,
+    pub settings_id: Uuid,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MessageResponse {
+    pub success: bool,
+    pub message: String,
+}
@@ -0,0 +1,438 @@
+//! Watches every active tracked wallet over the Solana RPC websocket (`logsSubscribe` for
+//! transactions that mention the wallet, `accountSubscribe` for its SOL balance) and broadcasts
+//! normalized [`ActivityEvent`]s.
+
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, Notify};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::SupabaseClient;
+use crate::error::AppError;
+use crate::shutdown::BackgroundTasks;
+
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ActivityEvent {
+    pub tracked_wallet_id: Uuid,
+    pub wallet_address: String,
+    pub slot: u64,
+    pub kind: ActivityKind,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// A transaction mentioning the wallet was seen. `failed` transactions had no effect.
+    Transaction { signature: String, failed: bool },
+    /// The wallet's SOL balance changed.
+    BalanceChanged { lamports: u64 },
+}
+
+/// Cheap to clone; lets the API ask for a resubscribe and lets consumers receive events.
+#[derive(Clone)]
+pub struct MonitorHandle {
+    refresh: Arc<Notify>,
+    events: broadcast::Sender<ActivityEvent>,
+}
+
+impl MonitorHandle {
+    fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            refresh: Arc::new(Notify::new()),
+            events,
+        }
+    }
+
+    /// Reloads the set of active tracked wallets. Call after a wallet is added, archived,
+    /// unarchived, updated or deleted.
+    pub fn refresh(&self) {
+        self.refresh.notify_one();
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ActivityEvent> {
+        self.events.subscribe()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MonitorSettings {
+    pub ws_url: String,
+    pub commitment: String,
+    pub reconnect_delay: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubscriptionKind {
+    Logs,
+    Account,
+}
+
+impl SubscriptionKind {
+    fn unsubscribe_method(self) -> &'static str {
+        match self {
+            SubscriptionKind::Logs => "logsUnsubscribe",
+            SubscriptionKind::Account => "accountUnsubscribe",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Subscription {
+    tracked_wallet_id: Uuid,
+    wallet_address: String,
+    kind: SubscriptionKind,
+}
+
+/// Subscription bookkeeping for a single websocket connection.
+#[derive(Default)]
+struct Subscriptions {
+    next_request_id: u64,
+    /// Wallets that should be watched, by address.
+    wanted: HashMap<String, Uuid>,
+    /// Subscribe requests waiting for the server to assign a subscription id.
+    pending: HashMap<u64, Subscription>,
+    /// Confirmed subscriptions by subscription id.
+    active: HashMap<u64, Subscription>,
+}
+
+pub fn spawn(
+    db: SupabaseClient,
+    settings: MonitorSettings,
+    tasks: &BackgroundTasks,
+) -> MonitorHandle {
+    let handle = MonitorHandle::new();
+    let monitor = WalletMonitor {
+        db,
+        settings,
+        handle: handle.clone(),
+        shutdown: tasks.shutdown_token(),
+    };
+    tasks.spawn(monitor.run());
+    handle
+}
+
+/// Returns a handle whose refreshes and events go nowhere, for when monitoring is disabled.
+pub fn disabled() -> MonitorHandle {
+    MonitorHandle::new()
+}
+
+struct WalletMonitor {
+    db: SupabaseClient,
+    settings: MonitorSettings,
+    handle: MonitorHandle,
+    shutdown: CancellationToken,
+}
+
+impl WalletMonitor {
+    async fn run(self) {
+        info!(ws_url = %self.settings.ws_url, "Wallet monitor started");
+
+        while !self.shutdown.is_cancelled() {
+            if let Err(e) = self.run_connection().await {
+                warn!(error = %e, "Wallet monitor connection failed, reconnecting in {:?}", self.settings.reconnect_delay);
+                tokio::select! {
+                    _ = self.shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(self.settings.reconnect_delay) => {}
+                }
+            }
+        }
+
+        info!("Wallet monitor stopped");
+    }
+
+    /// Runs until shutdown (`Ok`) or until the connection fails (`Err`).
+    async fn run_connection(&self) -> Result<(), AppError> {
+        let (mut socket, _) = connect_async(self.settings.ws_url.as_str()).await
+            .map_err(|e| AppError::RpcError(format!("Failed to connect to {}: {}", self.settings.ws_url, e)))?;
+
+        let mut subscriptions = Subscriptions::default();
+        self.resync(&mut socket, &mut subscriptions).await?;
+
+        loop {
+            tokio::select! {
+                _ = self.shutdown.cancelled() => {
+                    let _ = socket.close(None).await;
+                    return Ok(());
+                }
+                _ = self.handle.refresh.notified() => {
+                    self.resync(&mut socket, &mut subscriptions).await?;
+                }
+                message = socket.next() => {
+                    let message = match message {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => return Err(AppError::RpcError(format!("Websocket error: {}", e))),
+                        None => return Err(AppError::RpcError("Websocket closed by server".to_string())),
+                    };
+                    match message {
+                        Message::Text(text) => self.handle_message(&mut socket, &mut subscriptions, &text).await?,
+                        Message::Close(frame) => {
+                            return Err(AppError::RpcError(format!("Websocket closed by server: {:?}", frame)));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Subscribes to wallets that became active and unsubscribes from ones that no longer are.
+    async fn resync(&self, socket: &mut Socket, subscriptions: &mut Subscriptions) -> Result<(), AppError> {
+        let wallets = self.db.get_tracked_wallets().await?;
+        subscriptions.wanted = wallets
+            .into_iter()
+            .filter(|wallet| wallet.is_active)
+            .filter_map(|wallet| wallet.id.map(|id| (wallet.wallet_address, id)))
+            .collect();
+
+        let stale: Vec<u64> = subscriptions.active.iter()
+            .filter(|(_, sub)| subscriptions.wanted.get(&sub.wallet_address) != Some(&sub.tracked_wallet_id))
+            .map(|(id, _)| *id)
+            .collect();
+        for subscription_id in stale {
+            if let Some(sub) = subscriptions.active.remove(&subscription_id) {
+                self.unsubscribe(socket, subscriptions, subscription_id, sub.kind).await?;
+            }
+        }
+
+        let watched: Vec<(String, Uuid)> = subscriptions.wanted.iter()
+            .map(|(address, id)| (address.clone(), *id))
+            .collect();
+        for (wallet_address, tracked_wallet_id) in watched {
+            for kind in [SubscriptionKind::Logs, SubscriptionKind::Account] {
+                let already = subscriptions.active.values()
+                    .chain(subscriptions.pending.values())
+                    .any(|sub| sub.wallet_address == wallet_address && sub.tracked_wallet_id == tracked_wallet_id && sub.kind == kind);
+                if !already {
+                    let sub = Subscription { tracked_wallet_id, wallet_address: wallet_address.clone(), kind };
+                    self.subscribe(socket, subscriptions, sub).await?;
+                }
+            }
+        }
+
+        info!(wallets = subscriptions.wanted.len(), "Wallet monitor subscriptions synced");
+        Ok(())
+    }
+
+    async fn subscribe(&self, socket: &mut Socket, subscriptions: &mut Subscriptions, sub: Subscription) -> Result<(), AppError> {
+        subscriptions.next_request_id += 1;
+        let request_id = subscriptions.next_request_id;
+
+        let request = match sub.kind {
+            SubscriptionKind::Logs => json!({
+                "jsonrpc": "2.0",
+                "id": request_id,
+                "method": "logsSubscribe",
+                "params": [
+                    { "mentions": [sub.wallet_address] },
+                    { "commitment": self.settings.commitment },
+                ],
+            }),
+            SubscriptionKind::Account => json!({
+                "jsonrpc": "2.0",
+                "id": request_id,
+                "method": "accountSubscribe",
+                "params": [
+                    sub.wallet_address,
+                    { "encoding": "base64", "commitment": self.settings.commitment },
+                ],
+            }),
+        };
+
+        subscriptions.pending.insert(request_id, sub);
+        send(socket, request).await
+    }
+
+    async fn unsubscribe(&self, socket: &mut Socket, subscriptions: &mut Subscriptions, subscription_id: u64, kind: SubscriptionKind) -> Result<(), AppError> {
+        subscriptions.next_request_id += 1;
+        send(socket, json!({
+            "jsonrpc": "2.0",
+            "id": subscriptions.next_request_id,
+            "method": kind.unsubscribe_method(),
+            "params": [subscription_id],
+        })).await
+    }
+
+    async fn handle_message(&self, socket: &mut Socket, subscriptions: &mut Subscriptions, text: &str) -> Result<(), AppError> {
+        let message: Value = serde_json::from_str(text)
+            .map_err(|e| AppError::JsonParseError(format!("Invalid websocket message: {}", e)))?;
+
+        // Response to a subscribe request: remember the assigned subscription id.
+        if let Some(request_id) = message.get("id").and_then(Value::as_u64) {
+            let Some(sub) = subscriptions.pending.remove(&request_id) else {
+                return Ok(());
+            };
+            if let Some(error) = message.get("error") {
+                warn!(%error, wallet_address = %sub.wallet_address, kind = ?sub.kind, "Subscription rejected");
+                return Ok(());
+            }
+            let Some(subscription_id) = message.get("result").and_then(Value::as_u64) else {
+                return Ok(());
+            };
+            // The wallet may have been archived while the request was in flight.
+            if subscriptions.wanted.get(&sub.wallet_address) == Some(&sub.tracked_wallet_id) {
+                debug!(wallet_address = %sub.wallet_address, kind = ?sub.kind, subscription_id, "Subscribed");
+                subscriptions.active.insert(subscription_id, sub);
+            } else {
+                self.unsubscribe(socket, subscriptions, subscription_id, sub.kind).await?;
+            }
+            return Ok(());
+        }
+
+        let Some(params) = message.get("params") else {
+            return Ok(());
+        };
+        let Some(sub) = params.get("subscription").and_then(Value::as_u64)
+            .and_then(|id| subscriptions.active.get(&id)) else {
+            return Ok(());
+        };
+        let result = &params["result"];
+        let slot = result["context"]["slot"].as_u64().unwrap_or_default();
+        let value = &result["value"];
+
+        let kind = match message.get("method").and_then(Value::as_str) {
+            Some("logsNotification") => {
+                let Some(signature) = value["signature"].as_str() else {
+                    return Ok(());
+                };
+                ActivityKind::Transaction {
+                    signature: signature.to_string(),
+                    failed: !value["err"].is_null(),
+                }
+            }
+            Some("accountNotification") => ActivityKind::BalanceChanged {
+                lamports: value["lamports"].as_u64().unwrap_or_default(),
+            },
+            _ => return Ok(()),
+        };
+
+        let event = ActivityEvent {
+            tracked_wallet_id: sub.tracked_wallet_id,
+            wallet_address: sub.wallet_address.clone(),
+            slot,
+            kind,
+            received_at: Utc::now(),
+        };
+        debug!(tracked_wallet_id = %event.tracked_wallet_id, slot, kind = ?event.kind, "Wallet activity");
+        // No receivers just means nothing is consuming events yet.
+        let _ = self.handle.events.send(event);
+
+        Ok(())
+    }
+}
+
+async fn send(socket: &mut Socket, request: Value) -> Result<(), AppError> {
+    socket.send(Message::Text(request.to_string())).await
+        .map_err(|e| AppError::RpcError(format!("Failed to send websocket request: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::{routing::get, Json, Router};
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    const WALLET: &str = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU";
+
+    /// Reads requests from the monitor until one with `method` arrives.
+    async fn expect_request(socket: &mut WebSocketStream<TcpStream>, method: &str) -> Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next()).await
+                .expect("no request from the monitor")
+                .unwrap()
+                .unwrap();
+            let request: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            if request["method"] == method {
+                return request;
+            }
+        }
+    }
+
+    async fn reply(socket: &mut WebSocketStream<TcpStream>, request: &Value, subscription_id: u64) {
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": subscription_id });
+        socket.send(Message::Text(response.to_string())).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscribes_notifies_and_resubscribes() {
+        let wallet_id = Arc::new(Mutex::new(Uuid::new_v4()));
+        let first_id = *wallet_id.lock().unwrap();
+        let wallets = wallet_id.clone();
+        let supabase = testing::serve(Router::new().route("/tracked_wallets", get(move || {
+            let id = *wallets.lock().unwrap();
+            async move { Json(json!([{ "id": id, "wallet_address": WALLET, "is_active": true }])) }
+        }))).await;
+
+        let rpc = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = MonitorSettings {
+            ws_url: format!("ws://{}", rpc.local_addr().unwrap()),
+            commitment: "confirmed".to_string(),
+            reconnect_delay: Duration::from_millis(10),
+        };
+        let tasks = BackgroundTasks::new();
+        let handle = spawn(SupabaseClient::new(&supabase, "", "", "user"), settings, &tasks);
+        let mut events = handle.subscribe();
+
+        let mut socket = accept_async(rpc.accept().await.unwrap().0).await.unwrap();
+        let logs = expect_request(&mut socket, "logsSubscribe").await;
+        assert_eq!(logs["params"][0]["mentions"][0], WALLET);
+        let account = expect_request(&mut socket, "accountSubscribe").await;
+
+        // The wallet is re-added under a new id while both requests are still pending.
+        let second_id = Uuid::new_v4();
+        *wallet_id.lock().unwrap() = second_id;
+        handle.refresh();
+        let resubscribed = expect_request(&mut socket, "logsSubscribe").await;
+        expect_request(&mut socket, "accountSubscribe").await;
+
+        // Subscriptions confirmed for the old id are dropped again.
+        reply(&mut socket, &logs, 1).await;
+        let unsubscribe = expect_request(&mut socket, "logsUnsubscribe").await;
+        assert_eq!(unsubscribe["params"][0], 1);
+        reply(&mut socket, &account, 2).await;
+        expect_request(&mut socket, "accountUnsubscribe").await;
+
+        reply(&mut socket, &resubscribed, 3).await;
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "logsNotification",
+            "params": {
+                "subscription": 3,
+                "result": { "context": { "slot": 42 }, "value": { "signature": "SIG", "err": null, "logs": [] } },
+            },
+        });
+        socket.send(Message::Text(notification.to_string())).await.unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+        assert_eq!(event.tracked_wallet_id, second_id);
+        assert_ne!(event.tracked_wallet_id, first_id);
+        assert_eq!(event.slot, 42);
+        assert!(matches!(event.kind, ActivityKind::Transaction { ref signature, failed: false } if signature == "SIG"));
+
+        // After a disconnect the monitor reconnects and subscribes again.
+        socket.close(None).await.unwrap();
+        let mut socket = accept_async(rpc.accept().await.unwrap().0).await.unwrap();
+        let logs = expect_request(&mut socket, "logsSubscribe").await;
+        assert_eq!(logs["params"][0]["mentions"][0], WALLET);
+
+        tasks.shutdown(Duration::from_secs(5)).await;
+    }
+}
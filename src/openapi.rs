@@ -0,0 +1,145 @@
+use axum::{response::Html, Json};
+use utoipa::openapi::Deprecated;
+use utoipa::{Modify, OpenApi};
+
+use crate::monitor::{ActivityEvent, ActivityKind};
+use crate::models::{
+    TrackedWallet, TrackedWalletPatch, CopyTradeSettings, CopyTradeSettingsPatch, ScheduleWindow, Sizing, PriorityFee, Submission, Transaction, LeaderHolding,
+    TrackedWalletIdResponse, SettingsIdResponse, MessageResponse, TokenPnl, BacktestRequest, BacktestReport,
+    WalletBalances, WalletTokenBalance, RiskHalt, RiskStatus, HaltRequest, TokenRisk,
+    TransactionWithToken, TokenMetadata, OpenPosition, AllowedToken,
+};
+use crate::{routes, v1};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Copy Trader API"),
+    paths(
+        v1::list_tracked_wallets,
+        v1::create_tracked_wallet,
+        v1::get_tracked_wallet,
+        v1::update_tracked_wallet,
+        v1::delete_tracked_wallet,
+        v1::list_leader_holdings,
+        v1::list_copy_trade_settings,
+        v1::create_copy_trade_settings,
+        v1::get_copy_trade_settings,
+        v1::update_copy_trade_settings,
+        v1::delete_copy_trade_settings,
+        v1::list_transactions,
+        v1::list_positions,
+        v1::list_allowed_tokens,
+        v1::get_pnl,
+        v1::run_backtest,
+        v1::get_balances,
+        v1::get_risk_status,
+        v1::halt_copying,
+        v1::resume_copying,
+        v1::get_token_risk,
+        v1::stream_activity,
+        routes::get_tracked_wallets,
+        routes::add_tracked_wallet,
+        routes::archive_tracked_wallet,
+        routes::unarchive_tracked_wallet,
+        routes::delete_tracked_wallet,
+        routes::update_tracked_wallet,
+        routes::get_copy_trade_settings,
+        routes::create_copy_trade_settings,
+        routes::update_copy_trade_settings,
+        routes::delete_copy_trade_settings,
+        routes::get_transaction_history,
+    ),
+    components(schemas(
+        TrackedWallet,
+        TrackedWalletPatch,
+        CopyTradeSettings,
+        CopyTradeSettingsPatch,
+        ScheduleWindow,
+        Sizing,
+        PriorityFee,
+        Submission,
+        Transaction,
+        LeaderHolding,
+        TrackedWalletIdResponse,
+        SettingsIdResponse,
+        MessageResponse,
+        TokenPnl,
+        BacktestRequest,
+        BacktestReport,
+        WalletBalances,
+        WalletTokenBalance,
+        RiskHalt,
+        RiskStatus,
+        HaltRequest,
+        TokenRisk,
+        TransactionWithToken,
+        TokenMetadata,
+        OpenPosition,
+        AllowedToken,
+        ActivityEvent,
+        ActivityKind,
+    )),
+    tags(
+        (name = "tracked_wallets", description = "Wallets whose trades are copied"),
+        (name = "copy_trade_settings", description = "How trades of each tracked wallet are copied"),
+        (name = "transactions", description = "Copy trades made"),
+        (name = "backtest", description = "Copy trade settings tried against past trades"),
+        (name = "wallet", description = "The trading wallet"),
+        (name = "risk", description = "Account-wide kill switch and limits"),
+        (name = "tokens", description = "Tokens traded by tracked wallets"),
+        (name = "activity", description = "Live activity of tracked wallets"),
+    ),
+    modifiers(&DeprecateLegacyRoutes),
+)]
+pub struct ApiDoc;
+
+/// Every route outside `/v1` is a deprecated alias.
+struct DeprecateLegacyRoutes;
+
+impl Modify for DeprecateLegacyRoutes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if path.starts_with("/v1/") {
+                continue;
+            }
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.patch,
+            ];
+            for operation in operations.into_iter().flatten() {
+                operation.deprecated = Some(Deprecated::True);
+            }
+        }
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI, loaded from a CDN so nothing has to be bundled into the binary.
+pub async fn docs() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+const SWAGGER_UI: &str = r##"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Copy Trader API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,133 @@
+//! Paper trading: copy decisions are filled at the price the tracked wallet got, worsened by a
+//! configured slippage and fee, and recorded in `paper_transactions` instead of being executed.
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::engine::{self, CopyDecision, ExitReason, Position};
+use crate::models::Transaction;
+use crate::parser::ParsedTrade;
+
+#[derive(Debug, Clone)]
+pub struct PaperSettings {
+    /// Percent by which each simulated fill is worse than the tracked wallet's price.
+    pub slippage_pct: f64,
+    /// Network and priority fees charged per simulated trade, in SOL.
+    pub fee_sol: f64,
+    /// Virtual SOL each paper-traded wallet starts with.
+    pub starting_balance_sol: f64,
+}
+
+/// The simulated fill of `decision`, made when the tracked wallet made `trade`. `None` for a
+/// skip or when the trade has no SOL price to fill at.
+pub fn simulate_fill(
+    decision: &CopyDecision,
+    trade: &ParsedTrade,
+    settings: &PaperSettings,
+    user_id: &str,
+    tracked_wallet_id: Uuid,
+) -> Option<Transaction> {
+    let leader_price = trade.price_sol?;
+    let slippage = settings.slippage_pct / 100.0;
+
+    let (transaction_type, token_address, amount, price_sol) = match decision {
+        CopyDecision::Buy { token_address, sol_amount } => {
+            let fill_price = leader_price * (1.0 + slippage);
+            let tokens = (sol_amount - settings.fee_sol).max(0.0) / fill_price;
+            // The effective price includes the fee, as it would for a real fill.
+            ("buy", token_address, tokens, if tokens > 0.0 { sol_amount / tokens } else { 0.0 })
+        }
+        CopyDecision::Sell { token_address, token_amount } => {
+            let fill_price = leader_price * (1.0 - slippage);
+            let proceeds = (token_amount * fill_price - settings.fee_sol).max(0.0);
+            ("sell", token_address, *token_amount, if *token_amount > 0.0 { proceeds / token_amount } else { 0.0 })
+        }
+        CopyDecision::Skip { .. } => return None,
+    };
+
+    Some(Transaction {
+        id: Uuid::new_v4(),
+        user_id: user_id.to_string(),
+        tracked_wallet_id: Some(tracked_wallet_id),
+        signature: format!("paper:{}", trade.signature),
+        transaction_type: transaction_type.to_string(),
+        token_address: token_address.clone(),
+        amount,
+        price_sol,
+        timestamp: trade.timestamp.unwrap_or_else(Utc::now),
+    })
+}
+
+/// The simulated fill of an exit of `position` at `price_sol` per token, worsened like a copied
+/// sell.
+pub fn simulate_exit(
+    position: &Position,
+    price_sol: f64,
+    reason: ExitReason,
+    settings: &PaperSettings,
+    user_id: &str,
+    tracked_wallet_id: Uuid,
+) -> Transaction {
+    let fill_price = price_sol * (1.0 - settings.slippage_pct / 100.0);
+    let proceeds = (position.amount * fill_price - settings.fee_sol).max(0.0);
+    Transaction {
+        id: Uuid::new_v4(),
+        user_id: user_id.to_string(),
+        tracked_wallet_id: Some(tracked_wallet_id),
+        signature: format!("paper:{}:{}", reason.as_str(), Uuid::new_v4()),
+        transaction_type: reason.as_str().to_string(),
+        token_address: position.token_address.clone(),
+        amount: position.amount,
+        price_sol: proceeds / position.amount,
+        timestamp: Utc::now(),
+    }
+}
+
+/// The virtual SOL balance of a paper-traded wallet after its simulated fills.
+pub fn balance(settings: &PaperSettings, transactions: &[Transaction]) -> f64 {
+    transactions.iter().fold(settings.starting_balance_sol, |balance, transaction| {
+        let value = transaction.amount * transaction.price_sol;
+        match transaction.transaction_type.as_str() {
+            "buy" => balance - value,
+            kind if engine::is_sell(kind) => balance + value,
+            _ => balance,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::TradeKind;
+    use crate::testing;
+
+    fn settings() -> PaperSettings {
+        PaperSettings { slippage_pct: 1.0, fee_sol: 0.001, starting_balance_sol: 10.0 }
+    }
+
+    fn trade(kind: TradeKind) -> ParsedTrade {
+        testing::trade(kind, "token", 1000.0, 1.0)
+    }
+
+    #[test]
+    fn buy_and_sell_fill_worse_than_the_leader() {
+        let buy = CopyDecision::Buy { token_address: "token".to_string(), sol_amount: 0.101 };
+        let fill = simulate_fill(&buy, &trade(TradeKind::Buy), &settings(), "me", Uuid::nil()).unwrap();
+        // 0.1 SOL after the fee, at 0.00101 SOL per token.
+        assert!((fill.amount - 0.1 / 0.00101).abs() < 1e-6);
+        assert!((fill.amount * fill.price_sol - 0.101).abs() < 1e-9);
+        assert_eq!(fill.signature, "paper:sig");
+
+        let sell = CopyDecision::Sell { token_address: "token".to_string(), token_amount: 100.0 };
+        let fill = simulate_fill(&sell, &trade(TradeKind::Sell), &settings(), "me", Uuid::nil()).unwrap();
+        // 100 tokens at 0.00099 SOL, minus the fee.
+        assert!((fill.amount * fill.price_sol - (0.099 - 0.001)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn balance_follows_fills() {
+        let buy = CopyDecision::Buy { token_address: "token".to_string(), sol_amount: 1.0 };
+        let fill = simulate_fill(&buy, &trade(TradeKind::Buy), &settings(), "me", Uuid::nil()).unwrap();
+        assert!((balance(&settings(), &[fill]) - 9.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,462 @@
+//! Turns a confirmed transaction, as returned by `getTransaction` (`json` or `jsonParsed`
+//! encoding), into a trade made by one wallet by diffing that wallet's pre/post SOL and token
+//! balances. Known DEX programs are recognized from the outer and inner instructions.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::Transaction;
+
+pub const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+pub const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+/// Rent for a token account, which a wallet pays when it first receives a token.
+pub const TOKEN_ACCOUNT_RENT_SOL: f64 = 0.002_039_28;
+
+/// Balance changes smaller than this are rounding noise.
+const DUST: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dex {
+    RaydiumAmm,
+    RaydiumClmm,
+    RaydiumCpmm,
+    OrcaWhirlpool,
+    OrcaV2,
+    Jupiter,
+    PumpFun,
+}
+
+impl Dex {
+    pub fn from_program_id(program_id: &str) -> Option<Dex> {
+        match program_id {
+            "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8" => Some(Dex::RaydiumAmm),
+            "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK" => Some(Dex::RaydiumClmm),
+            "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C" => Some(Dex::RaydiumCpmm),
+            "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc" => Some(Dex::OrcaWhirlpool),
+            "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP" => Some(Dex::OrcaV2),
+            "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4" => Some(Dex::Jupiter),
+            "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P" => Some(Dex::PumpFun),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeKind {
+    Buy,
+    Sell,
+    Transfer,
+    /// One token for another, with no SOL leg.
+    Swap,
+}
+
+impl TradeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TradeKind::Buy => "buy",
+            TradeKind::Sell => "sell",
+            TradeKind::Transfer => "transfer",
+            TradeKind::Swap => "swap",
+        }
+    }
+}
+
+/// A trade made by one wallet. Amounts are in UI units (SOL, not lamports).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedTrade {
+    pub signature: String,
+    pub wallet: String,
+    pub kind: TradeKind,
+    /// The DEX the trade went through; for aggregators the aggregator rather than the pool.
+    pub dex: Option<Dex>,
+    pub input_mint: String,
+    pub input_amount: f64,
+    pub output_mint: String,
+    pub output_amount: f64,
+    /// The non-SOL side of the trade; for a swap, the token received.
+    pub token_address: String,
+    pub token_amount: f64,
+    pub token_decimals: u8,
+    /// SOL paid for a buy or received for a sell, excluding the network fee.
+    pub sol_amount: f64,
+    /// SOL per token. `None` when the trade has no SOL leg.
+    pub price_sol: Option<f64>,
+    /// The wallet's balance of `token_address` before and after the transaction.
+    pub token_balance_before: f64,
+    pub token_balance_after: f64,
+    /// The wallet's SOL balance before the transaction.
+    pub sol_balance_before: f64,
+    pub slot: u64,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl ParsedTrade {
+    pub fn to_transaction(&self, user_id: &str, tracked_wallet_id: Option<Uuid>) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: user_id.to_string(),
+            tracked_wallet_id,
+            signature: self.signature.clone(),
+            transaction_type: self.kind.as_str().to_string(),
+            token_address: self.token_address.clone(),
+            amount: self.token_amount,
+            price_sol: self.price_sol.unwrap_or_default(),
+            timestamp: self.timestamp.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TokenBalance {
+    before: f64,
+    after: f64,
+    decimals: u8,
+    /// The wallet had no account for the mint before the transaction.
+    opened: bool,
+    /// The wallet's account for the mint was closed by the transaction.
+    closed: bool,
+}
+
+impl TokenBalance {
+    fn delta(&self) -> f64 {
+        self.after - self.before
+    }
+}
+
+/// Parses `tx` from the point of view of `wallet`. Returns `Ok(None)` for failed transactions
+/// and ones that did not move any of the wallet's funds or are too complex to classify.
+pub fn parse_transaction(tx: &Value, wallet: &str) -> Result<Option<ParsedTrade>, AppError> {
+    let meta = tx.get("meta")
+        .filter(|meta| !meta.is_null())
+        .ok_or_else(|| AppError::JsonParseError("Transaction has no meta".to_string()))?;
+    if !meta["err"].is_null() {
+        return Ok(None);
+    }
+
+    let signature = tx["transaction"]["signatures"][0].as_str()
+        .ok_or_else(|| AppError::JsonParseError("Transaction has no signature".to_string()))?
+        .to_string();
+    let keys = account_keys(tx);
+
+    let Some(wallet_index) = keys.iter().position(|key| key == wallet) else {
+        return Ok(None);
+    };
+
+    let lamports = |field: &str| meta[field][wallet_index].as_u64().unwrap_or_default() as f64;
+    let sol_balance_before = lamports("preBalances") / LAMPORTS_PER_SOL;
+    let mut sol_delta = (lamports("postBalances") - lamports("preBalances")) / LAMPORTS_PER_SOL;
+    if wallet_index == 0 {
+        // The fee payer's balance also pays the network fee, which is not part of the trade.
+        sol_delta += meta["fee"].as_u64().unwrap_or_default() as f64 / LAMPORTS_PER_SOL;
+    }
+
+    let mut tokens = token_balances(meta, wallet);
+    if let Some(wsol) = tokens.remove(WSOL_MINT) {
+        // Wrapped SOL the wallet kept in a token account counts as SOL.
+        sol_delta += wsol.delta();
+    }
+    let changed: Vec<(String, TokenBalance)> = tokens.into_iter()
+        .filter(|(_, balance)| balance.delta().abs() > DUST)
+        .collect();
+
+    let dex = recognize_dex(&program_ids(tx, &keys));
+    let slot = tx["slot"].as_u64().unwrap_or_default();
+    let timestamp = tx["blockTime"].as_i64().and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+
+    let trade = |kind, input: (&str, f64), output: (&str, f64), token: (&str, TokenBalance), sol_amount: f64| {
+        let (token_address, balance) = token;
+        let token_amount = balance.delta().abs();
+        ParsedTrade {
+            signature: signature.clone(),
+            wallet: wallet.to_string(),
+            kind,
+            dex,
+            input_mint: input.0.to_string(),
+            input_amount: input.1,
+            output_mint: output.0.to_string(),
+            output_amount: output.1,
+            token_address: token_address.to_string(),
+            token_amount,
+            token_decimals: balance.decimals,
+            sol_amount,
+            price_sol: (sol_amount > DUST && token_amount > DUST).then(|| sol_amount / token_amount),
+            token_balance_before: balance.before,
+            token_balance_after: balance.after,
+            sol_balance_before,
+            slot,
+            timestamp,
+        }
+    };
+
+    let parsed = match changed.as_slice() {
+        [] => {
+            if dex.is_some() || sol_delta.abs() <= DUST {
+                None
+            } else {
+                let sol = TokenBalance { before: sol_balance_before, after: sol_balance_before + sol_delta, decimals: 9, ..Default::default() };
+                Some(trade(TradeKind::Transfer, (WSOL_MINT, sol_delta.abs()), (WSOL_MINT, sol_delta.abs()), (WSOL_MINT, sol), 0.0))
+            }
+        }
+        [(mint, balance)] => {
+            let token_delta = balance.delta();
+            // Without a DEX, a SOL change the size of an account's rent is the wallet paying for
+            // (or being refunded) a token account, not paying for the token.
+            let sol_leg = sol_delta.abs() > DUST
+                && (dex.is_some() || sol_delta.abs() > TOKEN_ACCOUNT_RENT_SOL + DUST)
+                && sol_delta.signum() != token_delta.signum();
+
+            if !sol_leg {
+                Some(trade(TradeKind::Transfer, (mint, token_delta.abs()), (mint, token_delta.abs()), (mint, *balance), 0.0))
+            } else if token_delta > 0.0 {
+                let mut spent = -sol_delta;
+                if balance.opened && spent > TOKEN_ACCOUNT_RENT_SOL {
+                    spent -= TOKEN_ACCOUNT_RENT_SOL;
+                }
+                Some(trade(TradeKind::Buy, (WSOL_MINT, spent), (mint, token_delta), (mint, *balance), spent))
+            } else {
+                let mut received = sol_delta;
+                if balance.closed && received > TOKEN_ACCOUNT_RENT_SOL {
+                    received -= TOKEN_ACCOUNT_RENT_SOL;
+                }
+                Some(trade(TradeKind::Sell, (mint, -token_delta), (WSOL_MINT, received), (mint, *balance), received))
+            }
+        }
+        [(first_mint, first), (second_mint, second)] if first.delta().signum() != second.delta().signum() => {
+            let ((in_mint, sold), (out_mint, bought)) = if first.delta() < 0.0 {
+                ((first_mint, first), (second_mint, second))
+            } else {
+                ((second_mint, second), (first_mint, first))
+            };
+            Some(trade(TradeKind::Swap, (in_mint, -sold.delta()), (out_mint, bought.delta()), (out_mint, *bought), 0.0))
+        }
+        _ => None,
+    };
+
+    Ok(parsed)
+}
+
+/// Account keys in the order instructions index them: static keys, then loaded writable and
+/// loaded readonly addresses of a versioned transaction.
+fn account_keys(tx: &Value) -> Vec<String> {
+    let static_keys = tx["transaction"]["message"]["accountKeys"].as_array().cloned().unwrap_or_default();
+    let loaded = &tx["meta"]["loadedAddresses"];
+    let loaded_keys = ["writable", "readonly"].into_iter()
+        .flat_map(|kind| loaded[kind].as_array().cloned().unwrap_or_default());
+
+    static_keys.into_iter()
+        .chain(loaded_keys)
+        .filter_map(|key| {
+            // `jsonParsed` encodes keys as objects, `json` as plain strings.
+            key.as_str()
+                .or_else(|| key["pubkey"].as_str())
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// The DEX the wallet traded with. An aggregator wins over the pools it routed through.
+fn recognize_dex(program_ids: &[String]) -> Option<Dex> {
+    let dexes: Vec<Dex> = program_ids.iter()
+        .filter_map(|program_id| Dex::from_program_id(program_id))
+        .collect();
+
+    dexes.iter().copied()
+        .find(|dex| *dex == Dex::Jupiter)
+        .or_else(|| dexes.first().copied())
+}
+
+/// Program ids of the outer instructions, in order, followed by those of inner instructions.
+fn program_ids(tx: &Value, keys: &[String]) -> Vec<String> {
+    let outer = tx["transaction"]["message"]["instructions"].as_array().cloned().unwrap_or_default();
+    let inner = tx["meta"]["innerInstructions"].as_array().cloned().unwrap_or_default()
+        .into_iter()
+        .flat_map(|group| group["instructions"].as_array().cloned().unwrap_or_default());
+
+    let mut program_ids: Vec<String> = Vec::new();
+    for ix in outer.into_iter().chain(inner) {
+        let program_id = match ix["programId"].as_str() {
+            Some(program_id) => Some(program_id.to_string()),
+            None => ix["programIdIndex"].as_u64()
+                .and_then(|index| keys.get(index as usize))
+                .cloned(),
+        };
+        if let Some(program_id) = program_id {
+            if !program_ids.contains(&program_id) {
+                program_ids.push(program_id);
+            }
+        }
+    }
+    program_ids
+}
+
+/// The wallet's balance of every mint it holds before or after the transaction.
+fn token_balances(meta: &Value, wallet: &str) -> BTreeMap<String, TokenBalance> {
+    let mut balances: BTreeMap<String, TokenBalance> = BTreeMap::new();
+    let mut seen_before: Vec<String> = Vec::new();
+    let mut seen_after: Vec<String> = Vec::new();
+
+    for (field, is_before) in [("preTokenBalances", true), ("postTokenBalances", false)] {
+        for entry in meta[field].as_array().into_iter().flatten() {
+            if entry["owner"].as_str() != Some(wallet) {
+                continue;
+            }
+            let Some(mint) = entry["mint"].as_str() else {
+                continue;
+            };
+            let amount = ui_amount(&entry["uiTokenAmount"]);
+            let balance = balances.entry(mint.to_string()).or_default();
+            balance.decimals = entry["uiTokenAmount"]["decimals"].as_u64().unwrap_or_default() as u8;
+            if is_before {
+                balance.before += amount;
+                seen_before.push(mint.to_string());
+            } else {
+                balance.after += amount;
+                seen_after.push(mint.to_string());
+            }
+        }
+    }
+
+    for (mint, balance) in balances.iter_mut() {
+        balance.opened = !seen_before.contains(mint);
+        balance.closed = !seen_after.contains(mint);
+    }
+
+    balances
+}
+
+fn ui_amount(amount: &Value) -> f64 {
+    if let Some(ui) = amount["uiAmountString"].as_str().and_then(|ui| ui.parse().ok()) {
+        return ui;
+    }
+    let raw: f64 = amount["amount"].as_str().and_then(|raw| raw.parse().ok()).unwrap_or_default();
+    let decimals = amount["decimals"].as_i64().unwrap_or_default() as i32;
+    raw / 10f64.powi(decimals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Value {
+        let path = format!("{}/tests/fixtures/transactions/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let contents = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        serde_json::from_str(&contents).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn pump_fun_buy() {
+        let tx = fixture("pump_fun_buy");
+        let trade = parse_transaction(&tx, "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap().unwrap();
+
+        assert_eq!(trade.kind, TradeKind::Buy);
+        assert_eq!(trade.dex, Some(Dex::PumpFun));
+        assert_eq!(trade.input_mint, WSOL_MINT);
+        assert_eq!(trade.output_mint, "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R");
+        assert_eq!(trade.token_address, trade.output_mint);
+        // 0.50203928 SOL left the wallet besides the fee, 0.00203928 of which is the rent of
+        // the new token account rather than the price of the tokens.
+        assert_close(trade.sol_amount, 0.5);
+        assert_close(trade.token_amount, 17_843_215.123456);
+        assert_eq!(trade.token_decimals, 6);
+        assert_close(trade.price_sol.unwrap(), 0.5 / 17_843_215.123456);
+        assert_close(trade.token_balance_before, 0.0);
+        assert_close(trade.sol_balance_before, 2.0);
+        assert_eq!(trade.timestamp.unwrap().timestamp(), 1_727_712_000);
+    }
+
+    #[test]
+    fn raydium_amm_sell() {
+        let tx = fixture("raydium_amm_sell");
+        let trade = parse_transaction(&tx, "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM").unwrap().unwrap();
+
+        assert_eq!(trade.kind, TradeKind::Sell);
+        assert_eq!(trade.dex, Some(Dex::RaydiumAmm));
+        assert_eq!(trade.input_mint, "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
+        assert_eq!(trade.output_mint, WSOL_MINT);
+        assert_close(trade.token_amount, 400_000.0);
+        assert_close(trade.sol_amount, 1.25);
+        assert_close(trade.price_sol.unwrap(), 1.25 / 400_000.0);
+        assert_close(trade.token_balance_before, 1_000_000.0);
+        assert_close(trade.token_balance_after, 600_000.0);
+    }
+
+    #[test]
+    fn jupiter_versioned_buy_with_loaded_addresses() {
+        let tx = fixture("jupiter_buy_v0");
+        let trade = parse_transaction(&tx, "5tzFkiKscXHK5ZXCGbXZxdw7gTjjD1mBwuoFbhUvuAi9").unwrap().unwrap();
+
+        assert_eq!(trade.kind, TradeKind::Buy);
+        // The route went through a Whirlpool, but the wallet traded with Jupiter.
+        assert_eq!(trade.dex, Some(Dex::Jupiter));
+        assert_eq!(trade.output_mint, "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_close(trade.sol_amount, 0.1);
+        assert_close(trade.token_amount, 15.5);
+        assert_close(trade.price_sol.unwrap(), 0.1 / 15.5);
+    }
+
+    #[test]
+    fn orca_whirlpool_sell_json_parsed() {
+        let tx = fixture("orca_whirlpool_sell_parsed");
+        let trade = parse_transaction(&tx, "HN7cABqLq46Es1jh92dQQisAq662SmxELLLsHHe4YWrH").unwrap().unwrap();
+
+        assert_eq!(trade.kind, TradeKind::Sell);
+        assert_eq!(trade.dex, Some(Dex::OrcaWhirlpool));
+        assert_eq!(trade.input_mint, "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN");
+        assert_close(trade.token_amount, 250.0);
+        // Proceeds were kept as wrapped SOL.
+        assert_close(trade.sol_amount, 0.75);
+        assert_close(trade.token_balance_after, 0.0);
+    }
+
+    #[test]
+    fn token_transfer_is_not_a_trade() {
+        let tx = fixture("spl_transfer");
+        let trade = parse_transaction(&tx, "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap().unwrap();
+
+        assert_eq!(trade.kind, TradeKind::Transfer);
+        assert_eq!(trade.dex, None);
+        assert_eq!(trade.token_address, "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
+        assert_close(trade.token_amount, 5_000.0);
+        assert_eq!(trade.price_sol, None);
+    }
+
+    #[test]
+    fn token_for_token_swap_has_no_price() {
+        let tx = fixture("token_for_token_swap");
+        let trade = parse_transaction(&tx, "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM").unwrap().unwrap();
+
+        assert_eq!(trade.kind, TradeKind::Swap);
+        assert_eq!(trade.dex, Some(Dex::RaydiumAmm));
+        assert_eq!(trade.input_mint, "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
+        assert_close(trade.input_amount, 400_000.0);
+        assert_eq!(trade.output_mint, "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_eq!(trade.token_address, trade.output_mint);
+        assert_close(trade.token_amount, 12.5);
+        assert_close(trade.sol_amount, 0.0);
+        assert_eq!(trade.price_sol, None);
+    }
+
+    #[test]
+    fn failed_transaction_is_ignored() {
+        let tx = fixture("failed_swap");
+        assert!(parse_transaction(&tx, "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap().is_none());
+    }
+
+    #[test]
+    fn unrelated_wallet_is_ignored() {
+        let tx = fixture("pump_fun_buy");
+        assert!(parse_transaction(&tx, "CuieVDEDtLo7FypA9SbLM9saXFdb1dsshEkyErMqkRQq").unwrap().is_none());
+    }
+}
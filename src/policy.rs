@@ -0,0 +1,285 @@
+//! Checks a signer applies to a transaction before signing it: which programs it calls and how
+//! much SOL it can take out of the wallet. Also sets the compute budget of a built transaction.
+
+use crate::signer::{short_vec_len, SIGNATURE_LEN};
+use crate::parser::LAMPORTS_PER_SOL;
+
+pub const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+pub const COMPUTE_BUDGET_PROGRAM: &str = "ComputeBudget111111111111111111111111111111";
+
+/// Lamports per signature.
+const SIGNATURE_FEE: u64 = 5_000;
+/// Compute units an instruction gets when the transaction does not set a limit.
+const DEFAULT_INSTRUCTION_UNITS: u64 = 200_000;
+pub const MAX_TRANSACTION_UNITS: u64 = 1_400_000;
+
+#[derive(Debug, Clone)]
+pub struct SignerPolicy {
+    /// Most SOL a transaction may move out of the wallet, fees included.
+    pub max_sol_out: f64,
+    /// Programs a transaction may call directly. Empty allows any.
+    pub allowed_programs: Vec<String>,
+}
+
+struct Instruction<'a> {
+    program_id: String,
+    accounts: &'a [u8],
+    data: &'a [u8],
+    /// Where `data` starts in the transaction.
+    data_offset: usize,
+}
+
+struct Message<'a> {
+    required_signatures: usize,
+    account_keys: Vec<String>,
+    recent_blockhash: &'a [u8],
+    instructions: Vec<Instruction<'a>>,
+}
+
+impl SignerPolicy {
+    /// Refuses `tx`, with the reason, unless it only calls allowed programs and moves at most
+    /// `max_sol_out` SOL out of `signer`. Returns the SOL it moves out.
+    ///
+    /// SOL out counts the fees and the System program transfers and account creations funded by
+    /// the wallet. Other System instructions touching the wallet are refused. Rent paid by other
+    /// programs on the wallet's behalf (e.g. creating token accounts) is not counted, and neither
+    /// is SOL an allowed program moves out of the wallet through a CPI (e.g. a swap program
+    /// transferring the wallet's SOL): the limit only holds for programs trusted not to do that.
+    pub fn check(&self, tx: &[u8], signer: &str) -> Result<f64, String> {
+        let message = decode_message(tx)?;
+
+        for instruction in &message.instructions {
+            if !self.allowed_programs.is_empty() && !self.allowed_programs.contains(&instruction.program_id) {
+                return Err(format!("program {} is not allowed", instruction.program_id));
+            }
+        }
+
+        let signer_index = message.account_keys.iter().position(|key| key == signer);
+        let mut lamports_out = SIGNATURE_FEE * message.required_signatures as u64;
+        let mut unit_price: u64 = 0;
+        let mut unit_limit: Option<u64> = None;
+
+        for instruction in &message.instructions {
+            match instruction.program_id.as_str() {
+                SYSTEM_PROGRAM => {
+                    let touches_signer = instruction.accounts.iter()
+                        .any(|&index| Some(index as usize) == signer_index);
+                    if !touches_signer {
+                        continue;
+                    }
+                    let from_signer = instruction.accounts.first()
+                        .is_some_and(|&index| Some(index as usize) == signer_index);
+                    match (read_u32(instruction.data, 0), read_u64(instruction.data, 4)) {
+                        // CreateAccount and Transfer, both funded by their first account.
+                        (Some(0 | 2), Some(lamports)) if from_signer => lamports_out += lamports,
+                        (Some(0 | 2), Some(_)) => {}
+                        _ => return Err("unsupported System instruction on the wallet".to_string()),
+                    }
+                }
+                COMPUTE_BUDGET_PROGRAM => match instruction.data.first() {
+                    Some(2) => unit_limit = read_u32(instruction.data, 1).map(u64::from),
+                    Some(3) => unit_price = read_u64(instruction.data, 1).unwrap_or_default(),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let units = unit_limit.unwrap_or_else(|| {
+            let instructions = message.instructions.iter()
+                .filter(|instruction| instruction.program_id != COMPUTE_BUDGET_PROGRAM)
+                .count() as u64;
+            (instructions * DEFAULT_INSTRUCTION_UNITS).min(MAX_TRANSACTION_UNITS)
+        });
+        // The price is in micro-lamports per unit.
+        lamports_out += (unit_price as u128 * units as u128).div_ceil(1_000_000) as u64;
+
+        let sol_out = lamports_out as f64 / LAMPORTS_PER_SOL;
+        if sol_out > self.max_sol_out {
+            return Err(format!("moves {} SOL out, more than the {} SOL allowed", sol_out, self.max_sol_out));
+        }
+        Ok(sol_out)
+    }
+}
+
+/// Sets the compute unit price (micro-lamports) and limit of an unsigned `tx` by rewriting its
+/// Compute Budget instructions, which must already be there. `None` leaves a value as built.
+pub fn set_compute_budget(tx: &mut [u8], unit_price: Option<u64>, unit_limit: Option<u32>) -> Result<(), String> {
+    let mut price_at = None;
+    let mut limit_at = None;
+    for instruction in decode_message(tx)?.instructions {
+        if instruction.program_id != COMPUTE_BUDGET_PROGRAM {
+            continue;
+        }
+        match (instruction.data.first(), instruction.data.len()) {
+            (Some(2), 5) => limit_at = Some(instruction.data_offset + 1),
+            (Some(3), 9) => price_at = Some(instruction.data_offset + 1),
+            _ => {}
+        }
+    }
+
+    if let Some(price) = unit_price {
+        let offset = price_at.ok_or("transaction does not set a compute unit price")?;
+        tx[offset..offset + 8].copy_from_slice(&price.to_le_bytes());
+    }
+    if let Some(limit) = unit_limit {
+        let offset = limit_at.ok_or("transaction does not set a compute unit limit")?;
+        tx[offset..offset + 4].copy_from_slice(&limit.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// The blockhash a serialized transaction was built with.
+pub fn recent_blockhash(tx: &[u8]) -> Result<[u8; 32], String> {
+    decode_message(tx)?.recent_blockhash.try_into().map_err(|_| "invalid transaction".to_string())
+}
+
+/// Decodes the static account keys, recent blockhash and top-level instructions of a serialized
+/// (legacy or versioned) transaction.
+fn decode_message(tx: &[u8]) -> Result<Message<'_>, String> {
+    let invalid = || "invalid transaction".to_string();
+    let mut reader = Reader { bytes: tx, offset: 0 };
+
+    let signature_count = reader.short_vec_len().ok_or_else(invalid)?;
+    reader.take(signature_count * SIGNATURE_LEN).ok_or_else(invalid)?;
+
+    // Versioned messages start with 0x80 | version; legacy ones start with the header.
+    if reader.peek().is_some_and(|byte| byte & 0x80 != 0) {
+        reader.take(1).ok_or_else(invalid)?;
+    }
+    let header = reader.take(3).ok_or_else(invalid)?;
+    let required_signatures = header[0] as usize;
+
+    let key_count = reader.short_vec_len().ok_or_else(invalid)?;
+    let account_keys = (0..key_count)
+        .map(|_| reader.take(32).map(|key| bs58::encode(key).into_string()))
+        .collect::<Option<Vec<String>>>()
+        .ok_or_else(invalid)?;
+    let recent_blockhash = reader.take(32).ok_or_else(invalid)?;
+
+    let instruction_count = reader.short_vec_len().ok_or_else(invalid)?;
+    let mut instructions = Vec::with_capacity(instruction_count);
+    for _ in 0..instruction_count {
+        let program_index = *reader.take(1).ok_or_else(invalid)?.first().ok_or_else(invalid)? as usize;
+        // Programs are always static keys, never loaded from lookup tables.
+        let program_id = account_keys.get(program_index).ok_or_else(invalid)?.clone();
+        let account_count = reader.short_vec_len().ok_or_else(invalid)?;
+        let accounts = reader.take(account_count).ok_or_else(invalid)?;
+        let data_len = reader.short_vec_len().ok_or_else(invalid)?;
+        let data_offset = reader.offset;
+        let data = reader.take(data_len).ok_or_else(invalid)?;
+        instructions.push(Instruction { program_id, accounts, data, data_offset });
+    }
+
+    Ok(Message { required_signatures, account_keys, recent_blockhash, instructions })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.offset).copied()
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn short_vec_len(&mut self) -> Option<usize> {
+        let (len, size) = short_vec_len(self.bytes.get(self.offset..)?)?;
+        self.offset += size;
+        Some(len)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET: [u8; 32] = [9; 32];
+    const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+    /// A v0 transaction from the wallet: a priority fee, a transfer of `lamports` to another
+    /// account, then a call of `program`.
+    fn transaction(lamports: u64, program: &str) -> Vec<u8> {
+        let keys = [
+            WALLET.to_vec(),
+            vec![1; 32],
+            bs58::decode(SYSTEM_PROGRAM).into_vec().unwrap(),
+            bs58::decode(COMPUTE_BUDGET_PROGRAM).into_vec().unwrap(),
+            bs58::decode(program).into_vec().unwrap(),
+        ];
+        let mut tx = vec![1];
+        tx.extend_from_slice(&[0; SIGNATURE_LEN]);
+        tx.extend_from_slice(&[0x80, 1, 0, 3, keys.len() as u8]);
+        for key in &keys {
+            tx.extend_from_slice(key);
+        }
+        tx.extend_from_slice(&[7; 32]);
+
+        tx.push(4);
+        // SetComputeUnitLimit(100_000), SetComputeUnitPrice(1_000_000 micro-lamports).
+        tx.extend_from_slice(&[3, 0, 5, 2]);
+        tx.extend_from_slice(&100_000u32.to_le_bytes());
+        tx.extend_from_slice(&[3, 0, 9, 3]);
+        tx.extend_from_slice(&1_000_000u64.to_le_bytes());
+        // Transfer from the wallet.
+        tx.extend_from_slice(&[2, 2, 0, 1, 12]);
+        tx.extend_from_slice(&2u32.to_le_bytes());
+        tx.extend_from_slice(&lamports.to_le_bytes());
+        tx.extend_from_slice(&[4, 1, 0, 1, 0xAB]);
+        tx.push(0); // no address table lookups
+        tx
+    }
+
+    fn policy() -> SignerPolicy {
+        SignerPolicy {
+            max_sol_out: 0.5,
+            allowed_programs: vec![SYSTEM_PROGRAM.to_string(), COMPUTE_BUDGET_PROGRAM.to_string(), JUPITER.to_string()],
+        }
+    }
+
+    #[test]
+    fn counts_transfers_and_fees() {
+        let wallet = bs58::encode(WALLET).into_string();
+        let sol_out = policy().check(&transaction(100_000_000, JUPITER), &wallet).unwrap();
+        // 0.1 SOL transferred, 5000 lamports signature fee, 100_000 units at 1 lamport.
+        assert!((sol_out - 0.100_105).abs() < 1e-12);
+
+        assert!(policy().check(&transaction(600_000_000, JUPITER), &wallet).is_err());
+    }
+
+    #[test]
+    fn sets_compute_budget() {
+        let wallet = bs58::encode(WALLET).into_string();
+        let mut tx = transaction(100_000_000, JUPITER);
+        set_compute_budget(&mut tx, Some(5_000_000), Some(200_000)).unwrap();
+        // 200_000 units at 5 lamports.
+        let sol_out = policy().check(&tx, &wallet).unwrap();
+        assert!((sol_out - 0.101_005).abs() < 1e-12);
+
+        set_compute_budget(&mut tx, None, Some(100_000)).unwrap();
+        let sol_out = policy().check(&tx, &wallet).unwrap();
+        assert!((sol_out - 0.100_505).abs() < 1e-12);
+    }
+
+    #[test]
+    fn refuses_other_programs() {
+        let wallet = bs58::encode(WALLET).into_string();
+        let error = policy().check(&transaction(1, "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo"), &wallet).unwrap_err();
+        assert!(error.contains("is not allowed"));
+    }
+}
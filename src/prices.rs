@@ -0,0 +1,91 @@
+//! Token prices in SOL, for the position watcher's exits.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+
+use crate::error::AppError;
+use crate::parser::WSOL_MINT;
+
+/// Where the position watcher gets prices from.
+pub trait PriceSource: Send + Sync + 'static {
+    /// SOL per whole token of each of `mints` that has a price.
+    fn prices_sol(&self, mints: &[String]) -> impl Future<Output = Result<HashMap<String, f64>, AppError>> + Send;
+}
+
+/// Prices from a Jupiter-compatible price API (`GET ?ids=<mints>&vsToken=<mint>`).
+#[derive(Clone)]
+pub struct JupiterPrices {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl JupiterPrices {
+    pub fn new(url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.to_string(),
+        }
+    }
+}
+
+impl PriceSource for JupiterPrices {
+    async fn prices_sol(&self, mints: &[String]) -> Result<HashMap<String, f64>, AppError> {
+        if mints.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let resp = self.http
+            .get(&self.url)
+            .query(&[("ids", mints.join(",")), ("vsToken", WSOL_MINT.to_string())])
+            .send()
+            .await
+            .map_err(|e| AppError::RequestError(format!("Price request failed: {}", e)))?;
+        let status = resp.status();
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(format!("Price request failed: {}", e)))?;
+        if !status.is_success() {
+            return Err(AppError::RequestError(format!("Price request failed with status {}: {}", status, body)));
+        }
+
+        let response: Value = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Invalid price response: {}", e)))?;
+        Ok(parse_prices(&response))
+    }
+}
+
+/// Reads `data.<mint>.price`, which the API gives as a string; mints without a price are left out.
+fn parse_prices(response: &Value) -> HashMap<String, f64> {
+    let Some(data) = response["data"].as_object() else {
+        return HashMap::new();
+    };
+    data.iter()
+        .filter_map(|(mint, entry)| {
+            let price = match &entry["price"] {
+                Value::String(price) => price.parse().ok()?,
+                price => price.as_f64()?,
+            };
+            (price > 0.0).then(|| (mint.clone(), price))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_prices() {
+        let response = json!({
+            "data": {
+                "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v": { "id": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "type": "derivedPrice", "price": "0.0068" },
+                "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R": null,
+            },
+            "timeTaken": 0.003,
+        });
+        let prices = parse_prices(&response);
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices["EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"], 0.0068);
+    }
+}
@@ -0,0 +1,252 @@
+//! Signing through a separate process, so the internet-facing API never holds the keypair.
+//!
+//! The signer process (`copy-trader-api signer-server`) listens on a Unix socket. Each
+//! connection carries one request and one response, both a line of JSON:
+//!
+//! - `{"method":"pubkey"}` → `{"pubkey":"<base58>"}`
+//! - `{"method":"sign_transaction","transaction":"<base64>"}` → `{"signature":"<base58>"}`
+//!
+//! or `{"error":"..."}`. Transactions are checked against the signer's `SignerPolicy` before
+//! they are signed.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+
+use crate::error::AppError;
+use crate::policy::SignerPolicy;
+use crate::signer::{self, Signer, SIGNATURE_LEN};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Requests are a transaction at most, which is well under this.
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum SignerRequest {
+    Pubkey,
+    SignTransaction { transaction: String },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SignerResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pubkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Client of a signer process.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    socket: PathBuf,
+    pubkey: String,
+}
+
+impl RemoteSigner {
+    /// Connects to the signer process at `socket` and asks for its wallet.
+    pub async fn connect(socket: &Path) -> Result<RemoteSigner, AppError> {
+        let mut remote = RemoteSigner { socket: socket.to_path_buf(), pubkey: String::new() };
+        remote.pubkey = remote.request(&SignerRequest::Pubkey).await?
+            .pubkey
+            .ok_or_else(|| AppError::ExecutionError("Signer did not return its address".to_string()))?;
+        Ok(remote)
+    }
+
+    pub fn pubkey(&self) -> &str {
+        &self.pubkey
+    }
+
+    /// The wallet's signature of `message`, the message of `tx`.
+    pub async fn sign(&self, tx: &[u8], message: &[u8]) -> Result<[u8; SIGNATURE_LEN], AppError> {
+        let response = self.request(&SignerRequest::SignTransaction { transaction: BASE64.encode(tx) }).await?;
+        let signature = response.signature
+            .and_then(|signature| bs58::decode(signature).into_vec().ok())
+            .and_then(|signature| Signature::from_slice(&signature).ok())
+            .ok_or_else(|| AppError::ExecutionError("Signer returned an invalid signature".to_string()))?;
+
+        // A signature that does not verify would only be rejected by the cluster later.
+        let pubkey: [u8; 32] = bs58::decode(&self.pubkey).into_vec().ok()
+            .and_then(|pubkey| pubkey.try_into().ok())
+            .ok_or_else(|| AppError::ExecutionError("Signer returned an invalid address".to_string()))?;
+        VerifyingKey::from_bytes(&pubkey)
+            .and_then(|key| key.verify(message, &signature))
+            .map_err(|_| AppError::ExecutionError("Signer's signature does not verify".to_string()))?;
+
+        Ok(signature.to_bytes())
+    }
+
+    async fn request(&self, request: &SignerRequest) -> Result<SignerResponse, AppError> {
+        let failed = |e: std::io::Error| AppError::ExecutionError(format!("Signer at {}: {}", self.socket.display(), e));
+
+        let exchange = async {
+            let mut stream = UnixStream::connect(&self.socket).await.map_err(failed)?;
+            let mut line = serde_json::to_string(request)?;
+            line.push('\n');
+            stream.write_all(line.as_bytes()).await.map_err(failed)?;
+
+            let mut response = String::new();
+            BufReader::new(stream).read_line(&mut response).await.map_err(failed)?;
+            serde_json::from_str::<SignerResponse>(&response)
+                .map_err(|e| AppError::ExecutionError(format!("Invalid signer response: {}", e)))
+        };
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, exchange).await
+            .map_err(|_| AppError::ExecutionError(format!("Signer at {} timed out", self.socket.display())))??;
+
+        match response.error {
+            Some(error) => Err(AppError::ExecutionError(format!("Signer: {}", error))),
+            None => Ok(response),
+        }
+    }
+}
+
+/// Serves signing requests for `signer` on `socket` until `shutdown` resolves.
+pub async fn serve(
+    socket: &Path,
+    signer: Signer,
+    policy: SignerPolicy,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), AppError> {
+    let failed = |e: std::io::Error| AppError::ServerError(format!("{}: {}", socket.display(), e));
+
+    // A socket left behind by a previous run would make bind fail; anything else is kept.
+    if fs::symlink_metadata(socket).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(socket).map_err(failed)?;
+    }
+    let listener = UnixListener::bind(socket).map_err(failed)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600)).map_err(failed)?;
+    info!(socket = %socket.display(), wallet = %signer.pubkey(), "Signer listening");
+
+    tokio::pin!(shutdown);
+    loop {
+        let stream = tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept signer connection");
+                    continue;
+                }
+            },
+        };
+        let (signer, policy) = (signer.clone(), policy.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &signer, &policy).await {
+                warn!(error = %e, "Signer connection failed");
+            }
+        });
+    }
+
+    let _ = fs::remove_file(socket);
+    info!("Signer stopped");
+    Ok(())
+}
+
+async fn handle_connection(stream: UnixStream, signer: &Signer, policy: &SignerPolicy) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    tokio::time::timeout(REQUEST_TIMEOUT, BufReader::new(reader).take(MAX_REQUEST_LEN).read_line(&mut line))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"))??;
+
+    let response = match serde_json::from_str::<SignerRequest>(&line) {
+        Ok(request) => respond(request, signer, policy).await
+            .unwrap_or_else(|error| SignerResponse { error: Some(error), ..Default::default() }),
+        Err(e) => SignerResponse { error: Some(format!("invalid request: {}", e)), ..Default::default() },
+    };
+
+    let mut line = serde_json::to_string(&response)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+/// The response to `request`, or the error to send back.
+async fn respond(request: SignerRequest, signer: &Signer, policy: &SignerPolicy) -> Result<SignerResponse, String> {
+    let pubkey = signer.pubkey();
+    match request {
+        SignerRequest::Pubkey => Ok(SignerResponse { pubkey: Some(pubkey), ..Default::default() }),
+        SignerRequest::SignTransaction { transaction } => {
+            let tx = BASE64.decode(transaction).map_err(|_| "transaction is not base64".to_string())?;
+            let sol_out = policy.check(&tx, &pubkey).map_err(|reason| {
+                warn!(%reason, "Refused to sign");
+                format!("refused to sign: {}", reason)
+            })?;
+
+            let signed = signer.sign_transaction(&tx).await.map_err(|e| e.to_string())?;
+            let (slot, _) = signer::signature_slot(&tx, &pubkey).map_err(|e| e.to_string())?;
+            info!(sol_out, "Signed transaction");
+            Ok(SignerResponse {
+                signature: Some(bs58::encode(&signed[slot..slot + SIGNATURE_LEN]).into_string()),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use std::sync::Arc;
+    use tokio::sync::oneshot;
+
+    const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+
+    /// A legacy transaction in which `wallet` transfers `lamports` to another account.
+    fn transfer(wallet: &str, lamports: u64) -> Vec<u8> {
+        let mut tx = vec![1];
+        tx.extend_from_slice(&[0; SIGNATURE_LEN]);
+        tx.extend_from_slice(&[1, 0, 1, 3]);
+        tx.extend_from_slice(&bs58::decode(wallet).into_vec().unwrap());
+        tx.extend_from_slice(&[1; 32]);
+        tx.extend_from_slice(&bs58::decode(SYSTEM_PROGRAM).into_vec().unwrap());
+        tx.extend_from_slice(&[7; 32]);
+        tx.extend_from_slice(&[1, 2, 2, 0, 1, 12]);
+        tx.extend_from_slice(&2u32.to_le_bytes());
+        tx.extend_from_slice(&lamports.to_le_bytes());
+        tx
+    }
+
+    #[tokio::test]
+    async fn signs_over_the_socket_within_policy() {
+        let socket = std::env::temp_dir().join(format!("signer-{}.sock", uuid::Uuid::new_v4()));
+        let signer = Signer::Local(Arc::new(SigningKey::from_bytes(&[9; 32])));
+        let wallet = signer.pubkey();
+        let policy = SignerPolicy { max_sol_out: 0.5, allowed_programs: vec![SYSTEM_PROGRAM.to_string()] };
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn({
+            let socket = socket.clone();
+            async move { serve(&socket, signer, policy, async { let _ = stopped.await; }).await }
+        });
+        while !socket.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let remote = RemoteSigner::connect(&socket).await.unwrap();
+        assert_eq!(remote.pubkey(), wallet);
+
+        let tx = transfer(&wallet, 100_000_000);
+        let (_, message) = signer::signature_slot(&tx, &wallet).unwrap();
+        let signature = remote.sign(&tx, message).await.unwrap();
+        let key = VerifyingKey::from_bytes(&bs58::decode(&wallet).into_vec().unwrap().try_into().unwrap()).unwrap();
+        assert!(key.verify(message, &Signature::from_bytes(&signature)).is_ok());
+
+        let tx = transfer(&wallet, 600_000_000);
+        let (_, message) = signer::signature_slot(&tx, &wallet).unwrap();
+        let refused = remote.sign(&tx, message).await.unwrap_err();
+        assert!(refused.to_string().contains("refused to sign"), "{}", refused);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!socket.exists());
+    }
+}
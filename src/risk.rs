@@ -0,0 +1,209 @@
+//! Account-wide risk controls: a kill switch that halts all copying regardless of each wallet's
+//! `is_enabled`, a limit on the loss realized per day, which halts copying when breached, and a
+//! limit on the SOL deployed in open positions at once.
+//!
+//! A halt stops the position watcher's exits too (see `watcher`): it stops every swap, so open
+//! positions are left as they are until copying resumes.
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::db::SupabaseClient;
+use crate::engine;
+use crate::error::AppError;
+use crate::models::{RiskHalt, RiskStatus, Transaction};
+
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    /// Realized loss, in SOL, after which copying halts for the day.
+    pub max_daily_loss_sol: Option<f64>,
+    /// Most SOL in open positions at once.
+    pub max_deployed_sol: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct HaltState {
+    active: Option<RiskHalt>,
+    /// When copying was last resumed. Losses realized before it do not count again.
+    resumed_at: Option<DateTime<Utc>>,
+}
+
+/// What the engine is told about the account's limits for one decision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiskCheck {
+    pub halted: bool,
+    pub deployable_sol: Option<f64>,
+}
+
+#[derive(Clone)]
+pub struct RiskGuard {
+    db: SupabaseClient,
+    limits: RiskLimits,
+    state: Arc<RwLock<HaltState>>,
+    /// Held while a halt or resume is recorded, so breaches found at once record one halt.
+    halting: Arc<Mutex<()>>,
+}
+
+impl RiskGuard {
+    /// Restores the halt state recorded in `risk_halts`, so a halt survives restarts.
+    pub async fn load(db: SupabaseClient, limits: RiskLimits) -> Result<RiskGuard, AppError> {
+        let state = match db.get_latest_halt().await? {
+            Some(halt) if halt.resumed_at.is_none() => {
+                warn!(reason = %halt.reason, "Copying is halted; resume it with POST /v1/risk/resume");
+                HaltState { active: Some(halt), resumed_at: None }
+            }
+            Some(halt) => HaltState { active: None, resumed_at: halt.resumed_at },
+            None => HaltState::default(),
+        };
+        Ok(RiskGuard { db, limits, state: Arc::new(RwLock::new(state)), halting: Arc::new(Mutex::new(())) })
+    }
+
+    pub fn active_halt(&self) -> Option<RiskHalt> {
+        self.state.read().expect("halt state lock poisoned").active.clone()
+    }
+
+    /// Halts all copying with `reason`. Halting while halted keeps the existing halt.
+    pub async fn halt(&self, reason: &str) -> Result<RiskHalt, AppError> {
+        let _halting = self.halting.lock().await;
+        if let Some(halt) = self.active_halt() {
+            return Ok(halt);
+        }
+        let halt = self.db.record_halt(reason).await?;
+        warn!(%reason, "Copying halted");
+        self.state.write().expect("halt state lock poisoned").active = Some(halt.clone());
+        Ok(halt)
+    }
+
+    pub async fn resume(&self) -> Result<(), AppError> {
+        let _halting = self.halting.lock().await;
+        self.db.resume_halts().await?;
+        let mut state = self.state.write().expect("halt state lock poisoned");
+        state.active = None;
+        state.resumed_at = Some(Utc::now());
+        info!("Copying resumed");
+        Ok(())
+    }
+
+    /// Checks the limits against the copy trades in `transactions`, halting on a breached loss
+    /// limit.
+    pub async fn check(&self, transactions: &[Transaction]) -> Result<RiskCheck, AppError> {
+        if self.active_halt().is_none() {
+            let since = loss_window_start(Utc::now(), self.resumed_at());
+            if let Some(reason) = breached_loss_limit(&self.limits, transactions, since) {
+                self.halt(&reason).await?;
+            }
+        }
+
+        Ok(RiskCheck {
+            halted: self.active_halt().is_some(),
+            deployable_sol: self.limits.max_deployed_sol
+                .map(|max| (max - engine::deployed_sol(transactions)).max(0.0)),
+        })
+    }
+
+    pub async fn status(&self) -> Result<RiskStatus, AppError> {
+        let transactions = self.db.get_transaction_history().await?;
+        let since = loss_window_start(Utc::now(), self.resumed_at());
+        Ok(RiskStatus {
+            halt: self.active_halt(),
+            max_daily_loss_sol: self.limits.max_daily_loss_sol,
+            max_deployed_sol: self.limits.max_deployed_sol,
+            realized_pnl_today_sol: engine::realized_pnl_since(&transactions, since),
+            deployed_sol: engine::deployed_sol(&transactions),
+        })
+    }
+
+    fn resumed_at(&self) -> Option<DateTime<Utc>> {
+        self.state.read().expect("halt state lock poisoned").resumed_at
+    }
+}
+
+/// Losses count from midnight UTC, or from the last resume if that was later today.
+fn loss_window_start(now: DateTime<Utc>, resumed_at: Option<DateTime<Utc>>) -> DateTime<Utc> {
+    let midnight = now.date_naive().and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+    resumed_at.map_or(midnight, |resumed_at| resumed_at.max(midnight))
+}
+
+/// The reason to halt with when the loss realized since `since` reaches `max_daily_loss_sol`.
+fn breached_loss_limit(limits: &RiskLimits, transactions: &[Transaction], since: DateTime<Utc>) -> Option<String> {
+    let max_loss = limits.max_daily_loss_sol?;
+    let loss = -engine::realized_pnl_since(transactions, since);
+    (loss >= max_loss).then(|| format!(
+        "Realized loss of {:.6} SOL today reached max_daily_loss_sol of {} SOL", loss, max_loss
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::{routing::get, Json, Router};
+    use chrono::TimeZone;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn transaction(kind: &str, amount: f64, price_sol: f64, hour: u32) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: "user".to_string(),
+            tracked_wallet_id: Some(Uuid::nil()),
+            signature: "sig".to_string(),
+            transaction_type: kind.to_string(),
+            token_address: "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R".to_string(),
+            amount,
+            price_sol,
+            timestamp: Utc.with_ymd_and_hms(2026, 3, 2, hour, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn halts_on_daily_loss() {
+        let limits = RiskLimits { max_daily_loss_sol: Some(0.04), max_deployed_sol: None };
+        // Bought 1000 for 0.1 SOL, then sold half for 0.02 at 10:00 (0.03 lost) and a quarter
+        // for 0.01 at 12:00 (0.015 lost).
+        let transactions = [
+            transaction("buy", 1000.0, 0.0001, 9),
+            transaction("sell", 500.0, 0.00004, 10),
+            transaction("stop_loss", 250.0, 0.00004, 12),
+        ];
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 13, 0, 0).unwrap();
+
+        let since = loss_window_start(now, None);
+        assert!(breached_loss_limit(&limits, &transactions, since).is_some());
+
+        // Resumed at 11:00: only the 0.015 SOL lost since counts.
+        let since = loss_window_start(now, Some(Utc.with_ymd_and_hms(2026, 3, 2, 11, 0, 0).unwrap()));
+        assert_eq!(breached_loss_limit(&limits, &transactions, since), None);
+
+        // Resumed yesterday: the whole day counts.
+        let since = loss_window_start(now, Some(Utc.with_ymd_and_hms(2026, 3, 1, 11, 0, 0).unwrap()));
+        assert!(breached_loss_limit(&limits, &transactions, since).is_some());
+    }
+
+    #[tokio::test]
+    async fn records_one_halt_for_breaches_at_once() {
+        let halts = Arc::new(AtomicUsize::new(0));
+        let counter = halts.clone();
+        let supabase = testing::serve(Router::new().route(
+            "/risk_halts",
+            get(|| async { Json(json!([])) }).post(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async {
+                    // Slow enough for the other breach to arrive while this one is recorded.
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Json(json!([{ "reason": "first", "halted_at": Utc::now(), "resumed_at": null }]))
+                }
+            }),
+        )).await;
+        let risk = RiskGuard::load(SupabaseClient::new(&supabase, "", "", "me"), RiskLimits::default()).await.unwrap();
+
+        let (first, second) = tokio::join!(risk.halt("first"), risk.halt("second"));
+        assert_eq!(first.unwrap().reason, "first");
+        assert_eq!(second.unwrap().reason, "first");
+        assert_eq!(halts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,14 +1,39 @@
 use axum::{
     extract::{Path, State},
+    http::{header, HeaderValue},
+    response::Response,
     Json,
 };
-use serde_json::json;
+use chrono::Utc;
+use serde_json::Value;
+use tracing::debug;
 use uuid::Uuid;
 
 use crate::db::SupabaseClient;
-use crate::models::{TrackedWallet, CopyTradeSettings, Transaction};
+use crate::executor::ComputeBudget;
+use crate::monitor::MonitorHandle;
+use crate::schedule;
+use crate::tokens::TokenMetadataService;
+use crate::models::{
+    TrackedWallet, CopyTradeSettings, CopyTradeSettingsPatch, TransactionWithToken,
+    TrackedWalletIdResponse, SettingsIdResponse, MessageResponse,
+};
 use crate::error::AppError;
 
+/// Marks responses of the pre-`/v1` routes as deprecated and points clients at the docs.
+pub async fn mark_deprecated(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(header::LINK, HeaderValue::from_static("</docs>; rel=\"deprecation\""));
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/tracked_wallets",
+    tag = "tracked_wallets",
+    responses((status = 200, description = "All tracked wallets of the user", body = [TrackedWallet])),
+)]
 pub async fn get_tracked_wallets(
     State(client): State<SupabaseClient>,
 ) -> Result<Json<Vec<TrackedWallet>>, AppError> {
@@ -16,82 +41,251 @@ pub async fn get_tracked_wallets(
     Ok(Json(wallets))
 }
 
+#[utoipa::path(
+    post,
+    path = "/tracked_wallets",
+    tag = "tracked_wallets",
+    request_body(content = TrackedWallet, description = "Only `wallet_address` and `is_active` are used"),
+    responses(
+        (status = 200, description = "Wallet is now tracked", body = TrackedWalletIdResponse),
+        (status = 400, description = "Invalid request body", body = String),
+    ),
+)]
 pub async fn add_tracked_wallet(
     State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
     Json(wallet): Json<TrackedWallet>,
-) -> Result<Json<serde_json::Value>, AppError> {
+) -> Result<Json<TrackedWalletIdResponse>, AppError> {
     let result = client.add_tracked_wallet(wallet).await?;
-    Ok(Json(json!({ "success": true, "tracked_wallet_id": result })))
+    monitor.refresh();
+    Ok(Json(TrackedWalletIdResponse { success: true, tracked_wallet_id: result }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/tracked_wallets/archive/{wallet_address}",
+    tag = "tracked_wallets",
+    params(("wallet_address" = String, Path, description = "Address of the tracked wallet")),
+    responses(
+        (status = 200, description = "Wallet archived", body = MessageResponse),
+        (status = 500, description = "No tracked wallet with that address", body = String),
+    ),
+)]
 pub async fn archive_tracked_wallet(
     State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
     Path(wallet_address): Path<String>,
-) -> Result<Json<serde_json::Value>, AppError> {
+) -> Result<Json<MessageResponse>, AppError> {
     let result = client.archive_tracked_wallet(&wallet_address).await?;
-    Ok(Json(json!({ "success": true, "message": result })))
+    monitor.refresh();
+    Ok(Json(MessageResponse { success: true, message: result }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/tracked_wallets/unarchive/{wallet_address}",
+    tag = "tracked_wallets",
+    params(("wallet_address" = String, Path, description = "Address of the tracked wallet")),
+    responses(
+        (status = 200, description = "Wallet unarchived", body = MessageResponse),
+        (status = 500, description = "No tracked wallet with that address", body = String),
+    ),
+)]
 pub async fn unarchive_tracked_wallet(
     State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
     Path(wallet_address): Path<String>,
-) -> Result<Json<serde_json::Value>, AppError> {
+) -> Result<Json<MessageResponse>, AppError> {
     let result = client.unarchive_tracked_wallet(&wallet_address).await?;
-    Ok(Json(json!({ "success": true, "message": result })))
+    monitor.refresh();
+    Ok(Json(MessageResponse { success: true, message: result }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/tracked_wallets/{wallet_address}",
+    tag = "tracked_wallets",
+    params(("wallet_address" = String, Path, description = "Address of the tracked wallet")),
+    responses(
+        (status = 200, description = "Wallet deleted", body = MessageResponse),
+        (status = 500, description = "No tracked wallet with that address", body = String),
+    ),
+)]
 pub async fn delete_tracked_wallet(
     State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
     Path(wallet_address): Path<String>,
-) -> Result<Json<serde_json::Value>, AppError> {
+) -> Result<Json<MessageResponse>, AppError> {
     let result = client.delete_tracked_wallet(&wallet_address).await?;
-    Ok(Json(json!({ "success": true, "message": result })))
+    monitor.refresh();
+    Ok(Json(MessageResponse { success: true, message: result }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/tracked_wallets/update",
+    tag = "tracked_wallets",
+    request_body(content = TrackedWallet, description = "`id` is required and selects the wallet to update"),
+    responses(
+        (status = 200, description = "Wallet updated", body = TrackedWalletIdResponse),
+        (status = 400, description = "`id` missing or invalid body", body = String),
+    ),
+)]
 pub async fn update_tracked_wallet(
     State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
     Json(update): Json<TrackedWallet>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    println!("update_tracked_wallet() called");
+) -> Result<Json<TrackedWalletIdResponse>, AppError> {
     let result = client.update_tracked_wallet(update).await?;
-    println!("update_tracked_wallet() result: {:?}", result);
-    Ok(Json(json!({ "success": true, "tracked_wallet_id": result })))
+    monitor.refresh();
+    debug!(tracked_wallet_id = %result, "Updated tracked wallet");
+    Ok(Json(TrackedWalletIdResponse { success: true, tracked_wallet_id: result }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/copy_trade_settings",
+    tag = "copy_trade_settings",
+    responses((status = 200, description = "Copy trade settings of every tracked wallet", body = [CopyTradeSettings])),
+)]
 pub async fn get_copy_trade_settings(
     State(client): State<SupabaseClient>,
 ) -> Result<Json<Vec<CopyTradeSettings>>, AppError> {
-    let settings = client.get_copy_trade_settings().await?;
+    let now = Utc::now();
+    let settings = client.get_copy_trade_settings().await?
+        .into_iter()
+        .map(|settings| schedule::with_active_now(settings, now))
+        .collect();
     Ok(Json(settings))
 }
 
+#[utoipa::path(
+    post,
+    path = "/copy_trade_settings",
+    tag = "copy_trade_settings",
+    request_body = CopyTradeSettings,
+    responses(
+        (status = 200, description = "Settings created", body = SettingsIdResponse),
+        (status = 400, description = "Invalid request body", body = String),
+    ),
+)]
 pub async fn create_copy_trade_settings(
     State(client): State<SupabaseClient>,
+    State(defaults): State<ComputeBudget>,
     Json(settings): Json<CopyTradeSettings>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    let result = client.create_copy_trade_settings(settings).await?;
-    Ok(Json(json!({ "success": true, "settings_id": result })))
+) -> Result<Json<SettingsIdResponse>, AppError> {
+    let result = client.create_copy_trade_settings(settings, &defaults).await?;
+    Ok(Json(SettingsIdResponse { success: true, settings_id: result }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/copy_trade_settings",
+    tag = "copy_trade_settings",
+    request_body(content = CopyTradeSettings, description = "`tracked_wallet_id` selects the settings to update; the other fields sent are overwritten and those left out are kept"),
+    responses(
+        (status = 200, description = "Settings updated", body = SettingsIdResponse),
+        (status = 400, description = "Invalid request body", body = String),
+    ),
+)]
 pub async fn update_copy_trade_settings(
     State(client): State<SupabaseClient>,
-    Json(settings): Json<CopyTradeSettings>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    let result = client.update_copy_trade_settings(settings).await?;
-    Ok(Json(json!({ "success": true, "settings_id": result })))
+    State(defaults): State<ComputeBudget>,
+    Json(body): Json<Value>,
+) -> Result<Json<SettingsIdResponse>, AppError> {
+    // The body must still be complete settings, but only the fields it has are written: the
+    // optional ones default when left out, and clients written before they existed would
+    // otherwise turn off paper trading and clear every limit.
+    let settings: CopyTradeSettings = serde_json::from_value(body.clone())?;
+    let patch: CopyTradeSettingsPatch = serde_json::from_value(body)?;
+    let updated = client.patch_copy_trade_settings(settings.tracked_wallet_id, patch, &defaults).await?;
+    let settings_id = updated.id
+        .ok_or_else(|| AppError::DatabaseError("Failed to update copy trade settings".to_string()))?;
+    Ok(Json(SettingsIdResponse { success: true, settings_id }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/copy_trade_settings/{tracked_wallet_id}",
+    tag = "copy_trade_settings",
+    params(("tracked_wallet_id" = Uuid, Path, description = "Tracked wallet whose settings are deleted")),
+    responses(
+        (status = 200, description = "Settings deleted", body = MessageResponse),
+        (status = 500, description = "Settings could not be deleted", body = String),
+    ),
+)]
 pub async fn delete_copy_trade_settings(
     State(client): State<SupabaseClient>,
     Path(tracked_wallet_id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, AppError> {
+) -> Result<Json<MessageResponse>, AppError> {
     let result = client.delete_copy_trade_settings(tracked_wallet_id).await?;
-    Ok(Json(json!({ "success": true, "message": result })))
+    Ok(Json(MessageResponse { success: true, message: result }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/transaction_history",
+    tag = "transactions",
+    responses((status = 200, description = "Every copy trade made for the user, with token metadata", body = [TransactionWithToken])),
+)]
 pub async fn get_transaction_history(
     State(client): State<SupabaseClient>,
-) -> Result<Json<Vec<Transaction>>, AppError> {
+    State(tokens): State<TokenMetadataService>,
+) -> Result<Json<Vec<TransactionWithToken>>, AppError> {
     let transactions = client.get_transaction_history().await?;
-    Ok(Json(transactions))
-}
\ No newline at end of file
+    Ok(Json(tokens.with_tokens(transactions).await))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::{routing::patch, Router};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn put_keeps_settings_it_does_not_send() {
+        let tracked_wallet_id = Uuid::new_v4();
+        let settings_id = Uuid::new_v4();
+        let written = Arc::new(Mutex::new(Value::Null));
+        let changes = written.clone();
+        let supabase = testing::serve(Router::new().route("/copy_trade_settings", patch(move |body: String| {
+            let mut row: Value = serde_json::from_str(&body).unwrap();
+            *changes.lock().unwrap() = row.clone();
+            row["id"] = json!(settings_id);
+            row["tracked_wallet_id"] = json!(tracked_wallet_id);
+            async move { axum::Json(json!([row])) }
+        }))).await;
+
+        // A body from before paper trading and the limits existed.
+        let body = json!({
+            "tracked_wallet_id": tracked_wallet_id,
+            "is_enabled": true,
+            "trade_amount_sol": 0.2,
+            "max_slippage": 1.0,
+            "max_open_positions": 3,
+            "allowed_tokens": null,
+            "use_allowed_tokens_list": false,
+            "allow_additional_buys": false,
+            "match_sell_percentage": false,
+            "min_sol_balance": 0.01,
+        });
+        let client = SupabaseClient::new(&supabase, "", "", "me");
+        let Json(response) = update_copy_trade_settings(State(client), State(ComputeBudget::default()), Json(body)).await.unwrap();
+        assert_eq!(response.settings_id, settings_id);
+
+        let written = written.lock().unwrap();
+        assert_eq!(written["trade_amount_sol"], 0.2);
+        for kept in ["paper_trading", "stop_loss_pct", "max_token_exposure_sol", "schedule", "sizing", "reject_mint_authority", "priority_fee", "bundle_tip_lamports"] {
+            assert!(written.get(kept).is_none(), "{} was overwritten", kept);
+        }
+    }
+
+    #[tokio::test]
+    async fn put_requires_complete_settings() {
+        let client = SupabaseClient::new("http://127.0.0.1:1", "", "", "me");
+        let body = json!({ "tracked_wallet_id": Uuid::new_v4(), "is_enabled": false });
+        assert!(matches!(update_copy_trade_settings(State(client), State(ComputeBudget::default()), Json(body)).await, Err(AppError::BadRequest(_))));
+    }
+}
@@ -0,0 +1,190 @@
+//! Minimal Solana JSON-RPC client over HTTP.
+
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::error::AppError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Not seen by the node yet.
+    Pending,
+    /// Landed but failed; holds the transaction error.
+    Failed(String),
+    /// Landed successfully at the given commitment (`processed`, `confirmed` or `finalized`).
+    Landed(String),
+}
+
+#[derive(Clone)]
+pub struct SolanaRpc {
+    http: reqwest::Client,
+    url: String,
+    commitment: String,
+}
+
+impl SolanaRpc {
+    pub fn new(url: &str, commitment: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.to_string(),
+            commitment: commitment.to_string(),
+        }
+    }
+
+    /// Sends a single JSON-RPC request and returns its `result`.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, AppError> {
+        debug!(method, "Solana RPC request");
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let resp = self.http
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.to_string())
+            .send()
+            .await
+            .map_err(|e| AppError::RpcError(format!("{} failed: {}", method, e)))?;
+
+        let status = resp.status();
+        let body = resp.text().await
+            .map_err(|e| AppError::RpcError(format!("{} failed: {}", method, e)))?;
+        if !status.is_success() {
+            return Err(AppError::RpcError(format!("{} failed with status {}: {}", method, status, body)));
+        }
+
+        let mut response: Value = serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Invalid {} response: {}", method, e)))?;
+        if let Some(error) = response.get("error") {
+            return Err(AppError::RpcError(format!("{} failed: {}", method, error)));
+        }
+
+        Ok(response["result"].take())
+    }
+
+    /// `getTransaction` in `json` encoding. `None` while the transaction is not yet available
+    /// at the configured commitment.
+    pub async fn get_transaction(&self, signature: &str) -> Result<Option<Value>, AppError> {
+        let result = self.call("getTransaction", json!([
+            signature,
+            {
+                "encoding": "json",
+                "maxSupportedTransactionVersion": 0,
+                // `processed` is not supported by getTransaction.
+                "commitment": if self.commitment == "processed" { "confirmed" } else { self.commitment.as_str() },
+            },
+        ])).await?;
+
+        Ok((!result.is_null()).then_some(result))
+    }
+
+    /// SOL balance of `address`, in lamports.
+    pub async fn get_balance(&self, address: &str) -> Result<u64, AppError> {
+        let result = self.call("getBalance", json!([
+            address,
+            { "commitment": self.commitment },
+        ])).await?;
+
+        result["value"].as_u64()
+            .ok_or_else(|| AppError::RpcError(format!("Unexpected getBalance result: {}", result)))
+    }
+
+    /// Token accounts owned by `owner` under the SPL Token program `program_id`, in
+    /// `jsonParsed` encoding.
+    pub async fn get_token_accounts(&self, owner: &str, program_id: &str) -> Result<Vec<Value>, AppError> {
+        let mut result = self.call("getTokenAccountsByOwner", json!([
+            owner,
+            { "programId": program_id },
+            { "encoding": "jsonParsed", "commitment": self.commitment },
+        ])).await?;
+
+        match result["value"].take() {
+            Value::Array(accounts) => Ok(accounts),
+            _ => Err(AppError::RpcError(format!("Unexpected getTokenAccountsByOwner result: {}", result))),
+        }
+    }
+
+    /// An account in `jsonParsed` encoding. `None` when it does not exist.
+    pub async fn get_parsed_account(&self, address: &str) -> Result<Option<Value>, AppError> {
+        let mut result = self.call("getAccountInfo", json!([
+            address,
+            { "encoding": "jsonParsed", "commitment": self.commitment },
+        ])).await?;
+
+        let account = result["value"].take();
+        Ok((!account.is_null()).then_some(account))
+    }
+
+    /// The base64-encoded data of an account. `None` when it does not exist.
+    pub async fn get_account_data(&self, address: &str) -> Result<Option<String>, AppError> {
+        let result = self.call("getAccountInfo", json!([
+            address,
+            { "encoding": "base64", "commitment": self.commitment },
+        ])).await?;
+
+        Ok(result["value"]["data"][0].as_str().map(str::to_string))
+    }
+
+    /// The largest token accounts of `mint`, up to 20, largest first.
+    pub async fn get_token_largest_accounts(&self, mint: &str) -> Result<Vec<Value>, AppError> {
+        let mut result = self.call("getTokenLargestAccounts", json!([
+            mint,
+            { "commitment": self.commitment },
+        ])).await?;
+
+        match result["value"].take() {
+            Value::Array(accounts) => Ok(accounts),
+            _ => Err(AppError::RpcError(format!("Unexpected getTokenLargestAccounts result: {}", result))),
+        }
+    }
+
+    /// Compute unit prices, in micro-lamports, paid in recent slots by transactions writing to
+    /// all of `accounts`; one per slot.
+    pub async fn get_recent_prioritization_fees(&self, accounts: &[String]) -> Result<Vec<u64>, AppError> {
+        let result = self.call("getRecentPrioritizationFees", json!([accounts])).await?;
+
+        result.as_array()
+            .map(|slots| slots.iter().filter_map(|slot| slot["prioritizationFee"].as_u64()).collect())
+            .ok_or_else(|| AppError::RpcError(format!("Unexpected getRecentPrioritizationFees result: {}", result)))
+    }
+
+    /// Submits a signed, base64-encoded transaction and returns its signature.
+    pub async fn send_transaction(&self, tx_base64: &str) -> Result<String, AppError> {
+        let result = self.call("sendTransaction", json!([
+            tx_base64,
+            { "encoding": "base64", "preflightCommitment": self.commitment, "maxRetries": 3 },
+        ])).await?;
+
+        result.as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AppError::RpcError(format!("Unexpected sendTransaction result: {}", result)))
+    }
+
+    /// Whether transactions built on `blockhash` can still land.
+    pub async fn is_blockhash_valid(&self, blockhash: &str) -> Result<bool, AppError> {
+        let result = self.call("isBlockhashValid", json!([
+            blockhash,
+            { "commitment": self.commitment },
+        ])).await?;
+
+        result["value"].as_bool()
+            .ok_or_else(|| AppError::RpcError(format!("Unexpected isBlockhashValid result: {}", result)))
+    }
+
+    pub async fn get_signature_status(&self, signature: &str) -> Result<SignatureStatus, AppError> {
+        let result = self.call("getSignatureStatuses", json!([[signature]])).await?;
+        let status = &result["value"][0];
+        if status.is_null() {
+            return Ok(SignatureStatus::Pending);
+        }
+        if !status["err"].is_null() {
+            return Ok(SignatureStatus::Failed(status["err"].to_string()));
+        }
+        Ok(SignatureStatus::Landed(status["confirmationStatus"].as_str().unwrap_or("processed").to_string()))
+    }
+}
@@ -0,0 +1,177 @@
+//! Assesses how much a token looks like a rug before a buy of it is copied: its mint and freeze
+//! authorities and holder concentration from the mint over RPC, and the size and age of its SOL
+//! pools from a DexScreener-compatible pool API.
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::models::TokenRisk;
+use crate::parser::WSOL_MINT;
+use crate::rpc::SolanaRpc;
+
+/// How long an assessment is reused before the token is assessed again.
+const MAX_AGE_SECS: i64 = 60;
+/// Holdings of this many of the largest token accounts make up `top_holders_pct`.
+const TOP_HOLDERS: usize = 10;
+
+#[derive(Clone)]
+pub struct SafetyService {
+    rpc: SolanaRpc,
+    http: reqwest::Client,
+    pool_api_url: String,
+    cached: Arc<Mutex<HashMap<String, TokenRisk>>>,
+}
+
+impl SafetyService {
+    pub fn new(rpc: SolanaRpc, pool_api_url: &str) -> Self {
+        Self {
+            rpc,
+            http: reqwest::Client::new(),
+            pool_api_url: pool_api_url.trim_end_matches('/').to_string(),
+            cached: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The assessment of `mint`, made again once the cached one is older than `MAX_AGE_SECS`.
+    pub async fn assess(&self, mint: &str) -> Result<TokenRisk, AppError> {
+        if let Some(risk) = self.cached.lock().await.get(mint) {
+            if Utc::now() - risk.assessed_at < Duration::seconds(MAX_AGE_SECS) {
+                return Ok(risk.clone());
+            }
+        }
+
+        let risk = self.fetch(mint).await?;
+        self.cached.lock().await.insert(mint.to_string(), risk.clone());
+        Ok(risk)
+    }
+
+    async fn fetch(&self, mint: &str) -> Result<TokenRisk, AppError> {
+        if bs58::decode(mint).into_vec().map_or(true, |bytes| bytes.len() != 32) {
+            return Err(AppError::BadRequest(format!("{} is not an address", mint)));
+        }
+        let account = self.rpc.get_parsed_account(mint).await?
+            .ok_or_else(|| AppError::NotFound(format!("Token {} not found", mint)))?;
+        let largest = self.rpc.get_token_largest_accounts(mint).await?;
+        let (liquidity_sol, pool_created_at) = parse_pools(&self.fetch_pools(mint).await?, mint);
+
+        let mut risk = parse_mint(&account, &largest)
+            .ok_or_else(|| AppError::BadRequest(format!("{} is not a token mint", mint)))?;
+        risk.mint = mint.to_string();
+        risk.liquidity_sol = liquidity_sol;
+        risk.pool_created_at = pool_created_at;
+        Ok(risk)
+    }
+
+    async fn fetch_pools(&self, mint: &str) -> Result<Value, AppError> {
+        let resp = self.http
+            .get(format!("{}/{}", self.pool_api_url, mint))
+            .send()
+            .await
+            .map_err(|e| AppError::RequestError(format!("Pool request failed: {}", e)))?;
+        let status = resp.status();
+        let body = resp.text().await
+            .map_err(|e| AppError::RequestError(format!("Pool request failed: {}", e)))?;
+        if !status.is_success() {
+            return Err(AppError::RequestError(format!("Pool request failed with status {}: {}", status, body)));
+        }
+        serde_json::from_str(&body)
+            .map_err(|e| AppError::JsonParseError(format!("Invalid pool response: {}", e)))
+    }
+}
+
+/// Reads a `jsonParsed` mint account and its largest token accounts. `None` if the account is
+/// not a mint.
+fn parse_mint(account: &Value, largest: &[Value]) -> Option<TokenRisk> {
+    let data = &account["data"]["parsed"];
+    if data["type"] != "mint" {
+        return None;
+    }
+    let info = &data["info"];
+    let decimals = u8::try_from(info["decimals"].as_u64()?).ok()?;
+    let supply = info["supply"].as_str()?.parse::<f64>().ok()? / 10f64.powi(i32::from(decimals));
+    let top_holdings: f64 = largest.iter()
+        .take(TOP_HOLDERS)
+        .filter_map(|holder| holder["uiAmountString"].as_str()?.parse::<f64>().ok())
+        .sum();
+
+    Some(TokenRisk {
+        mint: String::new(),
+        mint_authority: info["mintAuthority"].as_str().map(str::to_string),
+        freeze_authority: info["freezeAuthority"].as_str().map(str::to_string),
+        supply,
+        decimals,
+        top_holders_pct: if supply > 0.0 { top_holdings / supply * 100.0 } else { 0.0 },
+        liquidity_sol: None,
+        pool_created_at: None,
+        assessed_at: Utc::now(),
+    })
+}
+
+/// The SOL in the pools pairing `mint` with SOL and when the oldest of them was created. Both
+/// are `None` when there is no such pool.
+fn parse_pools(response: &Value, mint: &str) -> (Option<f64>, Option<DateTime<Utc>>) {
+    let mut liquidity_sol = None;
+    let mut created_at: Option<DateTime<Utc>> = None;
+    for pair in response["pairs"].as_array().into_iter().flatten() {
+        let sol_side = match (pair["baseToken"]["address"].as_str(), pair["quoteToken"]["address"].as_str()) {
+            (Some(base), Some(WSOL_MINT)) if base == mint => "quote",
+            (Some(WSOL_MINT), Some(quote)) if quote == mint => "base",
+            _ => continue,
+        };
+        let Some(sol) = pair["liquidity"][sol_side].as_f64() else {
+            continue;
+        };
+        *liquidity_sol.get_or_insert(0.0) += sol;
+        if let Some(created) = pair["pairCreatedAt"].as_i64().and_then(DateTime::from_timestamp_millis) {
+            created_at = Some(created_at.map_or(created, |oldest| oldest.min(created)));
+        }
+    }
+    (liquidity_sol, created_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MINT: &str = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R";
+
+    #[test]
+    fn reads_mint_and_pools() {
+        let account = json!({
+            "data": { "program": "spl-token", "parsed": { "type": "mint", "info": {
+                "decimals": 6,
+                "freezeAuthority": null,
+                "isInitialized": true,
+                "mintAuthority": "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU",
+                "supply": "1000000000000",
+            } } },
+        });
+        let largest = [
+            json!({ "address": "a", "amount": "400000000000", "decimals": 6, "uiAmount": 400000.0, "uiAmountString": "400000" }),
+            json!({ "address": "b", "amount": "50000000000", "decimals": 6, "uiAmount": 50000.0, "uiAmountString": "50000" }),
+        ];
+        let risk = parse_mint(&account, &largest).unwrap();
+        assert_eq!(risk.mint_authority.as_deref(), Some("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU"));
+        assert_eq!(risk.freeze_authority, None);
+        assert_eq!(risk.supply, 1_000_000.0);
+        assert!((risk.top_holders_pct - 45.0).abs() < 1e-9);
+        assert!(parse_mint(&json!({ "data": { "parsed": { "type": "account", "info": {} } } }), &[]).is_none());
+
+        let response = json!({
+            "pairs": [
+                { "baseToken": { "address": MINT }, "quoteToken": { "address": WSOL_MINT }, "liquidity": { "usd": 3000.0, "base": 10000.0, "quote": 12.5 }, "pairCreatedAt": 1_772_445_600_000i64 },
+                { "baseToken": { "address": WSOL_MINT }, "quoteToken": { "address": MINT }, "liquidity": { "usd": 600.0, "base": 2.5, "quote": 2000.0 }, "pairCreatedAt": 1_772_442_000_000i64 },
+                { "baseToken": { "address": MINT }, "quoteToken": { "address": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v" }, "liquidity": { "usd": 9000.0, "base": 1.0, "quote": 9000.0 }, "pairCreatedAt": 1 },
+            ],
+        });
+        let (liquidity_sol, created_at) = parse_pools(&response, MINT);
+        assert_eq!(liquidity_sol, Some(15.0));
+        assert_eq!(created_at, DateTime::from_timestamp_millis(1_772_442_000_000));
+        assert_eq!(parse_pools(&json!({ "pairs": null }), MINT), (None, None));
+    }
+}
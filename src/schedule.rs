@@ -0,0 +1,128 @@
+//! Weekly windows during which a tracked wallet's buys are copied. Outside of them its sells
+//! are still copied, so positions opened inside a window can be closed.
+
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::error::AppError;
+use crate::models::{CopyTradeSettings, ScheduleWindow};
+
+/// Whether `now` falls inside one of the settings' schedule windows. Settings without a schedule
+/// are always in schedule; a schedule that does not parse never is.
+pub fn in_schedule(settings: &CopyTradeSettings, now: DateTime<Utc>) -> bool {
+    let Some(windows) = &settings.schedule else {
+        return true;
+    };
+    let Ok(tz) = timezone(settings.schedule_timezone.as_deref()) else {
+        return false;
+    };
+    let local = now.with_timezone(&tz);
+    windows.iter().any(|window| {
+        window_contains(window, local.weekday(), local.time()).unwrap_or(false)
+    })
+}
+
+/// Whether buys are copied at `now`: `is_enabled` is on and the schedule allows it.
+pub fn is_active(settings: &CopyTradeSettings, now: DateTime<Utc>) -> bool {
+    settings.is_enabled && in_schedule(settings, now)
+}
+
+/// Fills in `active_now` of settings about to be returned by the API.
+pub fn with_active_now(mut settings: CopyTradeSettings, now: DateTime<Utc>) -> CopyTradeSettings {
+    settings.active_now = Some(is_active(&settings, now));
+    settings
+}
+
+/// Rejects windows or a timezone that do not parse.
+pub fn validate(windows: Option<&[ScheduleWindow]>, schedule_timezone: Option<&str>) -> Result<(), AppError> {
+    timezone(schedule_timezone)?;
+    for window in windows.unwrap_or_default() {
+        window_contains(window, Weekday::Mon, NaiveTime::MIN)?;
+    }
+    Ok(())
+}
+
+fn timezone(name: Option<&str>) -> Result<Tz, AppError> {
+    name.map_or(Ok(Tz::UTC), |name| {
+        name.parse().map_err(|_| AppError::BadRequest(format!("Unknown schedule_timezone {}", name)))
+    })
+}
+
+/// Whether the window is open at `time` on `day`, local time. A window whose `end` is not after
+/// its `start` runs past midnight, into the day after each of its `days`.
+fn window_contains(window: &ScheduleWindow, day: Weekday, time: NaiveTime) -> Result<bool, AppError> {
+    let start = parse_time(&window.start)?;
+    let end = parse_time(&window.end)?;
+    let days = window.days.iter()
+        .map(|day| day.parse::<Weekday>().map_err(|_| AppError::BadRequest(format!("Unknown schedule day {}", day))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let opens_on = |day: Weekday| days.is_empty() || days.contains(&day);
+
+    Ok(if start < end {
+        opens_on(day) && time >= start && time < end
+    } else {
+        (opens_on(day) && time >= start) || (opens_on(day.pred()) && time < end)
+    })
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, AppError> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| AppError::BadRequest(format!("Schedule time {} is not HH:MM", time)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn window(days: &[&str], start: &str, end: &str) -> ScheduleWindow {
+        ScheduleWindow {
+            days: days.iter().map(|day| day.to_string()).collect(),
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+
+    #[test]
+    fn checks_windows_in_local_time() {
+        let weekdays = window(&["mon", "tue", "wed", "thu", "fri"], "09:00", "17:00");
+        let overnight = window(&["sat"], "22:00", "02:00");
+        let at = |day: Weekday, time: &str| window_contains(&weekdays, day, parse_time(time).unwrap()).unwrap();
+        assert!(at(Weekday::Mon, "09:00"));
+        assert!(!at(Weekday::Mon, "17:00"));
+        assert!(!at(Weekday::Sat, "12:00"));
+
+        let at = |day: Weekday, time: &str| window_contains(&overnight, day, parse_time(time).unwrap()).unwrap();
+        assert!(at(Weekday::Sat, "23:00"));
+        assert!(at(Weekday::Sun, "01:59"));
+        assert!(!at(Weekday::Sat, "01:00"));
+
+        // 2026-03-02 is a Monday; 08:30 UTC is 09:30 in Berlin, 03:30 in New York.
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 8, 30, 0).unwrap();
+        let mut settings: CopyTradeSettings = serde_json::from_value(serde_json::json!({
+            "id": null,
+            "user_id": null,
+            "tracked_wallet_id": "00000000-0000-0000-0000-000000000000",
+            "is_enabled": true,
+            "trade_amount_sol": 0.1,
+            "max_slippage": 1.0,
+            "max_open_positions": 5,
+            "allowed_tokens": null,
+            "use_allowed_tokens_list": false,
+            "allow_additional_buys": false,
+            "match_sell_percentage": false,
+            "min_sol_balance": 0.0,
+            "schedule": [weekdays],
+            "schedule_timezone": "Europe/Berlin",
+            "created_at": null,
+            "updated_at": null,
+        })).unwrap();
+        assert!(is_active(&settings, now));
+        settings.schedule_timezone = Some("America/New_York".to_string());
+        assert!(!is_active(&settings, now));
+
+        assert!(validate(Some(&[window(&["someday"], "09:00", "17:00")]), None).is_err());
+        assert!(validate(Some(&[window(&[], "9am", "17:00")]), None).is_err());
+        assert!(validate(None, Some("Mars/Olympus_Mons")).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::signal;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// Resolves once SIGINT (Ctrl+C) or, on unix, SIGTERM is received.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = signal::ctrl_c().await {
+            warn!(error = %e, "Failed to listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// Background work (queued notifications, transaction writes, subscriptions) that must be
+/// given a chance to finish before the process exits.
+#[derive(Clone, Default)]
+pub struct BackgroundTasks {
+    tracker: TaskTracker,
+    token: CancellationToken,
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns a task that shutdown waits for. Long-running tasks should watch
+    /// `shutdown_token()` and flush whatever they have queued once it is cancelled.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(task);
+    }
+
+    /// Cancelled when the process starts shutting down.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Signals shutdown and waits up to `deadline` for every spawned task to finish.
+    pub async fn shutdown(&self, deadline: Duration) {
+        self.token.cancel();
+        self.tracker.close();
+
+        if tokio::time::timeout(deadline, self.tracker.wait()).await.is_err() {
+            warn!(
+                pending = self.tracker.len(),
+                "Background tasks did not finish within {:?}; exiting anyway", deadline
+            );
+        } else {
+            info!("Background tasks finished");
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Signs serialized Solana transactions for the trading wallet.
+
+use ed25519_dalek::{Signer as _, SigningKey};
+use std::fmt;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+use crate::error::AppError;
+#[cfg(unix)]
+use crate::remote_signer::RemoteSigner;
+
+pub const SIGNATURE_LEN: usize = 64;
+const PUBKEY_LEN: usize = 32;
+
+#[derive(Clone)]
+pub enum Signer {
+    /// A keypair held in memory.
+    Local(Arc<SigningKey>),
+    /// A signer process holding the keypair, reached over a Unix socket.
+    #[cfg(unix)]
+    Remote(Arc<RemoteSigner>),
+}
+
+/// Only the address is ever printed.
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Signer").field(&self.pubkey()).finish()
+    }
+}
+
+impl Signer {
+    /// Parses a keypair as exported by wallets (base58 of the 64 secret + public key bytes) or
+    /// as stored by `solana-keygen` (a JSON array of those bytes).
+    pub fn from_keypair(keypair: &str) -> Result<Signer, AppError> {
+        Signer::from_keypair_bytes(&*parse_keypair(keypair)?)
+    }
+
+    pub fn from_keypair_bytes(bytes: &[u8; 64]) -> Result<Signer, AppError> {
+        let key = SigningKey::from_keypair_bytes(bytes)
+            .map_err(|_| AppError::ConfigError("Invalid keypair: the public key does not match the secret key".to_string()))?;
+        Ok(Signer::Local(Arc::new(key)))
+    }
+
+    /// Base58 address of the wallet.
+    pub fn pubkey(&self) -> String {
+        match self {
+            Signer::Local(key) => bs58::encode(key.verifying_key().as_bytes()).into_string(),
+            #[cfg(unix)]
+            Signer::Remote(remote) => remote.pubkey().to_string(),
+        }
+    }
+
+    /// Adds the wallet's signature to a serialized (legacy or versioned) transaction.
+    pub async fn sign_transaction(&self, tx: &[u8]) -> Result<Vec<u8>, AppError> {
+        let (slot, message) = signature_slot(tx, &self.pubkey())?;
+        let signature = match self {
+            Signer::Local(key) => key.sign(message).to_bytes(),
+            #[cfg(unix)]
+            Signer::Remote(remote) => remote.sign(tx, message).await?,
+        };
+
+        let mut signed = tx.to_vec();
+        signed[slot..slot + SIGNATURE_LEN].copy_from_slice(&signature);
+        Ok(signed)
+    }
+}
+
+/// The 64 secret + public key bytes of a keypair in either of the formats `from_keypair`
+/// accepts. Errors never include the input.
+pub fn parse_keypair(keypair: &str) -> Result<Zeroizing<[u8; 64]>, AppError> {
+    let keypair = keypair.trim();
+    let bytes: Zeroizing<Vec<u8>> = if keypair.starts_with('[') {
+        serde_json::from_str(keypair)
+            .map(Zeroizing::new)
+            .map_err(|_| AppError::ConfigError("Invalid keypair: not a JSON array of bytes".to_string()))?
+    } else {
+        bs58::decode(keypair).into_vec()
+            .map(Zeroizing::new)
+            .map_err(|_| AppError::ConfigError("Invalid keypair: not base58".to_string()))?
+    };
+
+    let mut keypair = Zeroizing::new([0; 64]);
+    if bytes.len() != keypair.len() {
+        return Err(AppError::ConfigError(format!("Keypair must be 64 bytes, got {}", bytes.len())));
+    }
+    keypair.copy_from_slice(&bytes);
+    Ok(keypair)
+}
+
+/// Byte offset where `pubkey`'s signature goes, and the message that is signed.
+pub fn signature_slot<'a>(tx: &'a [u8], pubkey: &str) -> Result<(usize, &'a [u8]), AppError> {
+    let invalid = |reason: &str| AppError::RpcError(format!("Invalid transaction: {}", reason));
+
+    let (signature_count, offset) = short_vec_len(tx).ok_or_else(|| invalid("bad signature count"))?;
+    let message_start = offset + signature_count * SIGNATURE_LEN;
+    let message = tx.get(message_start..).ok_or_else(|| invalid("truncated signatures"))?;
+
+    // Versioned messages start with 0x80 | version; legacy ones start with the header.
+    let header = if message.first().is_some_and(|byte| byte & 0x80 != 0) { 1 } else { 0 };
+    let required_signatures = *message.get(header).ok_or_else(|| invalid("truncated header"))? as usize;
+    let keys = message.get(header + 3..).ok_or_else(|| invalid("truncated header"))?;
+    let (key_count, keys_offset) = short_vec_len(keys).ok_or_else(|| invalid("bad account count"))?;
+
+    let pubkey = bs58::decode(pubkey).into_vec().map_err(|_| invalid("bad signer address"))?;
+    let index = (0..required_signatures.min(key_count).min(signature_count))
+        .find(|index| {
+            let start = keys_offset + index * PUBKEY_LEN;
+            keys.get(start..start + PUBKEY_LEN) == Some(pubkey.as_slice())
+        })
+        .ok_or_else(|| invalid("the wallet is not a signer"))?;
+
+    Ok((offset + index * SIGNATURE_LEN, message))
+}
+
+/// Decodes a compact-u16 length prefix; returns the length and the bytes it took.
+pub fn short_vec_len(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut len = 0usize;
+    for (i, byte) in bytes.iter().take(3).enumerate() {
+        len |= ((byte & 0x7f) as usize) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((len, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signature, Verifier};
+
+    #[tokio::test]
+    async fn signs_versioned_transaction_in_the_wallets_slot() {
+        let fee_payer = SigningKey::from_bytes(&[7; 32]);
+        let wallet = SigningKey::from_bytes(&[9; 32]);
+
+        // Two required signatures, the wallet's second, over a v0 message.
+        let mut message = vec![0x80, 2, 0, 1, 3];
+        message.extend_from_slice(fee_payer.verifying_key().as_bytes());
+        message.extend_from_slice(wallet.verifying_key().as_bytes());
+        message.extend_from_slice(&[1; 32]);
+        message.extend_from_slice(&[0xAB; 40]);
+        let mut tx = vec![2];
+        tx.extend_from_slice(&[0; 2 * SIGNATURE_LEN]);
+        tx.extend_from_slice(&message);
+
+        let keypair = bs58::encode(wallet.to_keypair_bytes()).into_string();
+        let signer = Signer::from_keypair(&keypair).unwrap();
+        let signed = signer.sign_transaction(&tx).await.unwrap();
+
+        assert_eq!(signed[1..1 + SIGNATURE_LEN], [0; SIGNATURE_LEN]);
+        let signature = Signature::from_slice(&signed[1 + SIGNATURE_LEN..1 + 2 * SIGNATURE_LEN]).unwrap();
+        wallet.verifying_key().verify(&message, &signature).unwrap();
+        assert_eq!(signed[1 + 2 * SIGNATURE_LEN..], message[..]);
+    }
+
+    #[test]
+    fn reads_keygen_json_keypair() {
+        let wallet = SigningKey::from_bytes(&[9; 32]);
+        let json = serde_json::to_string(&wallet.to_keypair_bytes().to_vec()).unwrap();
+        let signer = Signer::from_keypair(&json).unwrap();
+        assert_eq!(signer.pubkey(), bs58::encode(wallet.verifying_key().as_bytes()).into_string());
+    }
+}
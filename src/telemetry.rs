@@ -0,0 +1,59 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::HeaderName,
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogFormat, LoggingConfig};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Installs the global tracing subscriber. The filter has already been validated by `config`.
+pub fn init(config: &LoggingConfig) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&config.level));
+
+    match config.format {
+        LogFormat::Pretty => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+/// The `x-request-id` of the API request currently being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Root span for every API request, tagged with the request id set by `SetRequestIdLayer`.
+pub fn make_request_span(req: &Request<Body>) -> Span {
+    let request_id = req.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        request_id = %request_id,
+    )
+}
+
+/// Makes the request id available to the db layer so it can be forwarded to PostgREST.
+pub async fn scope_request_id(req: Request, next: Next) -> Response {
+    let request_id = req.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    REQUEST_ID.scope(request_id, next.run(req)).await
+}
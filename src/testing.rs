@@ -0,0 +1,91 @@
+//! Local stand-ins for the services the app talks to, for tests.
+
+use axum::Router;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+use crate::models::CopyTradeSettings;
+use crate::parser::{ParsedTrade, TradeKind};
+
+/// Serves `router` on a free local port and returns its base URL.
+pub async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+/// Settings of an enabled wallet with every optional setting off: 0.1 SOL per buy, 1% slippage,
+/// at most two open positions and 0.05 SOL kept back.
+pub fn settings() -> CopyTradeSettings {
+    CopyTradeSettings {
+        id: None,
+        user_id: None,
+        tracked_wallet_id: Uuid::nil(),
+        is_enabled: true,
+        trade_amount_sol: 0.1,
+        max_slippage: 1.0,
+        max_open_positions: 2,
+        allowed_tokens: None,
+        use_allowed_tokens_list: false,
+        allow_additional_buys: false,
+        match_sell_percentage: false,
+        min_sol_balance: 0.05,
+        paper_trading: false,
+        take_profit_pct: None,
+        stop_loss_pct: None,
+        trailing_stop_pct: None,
+        max_token_exposure_sol: None,
+        max_wallet_exposure_sol: None,
+        min_seconds_between_trades: None,
+        max_trades_per_hour: None,
+        rebuy_cooldown_minutes: None,
+        schedule: None,
+        schedule_timezone: None,
+        sizing: None,
+        min_trade_sol: None,
+        max_trade_sol: None,
+        reject_mint_authority: false,
+        reject_freeze_authority: false,
+        max_top_holders_pct: None,
+        min_liquidity_sol: None,
+        min_pool_age_minutes: None,
+        priority_fee: None,
+        max_priority_fee_micro_lamports: None,
+        compute_unit_limit: None,
+        submission: None,
+        bundle_tip_lamports: None,
+        active_now: None,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+/// A `kind` trade by the wallet `leader` of `token_amount` tokens for `sol_amount` SOL, from a
+/// balance of 10 SOL and no tokens, or all of them when selling.
+pub fn trade(kind: TradeKind, token_address: &str, token_amount: f64, sol_amount: f64) -> ParsedTrade {
+    let (token_balance_before, token_balance_after) = match kind {
+        TradeKind::Sell => (token_amount, 0.0),
+        _ => (0.0, token_amount),
+    };
+    ParsedTrade {
+        signature: "sig".to_string(),
+        wallet: "leader".to_string(),
+        kind,
+        dex: None,
+        input_mint: String::new(),
+        input_amount: 0.0,
+        output_mint: String::new(),
+        output_amount: 0.0,
+        token_address: token_address.to_string(),
+        token_amount,
+        token_decimals: 6,
+        sol_amount,
+        price_sol: Some(sol_amount / token_amount),
+        token_balance_before,
+        token_balance_after,
+        sol_balance_before: 10.0,
+        slot: 1,
+        timestamp: None,
+    }
+}
@@ -0,0 +1,416 @@
+//! Symbols, names, decimals and logos of traded tokens, for API responses. Looked up in the
+//! configured token list file, then the `token_metadata` table, then on-chain: the Token-2022
+//! metadata extension or the token's Metaplex metadata account. On-chain lookups are saved to
+//! the table.
+//!
+//! The logo comes from the off-chain JSON the on-chain metadata points to. Anyone minting a
+//! token picks that URI, so it is only fetched over https from public addresses, and only so
+//! much of it is read.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use ed25519_dalek::VerifyingKey;
+use futures_util::{stream, StreamExt};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Url};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::db::SupabaseClient;
+use crate::error::AppError;
+use crate::models::{TokenMetadata, Transaction, TransactionWithToken};
+use crate::rpc::SolanaRpc;
+
+const METADATA_PROGRAM: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+/// Off-chain metadata only supplies the logo, so it is not waited on for long.
+const URI_TIMEOUT: Duration = Duration::from_secs(5);
+/// Off-chain metadata is a small JSON document; anything larger is not read.
+const MAX_URI_BYTES: usize = 64 * 1024;
+const MAX_URI_REDIRECTS: usize = 5;
+/// Most mints fetched on-chain at once for one lookup.
+const MAX_CONCURRENT_FETCHES: usize = 8;
+/// How long a mint that could not be fetched is not tried again.
+const MISS_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone)]
+pub struct TokenMetadataService {
+    db: SupabaseClient,
+    rpc: SolanaRpc,
+    http: reqwest::Client,
+    list: Arc<HashMap<String, TokenMetadata>>,
+    /// When each mint that had no metadata, or failed to fetch, was last tried.
+    misses: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl TokenMetadataService {
+    pub fn new(db: SupabaseClient, rpc: SolanaRpc, list: HashMap<String, TokenMetadata>) -> Self {
+        let http = reqwest::Client::builder()
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= MAX_URI_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if let Err(e) = check_uri(attempt.url()) {
+                    attempt.error(e)
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .timeout(URI_TIMEOUT)
+            .build()
+            .expect("HTTP client builds");
+        Self { db, rpc, http, list: Arc::new(list), misses: Arc::default() }
+    }
+
+    /// Reads a token list file: `{"tokens": [...]}` in the Solana token list format, or just the
+    /// array, with `address`, `symbol`, `name`, `decimals` and `logoURI` per token.
+    pub fn load_list(path: &Path) -> Result<HashMap<String, TokenMetadata>, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        let list: Value = serde_json::from_str(&contents)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        let tokens = list.get("tokens").unwrap_or(&list);
+        let tokens = tokens.as_array()
+            .ok_or_else(|| AppError::ConfigError(format!("{}: expected a list of tokens", path.display())))?;
+
+        tokens.iter()
+            .map(|token| {
+                list_entry(token).ok_or_else(|| AppError::ConfigError(format!("{}: invalid token {}", path.display(), token)))
+            })
+            .map(|token| token.map(|token| (token.mint.clone(), token)))
+            .collect()
+    }
+
+    /// Metadata of each of `mints` that can be found. Tokens that cannot be looked up are left
+    /// out rather than failing the response they are for, and are not fetched again for
+    /// `MISS_TTL`.
+    pub async fn lookup<'a>(&self, mints: impl IntoIterator<Item = &'a str>) -> HashMap<String, TokenMetadata> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        for mint in mints.into_iter().collect::<BTreeSet<_>>() {
+            match self.list.get(mint) {
+                Some(token) => {
+                    found.insert(mint.to_string(), token.clone());
+                }
+                None => missing.push(mint.to_string()),
+            }
+        }
+        if missing.is_empty() {
+            return found;
+        }
+
+        match self.db.get_token_metadata(&missing).await {
+            Ok(cached) => {
+                for token in cached {
+                    found.insert(token.mint.clone(), token);
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to read cached token metadata"),
+        }
+
+        missing.retain(|mint| !found.contains_key(mint) && !self.missed_recently(mint));
+        let fetched: Vec<TokenMetadata> = stream::iter(missing)
+            .map(|mint| self.fetch_and_save(mint))
+            .buffer_unordered(MAX_CONCURRENT_FETCHES)
+            .filter_map(|token| async { token })
+            .collect()
+            .await;
+        for token in fetched {
+            found.insert(token.mint.clone(), token);
+        }
+        found
+    }
+
+    /// `transactions` with the metadata of their tokens.
+    pub async fn with_tokens(&self, transactions: Vec<Transaction>) -> Vec<TransactionWithToken> {
+        let tokens = self.lookup(transactions.iter().map(|transaction| transaction.token_address.as_str())).await;
+        transactions.into_iter()
+            .map(|transaction| TransactionWithToken {
+                token: tokens.get(&transaction.token_address).cloned(),
+                transaction,
+            })
+            .collect()
+    }
+
+    /// Fetches the metadata of `mint` and saves it to the table, or notes it as a miss.
+    async fn fetch_and_save(&self, mint: String) -> Option<TokenMetadata> {
+        let token = match self.fetch(&mint).await {
+            Ok(Some(token)) => token,
+            Ok(None) => {
+                debug!(%mint, "No mint account");
+                self.miss(mint);
+                return None;
+            }
+            Err(e) => {
+                warn!(error = %e, %mint, "Failed to fetch token metadata");
+                self.miss(mint);
+                return None;
+            }
+        };
+        if let Err(e) = self.db.save_token_metadata(&token).await {
+            warn!(error = %e, %mint, "Failed to cache token metadata");
+        }
+        Some(token)
+    }
+
+    fn missed_recently(&self, mint: &str) -> bool {
+        let misses = self.misses.lock().expect("token misses lock poisoned");
+        misses.get(mint).is_some_and(|tried_at| tried_at.elapsed() < MISS_TTL)
+    }
+
+    fn miss(&self, mint: String) {
+        let mut misses = self.misses.lock().expect("token misses lock poisoned");
+        misses.retain(|_, tried_at| tried_at.elapsed() < MISS_TTL);
+        misses.insert(mint, Instant::now());
+    }
+
+    async fn fetch(&self, mint: &str) -> Result<Option<TokenMetadata>, AppError> {
+        let Some(account) = self.rpc.get_parsed_account(mint).await? else {
+            return Ok(None);
+        };
+        let info = &account["data"]["parsed"]["info"];
+        let decimals = info["decimals"].as_u64()
+            .and_then(|decimals| u8::try_from(decimals).ok())
+            .ok_or_else(|| AppError::BadRequest(format!("{} is not a token mint", mint)))?;
+
+        // Token-2022 mints can carry their metadata themselves.
+        let extension = info["extensions"].as_array()
+            .and_then(|extensions| extensions.iter().find(|extension| extension["extension"] == "tokenMetadata"));
+        let onchain = match extension {
+            Some(extension) => {
+                let state = &extension["state"];
+                let field = |name: &str| state[name].as_str().unwrap_or_default().to_string();
+                Some((field("name"), field("symbol"), field("uri")))
+            }
+            None => match metadata_address(mint) {
+                Some(address) => self.rpc.get_account_data(&address).await?
+                    .and_then(|data| BASE64.decode(data).ok())
+                    .and_then(|data| parse_metaplex(&data)),
+                None => None,
+            },
+        };
+
+        let (name, symbol, uri) = onchain.unwrap_or_default();
+        let logo_uri = if uri.is_empty() { None } else { self.fetch_logo(&uri).await };
+        Ok(Some(TokenMetadata {
+            mint: mint.to_string(),
+            symbol: Some(symbol).filter(|symbol| !symbol.is_empty()),
+            name: Some(name).filter(|name| !name.is_empty()),
+            decimals,
+            logo_uri,
+            updated_at: Some(Utc::now()),
+        }))
+    }
+
+    /// The `image` of the off-chain metadata JSON at `uri`, if it can be fetched.
+    async fn fetch_logo(&self, uri: &str) -> Option<String> {
+        let url = Url::parse(uri).ok()?;
+        if let Err(e) = check_uri(&url) {
+            debug!(%uri, error = %e, "Not fetching token metadata URI");
+            return None;
+        }
+        let mut resp = self.http.get(url).send().await.ok()?;
+        if resp.content_length().is_some_and(|len| len > MAX_URI_BYTES as u64) {
+            return None;
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await.ok()? {
+            if body.len() + chunk.len() > MAX_URI_BYTES {
+                return None;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let metadata: Value = serde_json::from_slice(&body).ok()?;
+        metadata["image"].as_str().map(str::to_string)
+    }
+}
+
+/// Refuses URIs other than https, and hosts given as addresses that are not public. Host names
+/// are checked as they resolve, by `PublicResolver`.
+fn check_uri(url: &Url) -> Result<(), String> {
+    if url.scheme() != "https" {
+        return Err(format!("{} is not an https URI", url));
+    }
+    let host = url.host_str().ok_or_else(|| format!("{} has no host", url))?;
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) if !is_public(ip) => Err(format!("{} is not a public address", ip)),
+        _ => Ok(()),
+    }
+}
+
+/// Resolves host names with the system resolver, failing for names with any address that is not
+/// public, so a URI cannot reach the host's own network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(format!("{} resolves to {}, which is not public", name.as_str(), addr.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is on the public internet: not loopback, private, link-local, shared (CGNAT),
+/// unspecified or otherwise reserved.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+fn list_entry(token: &Value) -> Option<TokenMetadata> {
+    Some(TokenMetadata {
+        mint: token["address"].as_str()?.to_string(),
+        symbol: token["symbol"].as_str().map(str::to_string),
+        name: token["name"].as_str().map(str::to_string),
+        decimals: u8::try_from(token["decimals"].as_u64()?).ok()?,
+        logo_uri: token["logoURI"].as_str().map(str::to_string),
+        updated_at: None,
+    })
+}
+
+/// The Metaplex metadata account of `mint`: the program address of
+/// `["metadata", METADATA_PROGRAM, mint]`.
+fn metadata_address(mint: &str) -> Option<String> {
+    let program = bs58::decode(METADATA_PROGRAM).into_vec().ok()?;
+    let mint = bs58::decode(mint).into_vec().ok()?;
+    (0..=u8::MAX).rev().find_map(|bump| {
+        let hash: [u8; 32] = Sha256::new()
+            .chain_update(b"metadata")
+            .chain_update(&program)
+            .chain_update(&mint)
+            .chain_update([bump])
+            .chain_update(&program)
+            .chain_update(b"ProgramDerivedAddress")
+            .finalize()
+            .into();
+        // A program address must not be a valid public key.
+        VerifyingKey::from_bytes(&hash).is_err().then(|| bs58::encode(hash).into_string())
+    })
+}
+
+/// Reads the name, symbol and URI of a Metaplex metadata account: a key byte, the update
+/// authority and mint, then three Borsh strings padded with NULs.
+fn parse_metaplex(data: &[u8]) -> Option<(String, String, String)> {
+    let mut rest = data.get(1 + 32 + 32..)?;
+    let mut string = || {
+        let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let value = String::from_utf8_lossy(rest.get(4..4 + len)?).trim_end_matches('\0').to_string();
+        rest = &rest[4 + len..];
+        Some(value)
+    };
+    Some((string()?, string()?, string()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::{routing::{get, post}, Json, Router};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn reads_metaplex_metadata() {
+        assert_eq!(
+            metadata_address("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").as_deref(),
+            Some("5x38Kp4hvdomTCnCrAny4UtMUt5rQBdB6px2K1Ui45Wq")
+        );
+
+        let mut data = vec![4];
+        data.extend([1; 32]);
+        data.extend([2; 32]);
+        for (value, padded) in [("USD Coin", 32), ("USDC", 10), ("https://example.com/usdc.json", 200)] {
+            data.extend((padded as u32).to_le_bytes());
+            data.extend(value.as_bytes());
+            data.extend(vec![0; padded - value.len()]);
+        }
+        let (name, symbol, uri) = parse_metaplex(&data).unwrap();
+        assert_eq!((name.as_str(), symbol.as_str(), uri.as_str()), ("USD Coin", "USDC", "https://example.com/usdc.json"));
+        assert!(parse_metaplex(&data[..80]).is_none());
+
+        let token = list_entry(&json!({
+            "chainId": 101,
+            "address": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "symbol": "USDC",
+            "name": "USD Coin",
+            "decimals": 6,
+            "logoURI": "https://example.com/usdc.png",
+        })).unwrap();
+        assert_eq!(token.decimals, 6);
+        assert_eq!(token.logo_uri.as_deref(), Some("https://example.com/usdc.png"));
+    }
+
+    #[tokio::test]
+    async fn does_not_fetch_missing_mints_again() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let rpc = testing::serve(Router::new().route("/", post(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Json(json!({ "jsonrpc": "2.0", "id": 1, "result": { "value": null } })) }
+        }))).await;
+        let supabase = testing::serve(Router::new().route("/token_metadata", get(|| async { Json(json!([])) }))).await;
+        let tokens = TokenMetadataService::new(
+            SupabaseClient::new(&supabase, "", "", "me"),
+            SolanaRpc::new(&rpc, "confirmed"),
+            HashMap::new(),
+        );
+
+        let mints = ["mint-a", "mint-b", "mint-c"];
+        assert!(tokens.lookup(mints).await.is_empty());
+        assert!(tokens.lookup(mints).await.is_empty());
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn fetches_only_public_https_uris() {
+        for uri in ["https://arweave.net/abc", "https://example.com/logo.json", "https://[2606:4700::1111]/a"] {
+            assert_eq!(check_uri(&Url::parse(uri).unwrap()), Ok(()), "{}", uri);
+        }
+        for uri in [
+            "http://arweave.net/abc",
+            "file:///etc/passwd",
+            "https://127.0.0.1/a",
+            "https://10.1.2.3/a",
+            "https://169.254.169.254/latest/meta-data",
+            "https://100.64.0.1/a",
+            "https://[::1]/a",
+            "https://[fd00::1]/a",
+            "https://[::ffff:192.168.0.1]/a",
+        ] {
+            assert!(check_uri(&Url::parse(uri).unwrap()).is_err(), "{}", uri);
+        }
+
+        // Names are checked as they resolve.
+        assert!(PublicResolver.resolve("localhost".parse().unwrap()).await.is_err());
+    }
+}
@@ -0,0 +1,507 @@
+//! `/v1` API: resources are addressed by id in the path and partially updated with `PATCH`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use chrono::Utc;
+use futures_util::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::balances::BalanceService;
+use crate::db::SupabaseClient;
+use crate::backtest;
+use crate::engine;
+use crate::executor::ComputeBudget;
+use crate::monitor::{ActivityEvent, MonitorHandle};
+use crate::paper::PaperSettings;
+use crate::risk::RiskGuard;
+use crate::safety::SafetyService;
+use crate::schedule;
+use crate::shutdown::BackgroundTasks;
+use crate::tokens::TokenMetadataService;
+use crate::models::{
+    TrackedWallet, TrackedWalletPatch, CopyTradeSettings, CopyTradeSettingsPatch, Transaction, LeaderHolding,
+    TrackedWalletIdResponse, SettingsIdResponse, MessageResponse, TokenPnl, HistoryQuery,
+    BacktestRequest, BacktestReport, WalletBalances, RiskHalt, RiskStatus, HaltRequest, TokenRisk,
+    TransactionWithToken, OpenPosition, AllowedToken,
+};
+use crate::error::AppError;
+
+#[utoipa::path(
+    get,
+    path = "/v1/tracked_wallets",
+    tag = "tracked_wallets",
+    responses((status = 200, description = "All tracked wallets of the user", body = [TrackedWallet])),
+)]
+pub async fn list_tracked_wallets(
+    State(client): State<SupabaseClient>,
+) -> Result<Json<Vec<TrackedWallet>>, AppError> {
+    let wallets = client.get_tracked_wallets().await?;
+    Ok(Json(wallets))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/tracked_wallets",
+    tag = "tracked_wallets",
+    request_body(content = TrackedWallet, description = "Only `wallet_address` and `is_active` are used"),
+    responses(
+        (status = 201, description = "Wallet is now tracked", body = TrackedWalletIdResponse),
+        (status = 400, description = "Invalid request body", body = String),
+    ),
+)]
+pub async fn create_tracked_wallet(
+    State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
+    Json(wallet): Json<TrackedWallet>,
+) -> Result<(StatusCode, Json<TrackedWalletIdResponse>), AppError> {
+    let result = client.add_tracked_wallet(wallet).await?;
+    monitor.refresh();
+    Ok((StatusCode::CREATED, Json(TrackedWalletIdResponse { success: true, tracked_wallet_id: result })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/tracked_wallets/{id}",
+    tag = "tracked_wallets",
+    params(("id" = Uuid, Path, description = "Tracked wallet id")),
+    responses(
+        (status = 200, description = "The tracked wallet", body = TrackedWallet),
+        (status = 404, description = "No tracked wallet with that id", body = String),
+    ),
+)]
+pub async fn get_tracked_wallet(
+    State(client): State<SupabaseClient>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TrackedWallet>, AppError> {
+    let wallet = client.get_tracked_wallet(id).await?;
+    Ok(Json(wallet))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/v1/tracked_wallets/{id}",
+    tag = "tracked_wallets",
+    params(("id" = Uuid, Path, description = "Tracked wallet id")),
+    request_body(content = TrackedWalletPatch, description = "Fields to change. `{\"is_active\": false}` archives the wallet"),
+    responses(
+        (status = 200, description = "The updated wallet", body = TrackedWallet),
+        (status = 400, description = "Empty or invalid patch", body = String),
+        (status = 404, description = "No tracked wallet with that id", body = String),
+    ),
+)]
+pub async fn update_tracked_wallet(
+    State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
+    Path(id): Path<Uuid>,
+    Json(patch): Json<TrackedWalletPatch>,
+) -> Result<Json<TrackedWallet>, AppError> {
+    let wallet = client.patch_tracked_wallet(id, patch).await?;
+    monitor.refresh();
+    Ok(Json(wallet))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/tracked_wallets/{id}",
+    tag = "tracked_wallets",
+    params(("id" = Uuid, Path, description = "Tracked wallet id")),
+    responses(
+        (status = 200, description = "Wallet deleted", body = MessageResponse),
+        (status = 404, description = "No tracked wallet with that id", body = String),
+    ),
+)]
+pub async fn delete_tracked_wallet(
+    State(client): State<SupabaseClient>,
+    State(monitor): State<MonitorHandle>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<MessageResponse>, AppError> {
+    let result = client.delete_tracked_wallet_by_id(id).await?;
+    monitor.refresh();
+    Ok(Json(MessageResponse { success: true, message: result }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/tracked_wallets/{id}/holdings",
+    tag = "tracked_wallets",
+    params(("id" = Uuid, Path, description = "Tracked wallet id")),
+    responses((status = 200, description = "Token holdings of the wallet, as tracked from its trades", body = [LeaderHolding])),
+)]
+pub async fn list_leader_holdings(
+    State(client): State<SupabaseClient>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<LeaderHolding>>, AppError> {
+    let holdings = client.get_leader_holdings(id).await?;
+    Ok(Json(holdings))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/copy_trade_settings",
+    tag = "copy_trade_settings",
+    responses((status = 200, description = "Copy trade settings of every tracked wallet", body = [CopyTradeSettings])),
+)]
+pub async fn list_copy_trade_settings(
+    State(client): State<SupabaseClient>,
+) -> Result<Json<Vec<CopyTradeSettings>>, AppError> {
+    let now = Utc::now();
+    let settings = client.get_copy_trade_settings().await?
+        .into_iter()
+        .map(|settings| schedule::with_active_now(settings, now))
+        .collect();
+    Ok(Json(settings))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/copy_trade_settings",
+    tag = "copy_trade_settings",
+    request_body = CopyTradeSettings,
+    responses(
+        (status = 201, description = "Settings created", body = SettingsIdResponse),
+        (status = 400, description = "Invalid request body", body = String),
+    ),
+)]
+pub async fn create_copy_trade_settings(
+    State(client): State<SupabaseClient>,
+    State(defaults): State<ComputeBudget>,
+    Json(settings): Json<CopyTradeSettings>,
+) -> Result<(StatusCode, Json<SettingsIdResponse>), AppError> {
+    let result = client.create_copy_trade_settings(settings, &defaults).await?;
+    Ok((StatusCode::CREATED, Json(SettingsIdResponse { success: true, settings_id: result })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/copy_trade_settings/{tracked_wallet_id}",
+    tag = "copy_trade_settings",
+    params(("tracked_wallet_id" = Uuid, Path, description = "Tracked wallet the settings belong to")),
+    responses(
+        (status = 200, description = "The settings", body = CopyTradeSettings),
+        (status = 404, description = "The wallet has no copy trade settings", body = String),
+    ),
+)]
+pub async fn get_copy_trade_settings(
+    State(client): State<SupabaseClient>,
+    Path(tracked_wallet_id): Path<Uuid>,
+) -> Result<Json<CopyTradeSettings>, AppError> {
+    let settings = client.get_copy_trade_settings_for_wallet(tracked_wallet_id).await?;
+    Ok(Json(schedule::with_active_now(settings, Utc::now())))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/v1/copy_trade_settings/{tracked_wallet_id}",
+    tag = "copy_trade_settings",
+    params(("tracked_wallet_id" = Uuid, Path, description = "Tracked wallet the settings belong to")),
+    request_body(content = CopyTradeSettingsPatch, description = "Fields to change"),
+    responses(
+        (status = 200, description = "The updated settings", body = CopyTradeSettings),
+        (status = 400, description = "Empty or invalid patch", body = String),
+        (status = 404, description = "The wallet has no copy trade settings", body = String),
+    ),
+)]
+pub async fn update_copy_trade_settings(
+    State(client): State<SupabaseClient>,
+    State(defaults): State<ComputeBudget>,
+    Path(tracked_wallet_id): Path<Uuid>,
+    Json(patch): Json<CopyTradeSettingsPatch>,
+) -> Result<Json<CopyTradeSettings>, AppError> {
+    let settings = client.patch_copy_trade_settings(tracked_wallet_id, patch, &defaults).await?;
+    Ok(Json(schedule::with_active_now(settings, Utc::now())))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/copy_trade_settings/{tracked_wallet_id}",
+    tag = "copy_trade_settings",
+    params(("tracked_wallet_id" = Uuid, Path, description = "Tracked wallet the settings belong to")),
+    responses(
+        (status = 200, description = "Settings deleted", body = MessageResponse),
+        (status = 500, description = "Settings could not be deleted", body = String),
+    ),
+)]
+pub async fn delete_copy_trade_settings(
+    State(client): State<SupabaseClient>,
+    Path(tracked_wallet_id): Path<Uuid>,
+) -> Result<Json<MessageResponse>, AppError> {
+    let result = client.delete_copy_trade_settings(tracked_wallet_id).await?;
+    Ok(Json(MessageResponse { success: true, message: result }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/transactions",
+    tag = "transactions",
+    params(HistoryQuery),
+    responses((status = 200, description = "Every copy trade made for the user, or every simulated one with `paper=true`, with token metadata", body = [TransactionWithToken])),
+)]
+pub async fn list_transactions(
+    State(client): State<SupabaseClient>,
+    State(tokens): State<TokenMetadataService>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<TransactionWithToken>>, AppError> {
+    let transactions = history(&client, &query).await?;
+    Ok(Json(tokens.with_tokens(transactions).await))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/positions",
+    tag = "transactions",
+    params(HistoryQuery),
+    responses((status = 200, description = "Open positions per tracked wallet, with token metadata", body = [OpenPosition])),
+)]
+pub async fn list_positions(
+    State(client): State<SupabaseClient>,
+    State(tokens): State<TokenMetadataService>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<OpenPosition>>, AppError> {
+    let positions = engine::open_positions(&history(&client, &query).await?);
+    let metadata = tokens.lookup(positions.values().flatten().map(|position| position.token_address.as_str())).await;
+    let positions = positions.into_iter()
+        .flat_map(|(tracked_wallet_id, positions)| positions.into_iter().map(move |position| (tracked_wallet_id, position)))
+        .map(|(tracked_wallet_id, position)| OpenPosition {
+            tracked_wallet_id,
+            token: metadata.get(&position.token_address).cloned(),
+            token_address: position.token_address,
+            amount: position.amount,
+            cost_sol: position.cost_sol,
+        })
+        .collect();
+    Ok(Json(positions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/allowed_tokens",
+    tag = "copy_trade_settings",
+    responses((status = 200, description = "The `allowed_tokens` of every tracked wallet, with token metadata", body = [AllowedToken])),
+)]
+pub async fn list_allowed_tokens(
+    State(client): State<SupabaseClient>,
+    State(tokens): State<TokenMetadataService>,
+) -> Result<Json<Vec<AllowedToken>>, AppError> {
+    let settings = client.get_copy_trade_settings().await?;
+    let metadata = tokens.lookup(settings.iter().flat_map(|settings| settings.allowed_tokens.iter().flatten().map(String::as_str))).await;
+    let metadata = &metadata;
+    let allowed = settings.iter()
+        .flat_map(|settings| settings.allowed_tokens.iter().flatten().map(move |mint| AllowedToken {
+            tracked_wallet_id: settings.tracked_wallet_id,
+            token_address: mint.clone(),
+            enforced: settings.use_allowed_tokens_list,
+            token: metadata.get(mint).cloned(),
+        }))
+        .collect();
+    Ok(Json(allowed))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/pnl",
+    tag = "transactions",
+    params(HistoryQuery),
+    responses((status = 200, description = "Profit and loss per tracked wallet and token", body = [TokenPnl])),
+)]
+pub async fn get_pnl(
+    State(client): State<SupabaseClient>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<TokenPnl>>, AppError> {
+    let transactions = history(&client, &query).await?;
+    Ok(Json(engine::pnl(&transactions)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/balances",
+    tag = "wallet",
+    responses((status = 200, description = "The trading wallet's SOL and token balances, as last fetched (at most 10s old)", body = WalletBalances)),
+)]
+pub async fn get_balances(
+    State(balances): State<BalanceService>,
+) -> Result<Json<WalletBalances>, AppError> {
+    let balances = balances.get().await?;
+    Ok(Json(balances))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/risk",
+    tag = "risk",
+    responses((status = 200, description = "Whether copying is halted, and the account's limits and usage", body = RiskStatus)),
+)]
+pub async fn get_risk_status(
+    State(risk): State<RiskGuard>,
+) -> Result<Json<RiskStatus>, AppError> {
+    let status = risk.status().await?;
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/risk/halt",
+    tag = "risk",
+    request_body(content = Option<HaltRequest>, description = "Optional reason to record"),
+    responses((status = 200, description = "Copying is halted for every wallet; the active halt", body = RiskHalt)),
+)]
+pub async fn halt_copying(
+    State(risk): State<RiskGuard>,
+    request: Option<Json<HaltRequest>>,
+) -> Result<Json<RiskHalt>, AppError> {
+    let reason = request.and_then(|Json(request)| request.reason)
+        .unwrap_or_else(|| "Halted through the API".to_string());
+    let halt = risk.halt(&reason).await?;
+    Ok(Json(halt))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/risk/resume",
+    tag = "risk",
+    responses((status = 200, description = "Copying resumed", body = MessageResponse)),
+)]
+pub async fn resume_copying(
+    State(risk): State<RiskGuard>,
+) -> Result<Json<MessageResponse>, AppError> {
+    risk.resume().await?;
+    Ok(Json(MessageResponse { success: true, message: "Copying resumed".to_string() }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/tokens/{mint}/risk",
+    tag = "tokens",
+    params(("mint" = String, Path, description = "Mint address of the token")),
+    responses(
+        (status = 200, description = "The token's safety assessment, as checked before copying a buy", body = TokenRisk),
+        (status = 400, description = "Not a token mint", body = String),
+        (status = 404, description = "No such account", body = String),
+    ),
+)]
+pub async fn get_token_risk(
+    State(safety): State<SafetyService>,
+    Path(mint): Path<String>,
+) -> Result<Json<TokenRisk>, AppError> {
+    let risk = safety.assess(&mint).await?;
+    Ok(Json(risk))
+}
+
+async fn history(client: &SupabaseClient, query: &HistoryQuery) -> Result<Vec<Transaction>, AppError> {
+    if query.paper {
+        client.get_paper_transaction_history().await
+    } else {
+        client.get_transaction_history().await
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/backtest",
+    tag = "backtest",
+    request_body = BacktestRequest,
+    responses(
+        (status = 200, description = "Simulated results of the settings", body = BacktestReport),
+        (status = 400, description = "A transaction could not be decoded"),
+    ),
+)]
+pub async fn run_backtest(
+    State(paper): State<PaperSettings>,
+    Json(request): Json<BacktestRequest>,
+) -> Result<Json<BacktestReport>, AppError> {
+    let paper = PaperSettings {
+        starting_balance_sol: request.starting_balance_sol.unwrap_or(paper.starting_balance_sol),
+        ..paper
+    };
+    let report = backtest::run(&request.settings, &request.wallet_address, &request.transactions, &paper)?;
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/activity",
+    tag = "activity",
+    responses((
+        status = 200,
+        description = "Server-sent `activity` events for every tracked wallet, as they are observed",
+        content_type = "text/event-stream",
+        body = ActivityEvent,
+    )),
+)]
+pub async fn stream_activity(
+    State(monitor): State<MonitorHandle>,
+    State(tasks): State<BackgroundTasks>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let shutdown = tasks.shutdown_token();
+    let events = futures_util::stream::unfold(monitor.subscribe(), move |mut receiver| {
+        let shutdown = shutdown.clone();
+        async move {
+            loop {
+                let received = tokio::select! {
+                    _ = shutdown.cancelled() => return None,
+                    received = receiver.recv() => received,
+                };
+                match received {
+                    Ok(event) => return Some((Event::default().event("activity").json_data(&event), receiver)),
+                    // A slow client only misses events; keep streaming.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::{routing::patch, Router};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn patch_clears_settings_sent_as_null() {
+        let tracked_wallet_id = Uuid::new_v4();
+        let written = Arc::new(Mutex::new(Value::Null));
+        let changes = written.clone();
+        let supabase = testing::serve(Router::new().route("/copy_trade_settings", patch(move |body: String| {
+            let changed: Value = serde_json::from_str(&body).unwrap();
+            *changes.lock().unwrap() = changed.clone();
+            let mut row = json!({
+                "tracked_wallet_id": tracked_wallet_id,
+                "is_enabled": true,
+                "trade_amount_sol": 0.2,
+                "max_slippage": 1.0,
+                "max_open_positions": 3,
+                "use_allowed_tokens_list": false,
+                "allow_additional_buys": false,
+                "match_sell_percentage": false,
+                "min_sol_balance": 0.01,
+                "take_profit_pct": 50.0,
+                "stop_loss_pct": 20.0,
+            });
+            for (field, value) in changed.as_object().unwrap() {
+                row[field] = value.clone();
+            }
+            async move { Json(json!([row])) }
+        }))).await;
+
+        let patch: CopyTradeSettingsPatch = serde_json::from_value(json!({
+            "stop_loss_pct": null,
+            "trade_amount_sol": 0.3,
+        })).unwrap();
+        let client = SupabaseClient::new(&supabase, "", "", "me");
+        let Json(settings) = update_copy_trade_settings(State(client), State(ComputeBudget::default()), Path(tracked_wallet_id), Json(patch)).await.unwrap();
+
+        assert_eq!(*written.lock().unwrap(), json!({ "trade_amount_sol": 0.3, "stop_loss_pct": null }));
+        assert_eq!(settings.stop_loss_pct, None);
+        assert_eq!(settings.take_profit_pct, Some(50.0));
+    }
+}
@@ -0,0 +1,295 @@
+//! Closes positions when they hit the take-profit, stop-loss or trailing-stop thresholds of
+//! their copy trade settings, without waiting for the tracked wallet to sell.
+//!
+//! Exits are swaps like copied sells, so they stop with them: while copying is halted nothing
+//! is exited, and neither are the positions of a wallet whose settings are disabled. Those
+//! positions are watched again once copying resumes or the wallet is enabled.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::balances::BalanceService;
+use crate::db::SupabaseClient;
+use crate::engine::{self, CopyDecision, Position};
+use crate::error::AppError;
+use crate::executor::{Executor, SwapOrder};
+use crate::ledger::Ledger;
+use crate::models::CopyTradeSettings;
+use crate::paper::{self, PaperSettings};
+use crate::prices::PriceSource;
+use crate::risk::RiskGuard;
+use crate::shutdown::BackgroundTasks;
+
+pub fn spawn<P: PriceSource>(watcher: PositionWatcher<P>, tasks: &BackgroundTasks) {
+    tasks.spawn(watcher.run(tasks.shutdown_token()));
+}
+
+pub struct PositionWatcher<P> {
+    pub db: SupabaseClient,
+    /// Copy trades and paper fills, which positions are replayed from.
+    pub ledger: Ledger,
+    pub prices: P,
+    /// The trading wallet and its balances, which cap what an exit sells.
+    pub balances: BalanceService,
+    /// `None` when execution is disabled; only paper-traded positions are then watched.
+    pub executor: Option<Executor>,
+    pub paper: PaperSettings,
+    /// While copying is halted no positions are exited.
+    pub risk: RiskGuard,
+    pub poll_interval: Duration,
+}
+
+/// Highest price seen per tracked wallet and token while the position is open. Kept in memory
+/// only, so trailing stops restart from the current price after a restart.
+type Peaks = HashMap<(Uuid, String), f64>;
+
+impl<P: PriceSource> PositionWatcher<P> {
+    async fn run(self, shutdown: CancellationToken) {
+        info!(poll_interval = ?self.poll_interval, "Position watcher started");
+
+        let mut peaks = Peaks::new();
+        let mut interval = tokio::time::interval(self.poll_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            if let Err(e) = self.check(&mut peaks).await {
+                warn!(error = %e, "Failed to check positions for exits");
+            }
+        }
+
+        info!("Position watcher stopped");
+    }
+
+    /// Prices every watched position and exits those past a threshold.
+    async fn check(&self, peaks: &mut Peaks) -> Result<(), AppError> {
+        if self.risk.active_halt().is_some() {
+            debug!("Copying is halted; not checking positions for exits");
+            return Ok(());
+        }
+        let settings: Vec<CopyTradeSettings> = self.db.get_copy_trade_settings().await?
+            .into_iter()
+            .filter(|settings| settings.is_enabled)
+            .filter(|settings| {
+                settings.take_profit_pct.is_some() || settings.stop_loss_pct.is_some() || settings.trailing_stop_pct.is_some()
+            })
+            .filter(|settings| settings.paper_trading || self.executor.is_some())
+            .collect();
+        if settings.is_empty() {
+            peaks.clear();
+            return Ok(());
+        }
+
+        let mut live = BTreeMap::new();
+        if settings.iter().any(|settings| !settings.paper_trading) {
+            live = engine::open_positions(&self.ledger.history().await?);
+        }
+        let mut simulated = BTreeMap::new();
+        if settings.iter().any(|settings| settings.paper_trading) {
+            simulated = engine::open_positions(&self.ledger.paper_history().await?);
+        }
+
+        let watched: Vec<(&CopyTradeSettings, &Position)> = settings.iter()
+            .flat_map(|settings| {
+                let positions = if settings.paper_trading { &simulated } else { &live };
+                positions.get(&Some(settings.tracked_wallet_id))
+                    .into_iter()
+                    .flatten()
+                    .map(move |position| (settings, position))
+            })
+            .collect();
+        let mut mints: Vec<String> = watched.iter().map(|(_, position)| position.token_address.clone()).collect();
+        mints.sort();
+        mints.dedup();
+        let prices = self.prices.prices_sol(&mints).await?;
+
+        let mut open = HashSet::new();
+        for (settings, position) in watched {
+            let key = (settings.tracked_wallet_id, position.token_address.clone());
+            open.insert(key.clone());
+            let Some(&price) = prices.get(&position.token_address) else {
+                debug!(token = %position.token_address, "No price for position");
+                continue;
+            };
+            let peak = peaks.entry(key.clone()).or_insert(price);
+            *peak = peak.max(price);
+
+            if engine::exit_due(settings, position, price, *peak).is_none() {
+                continue;
+            }
+            match self.exit(settings, &position.token_address, price, *peak).await {
+                Ok(true) => {
+                    peaks.remove(&key);
+                }
+                Ok(false) => {}
+                Err(e) => warn!(
+                    error = %e,
+                    tracked_wallet_id = %settings.tracked_wallet_id,
+                    token = %position.token_address,
+                    "Failed to exit position"
+                ),
+            }
+        }
+        peaks.retain(|key, _| open.contains(key));
+
+        Ok(())
+    }
+
+    /// Exits the wallet's position in `token_address` under the wallet's trade lock, so it does
+    /// not sell alongside a copied sell. The position is read again once the lock is held, as the
+    /// wallet may have sold meanwhile; `false` when it no longer needs an exit.
+    async fn exit(&self, settings: &CopyTradeSettings, token_address: &str, price: f64, peak: f64) -> Result<bool, AppError> {
+        let tracked_wallet_id = settings.tracked_wallet_id;
+        let lock = self.ledger.lock(tracked_wallet_id, false).await;
+        let history = if settings.paper_trading { self.ledger.paper_history().await? } else { self.ledger.history().await? };
+        let Some(position) = engine::open_positions(&history)
+            .remove(&Some(tracked_wallet_id))
+            .unwrap_or_default()
+            .into_iter()
+            .find(|position| position.token_address == token_address)
+        else {
+            return Ok(false);
+        };
+        let Some(reason) = engine::exit_due(settings, &position, price, peak) else {
+            return Ok(false);
+        };
+        info!(
+            %tracked_wallet_id,
+            token = %token_address,
+            reason = reason.as_str(),
+            price_sol = price,
+            "Exiting position"
+        );
+
+        if settings.paper_trading {
+            let fill = paper::simulate_exit(&position, price, reason, &self.paper, self.balances.wallet_address(), tracked_wallet_id);
+            self.ledger.record_paper(&fill).await;
+            info!(amount = fill.amount, price_sol = fill.price_sol, reason = reason.as_str(), "Paper exit recorded");
+            return Ok(true);
+        }
+        let Some(executor) = &self.executor else {
+            return Ok(false);
+        };
+
+        // The wallet can hold less than the recorded position, e.g. after a transfer out.
+        let balances = self.balances.get().await?;
+        let held = balances.tokens.iter()
+            .find(|token| token.mint == position.token_address)
+            .ok_or_else(|| AppError::ExecutionError(format!("Wallet holds no {}", position.token_address)))?;
+        let sell = CopyDecision::Sell {
+            token_address: position.token_address.clone(),
+            token_amount: position.amount.min(held.amount),
+        };
+        let Some(order) = SwapOrder::from_decision(&sell, held.decimals) else {
+            return Ok(false);
+        };
+
+        executor.execute(&SwapOrder { exit: Some(reason), ..order }, settings, lock).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Transaction;
+    use crate::risk::RiskLimits;
+    use crate::rpc::SolanaRpc;
+    use crate::testing;
+    use axum::{routing::get, Json, Router};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    struct FixedPrice(f64);
+
+    impl PriceSource for FixedPrice {
+        async fn prices_sol(&self, mints: &[String]) -> Result<HashMap<String, f64>, AppError> {
+            Ok(mints.iter().map(|mint| (mint.clone(), self.0)).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn exits_stop_while_halted_or_disabled() {
+        let tracked_wallet_id = Uuid::new_v4();
+        let enabled = Arc::new(Mutex::new(false));
+        let exits = Arc::new(Mutex::new(Vec::<Value>::new()));
+        let buy = Transaction {
+            id: Uuid::new_v4(),
+            user_id: "me".to_string(),
+            tracked_wallet_id: Some(tracked_wallet_id),
+            signature: "paper:buy".to_string(),
+            transaction_type: "buy".to_string(),
+            token_address: "mint".to_string(),
+            amount: 1000.0,
+            price_sol: 0.0001,
+            timestamp: Utc::now(),
+        };
+        let (is_enabled, recorded) = (enabled.clone(), exits.clone());
+        let supabase = testing::serve(Router::new()
+            .route("/copy_trade_settings", get(move || {
+                let is_enabled = *is_enabled.lock().unwrap();
+                async move {
+                    Json(json!([{
+                        "tracked_wallet_id": tracked_wallet_id,
+                        "is_enabled": is_enabled,
+                        "trade_amount_sol": 0.1,
+                        "max_slippage": 1.0,
+                        "max_open_positions": 3,
+                        "use_allowed_tokens_list": false,
+                        "allow_additional_buys": false,
+                        "match_sell_percentage": false,
+                        "min_sol_balance": 0.01,
+                        "paper_trading": true,
+                        "stop_loss_pct": 20.0,
+                    }]))
+                }
+            }))
+            .route("/paper_transactions", get(move || async move { Json(json!([buy])) })
+                .post(move |row: String| {
+                    let row: Value = serde_json::from_str(&row).unwrap();
+                    recorded.lock().unwrap().push(row.clone());
+                    async move { Json(json!([row])) }
+                }))
+            .route("/risk_halts", get(|| async {
+                Json(json!([{ "reason": "manual", "halted_at": Utc::now(), "resumed_at": null }]))
+            }).patch(|| async { Json(json!([])) })),
+        ).await;
+        let db = SupabaseClient::new(&supabase, "", "", "me");
+        let risk = RiskGuard::load(db.clone(), RiskLimits::default()).await.unwrap();
+        let watcher = PositionWatcher {
+            db: db.clone(),
+            ledger: Ledger::new(db, &BackgroundTasks::new()),
+            // Half the entry price, past the stop loss.
+            prices: FixedPrice(0.00005),
+            balances: BalanceService::new(SolanaRpc::new("http://127.0.0.1:1", "confirmed"), "me".to_string()),
+            executor: None,
+            paper: PaperSettings { slippage_pct: 0.0, fee_sol: 0.0, starting_balance_sol: 1.0 },
+            risk: risk.clone(),
+            poll_interval: Duration::from_secs(1),
+        };
+        let mut peaks = Peaks::new();
+
+        // Halted, then resumed with the wallet disabled.
+        watcher.check(&mut peaks).await.unwrap();
+        risk.resume().await.unwrap();
+        watcher.check(&mut peaks).await.unwrap();
+        assert!(exits.lock().unwrap().is_empty());
+
+        *enabled.lock().unwrap() = true;
+        watcher.check(&mut peaks).await.unwrap();
+        assert_eq!(exits.lock().unwrap().len(), 1);
+        assert_eq!(exits.lock().unwrap()[0]["transaction_type"], "stop_loss");
+
+        // An exit decided on a stale snapshot finds the position closed once it holds the lock.
+        let settings = &watcher.db.get_copy_trade_settings().await.unwrap()[0];
+        assert!(!watcher.exit(settings, "mint", 0.00005, 0.0001).await.unwrap());
+        assert_eq!(exits.lock().unwrap().len(), 1);
+    }
+}